@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS audit_event(
+    id bigserial PRIMARY KEY,
+    created_at timestamptz not null default now(),
+    actor varchar(255),
+    action varchar(64) not null,
+    target varchar(255),
+    ip varchar(45),
+    details jsonb
+);
+
+CREATE INDEX IF NOT EXISTS audit_event_actor_idx ON audit_event(actor, created_at);
+CREATE INDEX IF NOT EXISTS audit_event_target_idx ON audit_event(target, created_at);
+CREATE INDEX IF NOT EXISTS audit_event_created_at_idx ON audit_event(created_at);
+
+CREATE OR REPLACE FUNCTION audit_event_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_event is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_event_append_only
+    BEFORE UPDATE OR DELETE ON audit_event
+    FOR EACH ROW EXECUTE FUNCTION audit_event_append_only();
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS api_key(
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    name varchar(255) unique not null,
+    key_hash varchar(64) unique not null,
+    created_at timestamptz not null default now(),
+    revoked boolean not null default false
+);
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS lvl_world_iid_idx;
+
+ALTER TABLE lvl
+    DROP COLUMN IF EXISTS identifier,
+    DROP COLUMN IF EXISTS iid;
@@ -0,0 +1,5 @@
+ALTER TABLE lvl
+    ADD COLUMN IF NOT EXISTS iid varchar(64),
+    ADD COLUMN IF NOT EXISTS identifier varchar(255);
+
+CREATE INDEX IF NOT EXISTS lvl_world_iid_idx ON lvl(world, iid);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS map_file;
+DROP TABLE IF EXISTS map_blob;
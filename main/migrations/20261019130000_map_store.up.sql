@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS map_blob(
+    hash varchar(64) PRIMARY KEY,
+    data bytea not null,
+    size bigint not null
+);
+
+CREATE TABLE IF NOT EXISTS map_file(
+    project varchar(255) not null,
+    path varchar(1024) not null,
+    version bigint not null,
+    hash varchar(64) not null references map_blob(hash),
+    created_at timestamptz not null default now(),
+    PRIMARY KEY (project, path, version)
+);
@@ -0,0 +1,12 @@
+ALTER TABLE lvl
+    DROP COLUMN IF EXISTS px_hei,
+    DROP COLUMN IF EXISTS px_wid,
+    DROP COLUMN IF EXISTS world_depth,
+    DROP COLUMN IF EXISTS world_y,
+    DROP COLUMN IF EXISTS world_x;
+
+DROP INDEX IF EXISTS world_iid_idx;
+
+ALTER TABLE world
+    DROP COLUMN IF EXISTS project,
+    DROP COLUMN IF EXISTS iid;
@@ -0,0 +1,12 @@
+ALTER TABLE world
+    ADD COLUMN IF NOT EXISTS iid varchar(64),
+    ADD COLUMN IF NOT EXISTS project varchar(1024);
+
+CREATE UNIQUE INDEX IF NOT EXISTS world_iid_idx ON world(iid);
+
+ALTER TABLE lvl
+    ADD COLUMN IF NOT EXISTS world_x integer,
+    ADD COLUMN IF NOT EXISTS world_y integer,
+    ADD COLUMN IF NOT EXISTS world_depth integer,
+    ADD COLUMN IF NOT EXISTS px_wid integer,
+    ADD COLUMN IF NOT EXISTS px_hei integer;
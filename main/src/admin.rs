@@ -0,0 +1,87 @@
+use axum::{
+    extract::{ConnectInfo, Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use std::{net::SocketAddr, path::{Component, Path as FsPath}};
+
+use crate::{
+    app_state::AppState,
+    audit::{self, AuditAction, AuditEvent, AuditQuery, AuditRecord},
+    auth::AdminClaims,
+    world::import::{self, WorldImport},
+};
+
+pub async fn audit_log(
+    AdminClaims(_): AdminClaims,
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditRecord>>, (StatusCode, String)> {
+    audit::find(state.db_pool(), &query)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            tracing::error!("Cannot query audit log: {}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Cannot query audit log".to_owned())
+        })
+}
+
+pub async fn kick_client(
+    AdminClaims(claims): AdminClaims,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> StatusCode {
+    let Some(client) = state.clients().get_client(&id).await else {
+        return StatusCode::NOT_FOUND;
+    };
+    client.lock().await.kick();
+
+    let event = AuditEvent::new(AuditAction::Kick)
+        .actor(claims.id)
+        .target(id)
+        .ip(Some(addr.ip()));
+    audit::record(state.db_pool(), event).await;
+    StatusCode::NO_CONTENT
+}
+
+#[derive(Deserialize)]
+pub struct ImportRequest {
+    /// Project file relative to `game.maps_dir`, like `map1/map1.ldtk`.
+    pub project: String,
+}
+
+pub async fn import_world(
+    AdminClaims(claims): AdminClaims,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<AppState>,
+    Json(request): Json<ImportRequest>,
+) -> Result<Json<Vec<WorldImport>>, (StatusCode, String)> {
+    let relative = FsPath::new(&request.project);
+    if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err((StatusCode::BAD_REQUEST, "project must be a path inside the maps directory".to_owned()));
+    }
+    let path = state.config().game.maps_dir.join(relative);
+    if !path.is_file() {
+        return Err((StatusCode::NOT_FOUND, format!("Project {} not found", request.project)));
+    }
+
+    let imports = import::import_project(state.db_pool(), &path, &request.project)
+        .await
+        .map_err(|err| match err {
+            import::ImportError::World(err) => (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()),
+            import::ImportError::Db(err) => {
+                tracing::error!("Cannot import {}: {}", request.project, err);
+                (StatusCode::INTERNAL_SERVER_ERROR, "Cannot import world".to_owned())
+            }
+        })?;
+
+    let event = AuditEvent::new(AuditAction::ImportWorld)
+        .actor(claims.id)
+        .target(request.project)
+        .ip(Some(addr.ip()))
+        .details(serde_json::json!(imports));
+    audit::record(state.db_pool(), event).await;
+    Ok(Json(imports))
+}
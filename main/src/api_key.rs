@@ -0,0 +1,32 @@
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Generates a new key, stores its hash and returns the plain key. The key
+/// itself is never stored, so it can only be shown once.
+pub async fn create(pool: &Pool<Postgres>, name: &str) -> Result<String, sqlx::Error> {
+    let key = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    sqlx::query("INSERT INTO api_key(name, key_hash) VALUES ($1, $2)")
+        .bind(name)
+        .bind(hash(&key))
+        .execute(pool)
+        .await?;
+    Ok(key)
+}
+
+/// Returns the name of the key if it exists and is not revoked.
+pub async fn verify(pool: &Pool<Postgres>, key: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT name FROM api_key WHERE key_hash = $1 AND NOT revoked")
+        .bind(hash(key))
+        .fetch_optional(pool)
+        .await
+}
+
+fn hash(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
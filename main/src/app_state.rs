@@ -1,35 +1,90 @@
-use casdoor_rust_sdk::AuthService;
+use casdoor_rust_sdk::{AuthService, CasdoorConfig};
+use jsonwebtoken::DecodingKey;
 use sqlx::{Pool, Postgres};
 use tokio::sync::{Mutex, MutexGuard, RwLock};
-use crate::{client::WsClient, config};
-use std::{collections::HashMap, sync::Arc};
+use crate::{
+    client::WsClient, config::Config, game::GameSession, maps::MapCache, metrics::Metrics,
+    world::{preview::PreviewCache, store::MapStore},
+};
+use std::{collections::HashMap, sync::{atomic::{AtomicBool, Ordering}, Arc}};
 
 pub struct AppState {
+    config: Arc<Config>,
+    casdoor_conf: &'static CasdoorConfig,
     auth_service: AuthService<'static>,
+    jwt_decoding_key: Arc<DecodingKey>,
     db_pool: Pool<Postgres>,
     clients: Arc<ClientsState>,
+    draining: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+    game: Arc<Mutex<GameSession>>,
+    maps: Arc<MapCache>,
+    previews: Arc<PreviewCache>,
+    store: Arc<dyn MapStore>,
 }
 
 impl Clone for AppState {
     fn clone(&self) -> Self {
         Self { 
-            auth_service: AuthService::new(&config::CASDOOR_CONF),
+            config: self.config.clone(),
+            casdoor_conf: self.casdoor_conf,
+            auth_service: AuthService::new(self.casdoor_conf),
+            jwt_decoding_key: self.jwt_decoding_key.clone(),
             db_pool: self.db_pool.clone(),
             clients: self.clients.clone(),
+            draining: self.draining.clone(),
+            metrics: self.metrics.clone(),
+            game: self.game.clone(),
+            maps: self.maps.clone(),
+            previews: self.previews.clone(),
+            store: self.store.clone(),
         }
     }
 }
 
 impl AppState {
-    pub fn new(db_pool: Pool<Postgres>) -> Self {
+    pub fn new(config: Config, db_pool: Pool<Postgres>, store: Arc<dyn MapStore>) -> Self {
+        let iam = &config.iam;
+        let jwt_decoding_key = iam.decoding_key()
+            .unwrap_or_else(|err| panic!("Invalid IAM public certificate: {}", err));
+        // AuthService borrows its config for 'static; the state lives for the whole process anyway.
+        let casdoor_conf: &'static CasdoorConfig = Box::leak(Box::new(CasdoorConfig::new(
+            iam.url.clone(),
+            iam.client_id.clone(),
+            iam.client_secret.clone(),
+            iam.pub_cert.clone(),
+            iam.org_name.clone(),
+            Some(iam.app_name.clone()))));
+        let game = GameSession::load(&config.game)
+            .unwrap_or_else(|err| panic!("Cannot load game world: {}", err));
+
+        let previews = PreviewCache::new(config.game.preview_dir.clone(), config.game.thumbnail_size);
+
         Self {
-            auth_service: AuthService::new(&config::CASDOOR_CONF),
+            config: Arc::new(config),
+            casdoor_conf,
+            auth_service: AuthService::new(casdoor_conf),
+            jwt_decoding_key: Arc::new(jwt_decoding_key),
             db_pool,
             clients: Arc::new(ClientsState::new()),
+            draining: Arc::new(AtomicBool::new(false)),
+            metrics: Arc::new(Metrics::new()),
+            game: Arc::new(Mutex::new(game)),
+            maps: Arc::new(MapCache::new()),
+            previews: Arc::new(previews),
+            store,
         }
     }
 
-    pub fn auth_service(&self) -> &AuthService {
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn jwt_decoding_key(&self) -> &DecodingKey {
+        &self.jwt_decoding_key
+    }
+
+    pub fn auth_service(&self) -> &AuthService<'_> {
         &self.auth_service
     }
 
@@ -40,6 +95,34 @@ impl AppState {
     pub fn clients(&self) -> Arc<ClientsState> {
         self.clients.clone()
     }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    pub fn game(&self) -> Arc<Mutex<GameSession>> {
+        self.game.clone()
+    }
+
+    pub fn maps(&self) -> &MapCache {
+        &self.maps
+    }
+
+    pub fn previews(&self) -> Arc<PreviewCache> {
+        self.previews.clone()
+    }
+
+    pub fn store(&self) -> Arc<dyn MapStore> {
+        self.store.clone()
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::Relaxed)
+    }
     
 }
 
@@ -56,6 +139,12 @@ pub struct ClientsState {
     clients: RwLock<HashMap<String, ClientHandle>>,
 }
 
+impl Default for ClientsState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ClientsState {
 
     pub fn new() -> Self {
@@ -74,7 +163,11 @@ impl ClientsState {
         let id = client.id().to_string();
         let cl = ClientHandle(Arc::new(Mutex::new(client)));
         guard.insert(id, cl.clone());
-        return cl;
+        cl
+    }
+
+    pub async fn count(&self) -> usize {
+        self.clients.read().await.len()
     }
 
     pub async fn del_client(&self, id: &str) -> Option<ClientHandle> {
@@ -0,0 +1,127 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Postgres};
+use std::net::IpAddr;
+
+const DEFAULT_QUERY_LIMIT: i64 = 100;
+const MAX_QUERY_LIMIT: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    TokenRejected,
+    AccessDenied,
+    Kick,
+    ImportWorld,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::TokenRejected => "token_rejected",
+            AuditAction::AccessDenied => "access_denied",
+            AuditAction::Kick => "kick",
+            AuditAction::ImportWorld => "import_world",
+        }
+    }
+}
+
+/// A single entry of the audit log. Built with [`AuditEvent::new`] and the
+/// chained setters, then written with [`record`].
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    action: AuditAction,
+    actor: Option<String>,
+    target: Option<String>,
+    ip: Option<IpAddr>,
+    details: Option<serde_json::Value>,
+}
+
+impl AuditEvent {
+
+    pub fn new(action: AuditAction) -> Self {
+        Self {
+            action,
+            actor: None,
+            target: None,
+            ip: None,
+            details: None,
+        }
+    }
+
+    pub fn actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn ip(mut self, ip: Option<IpAddr>) -> Self {
+        self.ip = ip;
+        self
+    }
+
+    pub fn details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct AuditRecord {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub actor: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub details: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub user: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+/// Appends an event to the audit log. Failures are logged and never
+/// propagated: losing an audit entry must not break the request being audited.
+pub async fn record(pool: &Pool<Postgres>, event: AuditEvent) {
+    let result = sqlx::query(
+        "INSERT INTO audit_event(actor, action, target, ip, details) VALUES ($1, $2, $3, $4, $5)")
+        .bind(&event.actor)
+        .bind(event.action.as_str())
+        .bind(&event.target)
+        .bind(event.ip.map(|ip| ip.to_string()))
+        .bind(&event.details)
+        .execute(pool)
+        .await;
+    if let Err(err) = result {
+        tracing::error!("Cannot record audit event {}: {}", event.action.as_str(), err);
+    }
+}
+
+/// Returns events where `user` is either the actor or the target, newest first.
+pub async fn find(pool: &Pool<Postgres>, query: &AuditQuery) -> Result<Vec<AuditRecord>, sqlx::Error> {
+    let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT).clamp(1, MAX_QUERY_LIMIT);
+    sqlx::query_as::<_, AuditRecord>(
+        "SELECT id, created_at, actor, action, target, ip, details FROM audit_event \
+         WHERE ($1::varchar IS NULL OR actor = $1 OR target = $1) \
+         AND ($2::timestamptz IS NULL OR created_at >= $2) \
+         AND ($3::timestamptz IS NULL OR created_at < $3) \
+         ORDER BY created_at DESC, id DESC LIMIT $4")
+        .bind(&query.user)
+        .bind(query.from)
+        .bind(query.to)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
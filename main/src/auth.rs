@@ -3,7 +3,7 @@ use jsonwebtoken::{decode, Algorithm, Validation};
 use serde::{Deserialize, Serialize};
 use axum::{
     extract::{
-        FromRef, FromRequestParts, Query, Request, State
+        ConnectInfo, FromRef, FromRequestParts, Query, Request, State
     }, http::{request::Parts, StatusCode},
     middleware::Next, response::{IntoResponse, Redirect, Response},
 };
@@ -11,9 +11,9 @@ use axum_extra::{
     headers::{authorization::Bearer, Authorization},
     TypedHeader,
 };
-use std::{error::Error, fmt::Display};
+use std::{error::Error, fmt::Display, net::SocketAddr};
 
-use crate::{app_state::AppState, config};
+use crate::{api_key, app_state::AppState, audit::{self, AuditAction, AuditEvent}, metrics};
 use crate::route;
 
 #[derive(Deserialize)]
@@ -23,8 +23,8 @@ struct WsAuthQuery {
 
 #[derive(Deserialize)]
 pub struct AuthQuery {
-    code: String,
-    state: String
+    pub code: String,
+    pub state: String
 }
 
 pub async fn ws_auth(_state: State<AppState>, request: Request<axum::body::Body>, next: Next) 
@@ -43,17 +43,32 @@ pub async fn ws_auth(_state: State<AppState>, request: Request<axum::body::Body>
     Ok(next.run(Request::from_parts(parts, body)).await)
 }
 
-pub async fn auth_by_code(state: State<AppState>, query: Query<AuthQuery>) 
+pub async fn auth_by_code(state: State<AppState>, ConnectInfo(addr): ConnectInfo<SocketAddr>, query: Query<AuthQuery>) 
     -> Result<impl IntoResponse, AuthError> {
         
     let code = query.code.clone();
+    let auth_state = state.clone();
     let token = tokio::task::block_in_place(move || {
-        state.auth_service()
+        auth_state.auth_service()
             .get_auth_token(code)
-            .map_err(|err| AuthError::from_err("Cannot get token by code", err, StatusCode::UNAUTHORIZED))
-    })?;
-    
-    Ok(token)
+            .map_err(|err| err.to_string())
+    });
+
+    let event = match &token {
+        Ok(token) => match decode_claims(&state, token) {
+            Ok(claims) => AuditEvent::new(AuditAction::Login).actor(claims.id),
+            Err(err) => AuditEvent::new(AuditAction::LoginFailed)
+                .details(serde_json::json!({ "reason": err.to_string() })),
+        },
+        Err(err) => AuditEvent::new(AuditAction::LoginFailed)
+            .details(serde_json::json!({ "reason": err })),
+    };
+    if token.is_err() {
+        state.metrics().auth_failure(metrics::AUTH_LOGIN_FAILED);
+    }
+    audit::record(state.db_pool(), event.ip(Some(addr.ip()))).await;
+
+    token.map_err(|err| AuthError::from_err("Cannot get token by code", err.into(), StatusCode::UNAUTHORIZED))
 }
 
 //#[async_trait::async_trait]
@@ -84,6 +99,7 @@ where
             TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
                 .await
                 .map_err(|err| {
+                    st.metrics().auth_failure(metrics::AUTH_MISSING_TOKEN);
                     AuthError::from_err_redirect(
                         "Cannot extract token",
                         Box::new(err),
@@ -91,20 +107,83 @@ where
                     )
                 })?;
 
-        parse_token(bearer.token(), redirect_uri)
+        match decode_claims(&st, bearer.token()) {
+            Ok(claims) => Ok(claims),
+            Err(err) => {
+                st.metrics().auth_failure(metrics::AUTH_INVALID_TOKEN);
+                let event = AuditEvent::new(AuditAction::TokenRejected)
+                    .ip(peer_ip(parts))
+                    .details(serde_json::json!({ "reason": err.to_string(), "path": parts.uri.path() }));
+                audit::record(st.db_pool(), event).await;
+                Err(AuthError::from_err_redirect("Cannot decode token", Box::new(err), redirect_uri))
+            }
+        }
+    }
+}
+
+/// Claims of a user that belongs to the configured admin group.
+pub struct AdminClaims(pub Claims);
+
+impl<S> FromRequestParts<S> for AdminClaims
+where
+    S: Send + Sync,
+    AppState: FromRef<S>
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let st = AppState::from_ref(state);
+        if let Some(key) = parts.headers.get(api_key::API_KEY_HEADER).and_then(|v| v.to_str().ok()) {
+            return match api_key::verify(st.db_pool(), key).await {
+                Ok(Some(name)) => Ok(AdminClaims(Claims {
+                    id: format!("api-key:{}", name),
+                    name,
+                    groups: vec![st.config().iam.admin_group.clone()],
+                })),
+                Ok(None) => {
+                    st.metrics().auth_failure(metrics::AUTH_INVALID_API_KEY);
+                    let event = AuditEvent::new(AuditAction::TokenRejected)
+                        .ip(peer_ip(parts))
+                        .details(serde_json::json!({ "reason": "unknown or revoked api key", "path": parts.uri.path() }));
+                    audit::record(st.db_pool(), event).await;
+                    Err(AuthError::from_err("Invalid API key", "unknown or revoked key".into(), StatusCode::UNAUTHORIZED))
+                }
+                Err(err) => Err(AuthError::from_err("Cannot verify API key", Box::new(err), StatusCode::INTERNAL_SERVER_ERROR)),
+            };
+        }
+
+        let claims = Claims::from_request_parts(parts, state).await?;
+        if claims.is_admin(&st.config().iam.admin_group) {
+            return Ok(AdminClaims(claims));
+        }
+
+        st.metrics().auth_failure(metrics::AUTH_FORBIDDEN);
+        let event = AuditEvent::new(AuditAction::AccessDenied)
+            .actor(claims.id.clone())
+            .target(parts.uri.path())
+            .ip(peer_ip(parts));
+        audit::record(st.db_pool(), event).await;
+        Err(AuthError::from_err("Admin access required", "user is not an admin".into(), StatusCode::FORBIDDEN))
     }
 }
 
-fn parse_token(token: &str, redirect_uri: String) -> Result<Claims, AuthError> {
+fn decode_claims(state: &AppState, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
     let mut validation = Validation::new(Algorithm::RS256);
-    validation.set_audience(&[config::load_env_var_or_fail(config::IAM_CLIENT_ID)]);
-    let token_data = 
-        decode::<Claims>(token, &config::JWT_DECODING_KEY, &validation)
-            .map_err(|err| AuthError::from_err_redirect("Cannot decode token", Box::new(err), redirect_uri))?;
+    validation.set_audience(&[&state.config().iam.client_id]);
+    let token_data = decode::<Claims>(token, state.jwt_decoding_key(), &validation)?;
 
     Ok(token_data.claims)
 }
 
+fn peer_ip(parts: &Parts) -> Option<std::net::IpAddr> {
+    parts.extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
 #[derive(Debug)]
 pub struct  AuthError {
     err: Box<dyn Error>,
@@ -152,6 +231,12 @@ pub struct Claims {
     pub groups: Vec<String>
 }
 
+impl Claims {
+    pub fn is_admin(&self, admin_group: &str) -> bool {
+        self.groups.iter().any(|group| group == admin_group)
+    }
+}
+
 impl Display for Claims {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.id)
@@ -0,0 +1,349 @@
+use clap::{Args, Parser, Subcommand};
+use std::{error::Error, fs, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::{
+    api_key,
+    config::{Config, GameConfig},
+    db,
+    world::{self, procgen::{self, Algorithm, GenParams}, Issue, Severity, WorldError},
+};
+
+#[derive(Parser)]
+#[command(version, about = "Game server and content tools")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Start the HTTP and WebSocket server (default)
+    Serve(ServeArgs),
+    /// Manage database migrations
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Import an LDtk project or Tiled map into the world and lvl tables, updating worlds and levels imported before
+    ImportWorld {
+        file: PathBuf,
+    },
+    /// Check LDtk projects for content problems; takes a project file or a maps directory
+    ValidateMap {
+        path: PathBuf,
+        /// Layer every level must have, defaults to game.required_layers of the config
+        #[arg(long = "require-layer")]
+        required_layers: Vec<String>,
+    },
+    /// Compile LDtk projects into the world cache; takes a project file or a maps directory,
+    /// defaults to game.maps_dir
+    BuildCache {
+        path: Option<PathBuf>,
+        /// Rebuild caches that are already up to date
+        #[arg(long)]
+        force: bool,
+    },
+    /// Generate a level from a seed and write the project with it added, to play it or
+    /// open it in LDtk; keep the output next to the project so its relative paths resolve
+    GenerateLevel(GenerateLevelArgs),
+    /// Inspect and fill the map store selected by game.store.backend
+    #[command(subcommand)]
+    Store(StoreCommand),
+    /// Create an API key for admin endpoints and print it
+    CreateApiKey {
+        name: String,
+    },
+    /// Import every map from the maps directory and create base item types
+    SeedDevData,
+}
+
+#[derive(Args, Default)]
+pub struct ServeArgs {
+    /// Do not apply pending migrations on startup
+    #[arg(long)]
+    pub skip_migrations: bool,
+}
+
+#[derive(Args)]
+pub struct GenerateLevelArgs {
+    project: PathBuf,
+    output: PathBuf,
+    /// rooms or caves
+    #[arg(long, default_value = "rooms")]
+    algorithm: Algorithm,
+    /// Defaults to a random seed, which is printed
+    #[arg(long)]
+    seed: Option<u64>,
+    /// In cells of the collision layer
+    #[arg(long, default_value_t = GenParams::default().width)]
+    width: i64,
+    #[arg(long, default_value_t = GenParams::default().height)]
+    height: i64,
+    #[arg(long, default_value_t = GenParams::default().identifier)]
+    identifier: String,
+    /// Entity to place as IDENTIFIER=COUNT, repeatable; defaults to Key=1 Heal=2
+    #[arg(long = "place", value_parser = parse_placement)]
+    placements: Vec<(String, u32)>,
+    /// Number of entities drawn from the definitions tagged `encounter`
+    #[arg(long, default_value_t = GenParams::default().encounters)]
+    encounters: u32,
+}
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    /// Apply all pending migrations
+    Up,
+    /// Revert applied migrations
+    Down {
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+    },
+    /// List migrations and whether they are applied
+    Status,
+}
+
+#[derive(Subcommand)]
+pub enum StoreCommand {
+    /// List stored projects, or the files of one project
+    List {
+        project: Option<String>,
+    },
+    /// Store every file of a local project directory as a new version
+    Push {
+        dir: PathBuf,
+        /// Project name, defaults to the directory name
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// List the versions of a stored file
+    History {
+        project: String,
+        path: String,
+    },
+}
+
+const DEV_ITEM_TYPES: [&str; 2] = ["Key", "Heal"];
+
+pub async fn run(command: Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Serve(_) => unreachable!("serve is handled by main"),
+        Command::Migrate(command) => migrate(command).await,
+        Command::ImportWorld { file } => import_world(file).await,
+        Command::ValidateMap { path, required_layers } => validate_map(path, required_layers),
+        Command::BuildCache { path, force } => build_cache(path, force),
+        Command::GenerateLevel(args) => generate_level(args),
+        Command::Store(command) => store(command).await,
+        Command::CreateApiKey { name } => create_api_key(name).await,
+        Command::SeedDevData => seed_dev_data().await,
+    }
+}
+
+async fn migrate(command: MigrateCommand) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let pool = db::connect(&config.database).await;
+    match command {
+        MigrateCommand::Up => {
+            db::migrate_up(&pool).await?;
+            println!("Migrations applied");
+        }
+        MigrateCommand::Down { steps } => {
+            db::migrate_down(&pool, steps).await?;
+            println!("Reverted {} migration(s)", steps);
+        }
+        MigrateCommand::Status => {
+            for status in db::migration_status(&pool).await? {
+                let mark = if status.applied { "applied" } else { "pending" };
+                println!("{:>14}  {:<8} {}", status.version, mark, status.description);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn import_world(file: PathBuf) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let pool = db::connect(&config.database).await;
+    let project = world::import::project_path(&config.game.maps_dir, &file);
+    for import in world::import::import_project(&pool, &file, &project).await? {
+        print_import(&import);
+    }
+    Ok(())
+}
+
+fn print_import(import: &world::import::WorldImport) {
+    let action = if import.created { "Imported" } else { "Updated" };
+    println!("{} world {} ({}): {} level(s) added, {} updated, {} removed",
+        action, import.name, import.world_id, import.added.len(), import.updated, import.removed.len());
+    for removed in &import.removed {
+        println!("  removed level {} ({})",
+            removed.identifier.as_deref().unwrap_or("?"), removed.iid.as_deref().unwrap_or("no iid"));
+    }
+}
+
+fn validate_map(path: PathBuf, required_layers: Vec<String>) -> Result<(), Box<dyn Error>> {
+    let required_layers = if required_layers.is_empty() {
+        GameConfig::load()?.required_layers
+    } else {
+        required_layers
+    };
+    let projects = if path.is_dir() { world::find_projects(&path)? } else { vec![path] };
+
+    let mut failed = 0;
+    for project in &projects {
+        let load_error = |err: WorldError| Issue {
+            severity: Severity::Error,
+            file: project.clone(),
+            iid: None,
+            message: err.to_string(),
+        };
+        let issues = world::validate_project(project, &required_layers).unwrap_or_else(|err| vec![load_error(err)]);
+        for issue in &issues {
+            println!("{}", issue);
+        }
+        if world::validate::has_errors(&issues) {
+            failed += 1;
+            continue;
+        }
+        match world::load_worlds(project) {
+            Ok(worlds) => for world in worlds {
+                println!("{}: world {} OK, {} level(s)", project.display(), world.identifier, world.levels.len());
+            }
+            Err(err) => {
+                println!("{}", load_error(err));
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} project(s) failed validation", failed, projects.len()).into());
+    }
+    Ok(())
+}
+
+fn build_cache(path: Option<PathBuf>, force: bool) -> Result<(), Box<dyn Error>> {
+    let config = GameConfig::load()?;
+    let path = path.unwrap_or_else(|| config.maps_dir.clone());
+    let projects = if path.is_dir() { world::find_projects(&path)? } else { vec![path] };
+
+    let mut failed = 0;
+    for project in &projects {
+        match world::cache::prebuild(project, &config, force) {
+            Ok((cache, true)) => println!("{}: built {}", project.display(), cache.display()),
+            Ok((cache, false)) => println!("{}: up to date {}", project.display(), cache.display()),
+            Err(err) => {
+                println!("{}: error: {}", project.display(), err);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} project(s) failed to build", failed, projects.len()).into());
+    }
+    Ok(())
+}
+
+fn parse_placement(value: &str) -> Result<(String, u32), String> {
+    let (identifier, count) = value.split_once('=')
+        .ok_or_else(|| format!("expected IDENTIFIER=COUNT, got {}", value))?;
+    let count = count.parse().map_err(|err| format!("invalid count {}: {}", count, err))?;
+    Ok((identifier.to_owned(), count))
+}
+
+fn generate_level(args: GenerateLevelArgs) -> Result<(), Box<dyn Error>> {
+    let seed = args.seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos() as u64)
+    });
+    let defaults = GenParams::default();
+    let params = GenParams {
+        algorithm: args.algorithm,
+        seed,
+        width: args.width,
+        height: args.height,
+        identifier: args.identifier,
+        placements: if args.placements.is_empty() { defaults.placements.clone() } else { args.placements },
+        encounters: args.encounters,
+        ..defaults
+    };
+    let config = GameConfig::load()?;
+    let mut project = world::Project::load(&args.project)?.into_json();
+    let level = procgen::generate(&project, &params)?;
+    println!("Generated level {} ({}) with seed {}", level.identifier, level.iid, seed);
+    procgen::add_level(&mut project, level)?;
+    fs::write(&args.output, serde_json::to_string_pretty(&project)?)?;
+
+    let issues = world::validate_project(&args.output, &config.required_layers)?;
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    if world::validate::has_errors(&issues) {
+        return Err(format!("{} failed validation", args.output.display()).into());
+    }
+    world::load_worlds(&args.output)?;
+    println!("Wrote {}", args.output.display());
+    Ok(())
+}
+
+async fn store(command: StoreCommand) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let pool = db::connect(&config.database).await;
+    let store = world::store::open(&config.game, pool);
+    match command {
+        StoreCommand::List { project: None } => {
+            for project in store.projects().await? {
+                println!("{}", project);
+            }
+        }
+        StoreCommand::List { project: Some(project) } => {
+            for file in store.files(&project).await? {
+                println!("{:>4}  {}  {:>10}  {}", file.version.version, &file.version.hash[..12],
+                    file.version.size, file.path);
+            }
+        }
+        StoreCommand::Push { dir, name } => {
+            let project = match name {
+                Some(name) => name,
+                None => dir.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .ok_or_else(|| format!("cannot name project from {}", dir.display()))?,
+            };
+            for (path, version) in world::store::push_dir(store.as_ref(), &project, &dir).await? {
+                println!("{}/{} v{}", project, path, version.version);
+            }
+        }
+        StoreCommand::History { project, path } => {
+            for version in store.versions(&project, &path).await? {
+                println!("{:>4}  {}  {:>10}  {}", version.version, &version.hash[..12], version.size,
+                    version.created_at.format("%Y-%m-%d %H:%M:%S"));
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn create_api_key(name: String) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let pool = db::connect(&config.database).await;
+    let key = api_key::create(&pool, &name).await?;
+    println!("API key {} created. Store it now, it cannot be shown again:\n{}", name, key);
+    Ok(())
+}
+
+async fn seed_dev_data() -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let pool = db::connect(&config.database).await;
+    db::migrate_up(&pool).await?;
+
+    for path in world::find_projects(&config.game.maps_dir)? {
+        let project = world::import::project_path(&config.game.maps_dir, &path);
+        for import in world::import::import_project(&pool, &path, &project).await? {
+            print_import(&import);
+        }
+    }
+
+    for item_type in DEV_ITEM_TYPES {
+        sqlx::query("INSERT INTO item_type(name) VALUES ($1) ON CONFLICT (name) DO NOTHING")
+            .bind(item_type)
+            .execute(&pool)
+            .await?;
+    }
+    println!("Item types: {}", DEV_ITEM_TYPES.join(", "));
+    Ok(())
+}
@@ -1,8 +1,20 @@
+use tokio::sync::mpsc::UnboundedSender;
 use uuid::Uuid;
 
+use crate::game::protocol::ServerMessage;
+
 pub struct WsClient {
     id: Uuid,
-    x: i32
+    x: i32,
+    kicked: bool,
+    /// Messages the server pushes without a client request, like level reloads.
+    outbox: Option<UnboundedSender<ServerMessage>>,
+}
+
+impl Default for WsClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WsClient {
@@ -11,6 +23,15 @@ impl WsClient {
         Self {
             id: Uuid::new_v4(),
             x: 0,
+            kicked: false,
+            outbox: None,
+        }
+    }
+
+    pub fn with_outbox(outbox: UnboundedSender<ServerMessage>) -> Self {
+        Self {
+            outbox: Some(outbox),
+            ..Self::new()
         }
     }
 
@@ -18,6 +39,26 @@ impl WsClient {
         self.id
     }
 
+    /// Also wakes the socket task, which closes the connection without waiting for the
+    /// client's next message.
+    pub fn kick(&mut self) {
+        self.kicked = true;
+        self.push(ServerMessage::Kicked);
+    }
+
+    pub fn is_kicked(&self) -> bool {
+        self.kicked
+    }
+
+    /// Queues a message for the socket task; false when nothing reads the queue anymore.
+    pub fn push(&self, message: ServerMessage) -> bool {
+        self.outbox.as_ref().is_some_and(|outbox| outbox.send(message).is_ok())
+    }
+
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+
     pub fn set_x(&mut self, x: i32) {
         self.x = x
     }
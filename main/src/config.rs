@@ -1,51 +1,452 @@
-use std::env;
-use casdoor_rust_sdk::CasdoorConfig;
+use serde::Deserialize;
+use std::{collections::HashMap, env, fmt::Display, fs, path::PathBuf, str::FromStr, time::Duration};
 use jsonwebtoken::DecodingKey;
-use once_cell::sync::Lazy;
-use std::fs;
 
+use crate::world::{collision::Terrain, pathfinding::DiagonalPolicy};
+
+pub const CONFIG_FILE: &str = "CONFIG_FILE";
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+pub const HOST: &str = "HOST";
+pub const PORT: &str = "PORT";
+pub const DATABASE_URL: &str = "DATABASE_URL";
+pub const DB_MAX_CONNECTIONS: &str = "DB_MAX_CONNECTIONS";
+pub const DB_ACQUIRE_TIMEOUT_MS: &str = "DB_ACQUIRE_TIMEOUT_MS";
 pub const IAM_URL: &str = "IAM_URL";
 pub const IAM_CLIENT_ID: &str = "IAM_CLIENT_ID";
 pub const IAM_CLIENT_SECRET: &str = "IAM_CLIENT_SECRET";
 pub const IAM_PUB_CERT_FILE: &str = "IAM_PUB_CERT_FILE";
 pub const IAM_ORG_NAME: &str = "IAM_ORG_NAME";
 pub const IAM_APP_NAME: &str = "IAM_APP_NAME";
-pub const HOST: &str = "HOST";
-pub const PORT: &str = "PORT";
-pub const DATABASE_URL: &str = "DATABASE_URL";
+pub const IAM_ADMIN_GROUP: &str = "IAM_ADMIN_GROUP";
+pub const SERVER_SHUTDOWN_DRAIN_MS: &str = "SERVER_SHUTDOWN_DRAIN_MS";
+pub const GAME_MAPS_DIR: &str = "GAME_MAPS_DIR";
+pub const GAME_WS_IDLE_TIMEOUT_MS: &str = "GAME_WS_IDLE_TIMEOUT_MS";
+pub const GAME_HOT_RELOAD: &str = "GAME_HOT_RELOAD";
+
+/// Application settings. Loaded once at startup from an optional TOML file
+/// (`CONFIG_FILE`, or `config.toml` when present), then overridden by env vars.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub iam: IamConfig,
+    pub game: GameConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// How long `/readyz` reports not ready before the server stops accepting connections.
+    pub shutdown_drain_ms: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_owned(),
+            port: 3000,
+            shutdown_drain_ms: 5000,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn shutdown_drain(&self) -> Duration {
+        Duration::from_millis(self.shutdown_drain_ms)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    pub acquire_timeout_ms: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            max_connections: 5,
+            acquire_timeout_ms: 3000,
+        }
+    }
+}
+
+impl DatabaseConfig {
+    pub fn acquire_timeout(&self) -> Duration {
+        Duration::from_millis(self.acquire_timeout_ms)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IamConfig {
+    pub url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub pub_cert_file: PathBuf,
+    pub org_name: String,
+    pub app_name: String,
+    pub admin_group: String,
+    /// Contents of `pub_cert_file` in PEM public key form, filled by validation.
+    #[serde(skip)]
+    pub pub_cert: String,
+}
+
+impl Default for IamConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            client_id: String::new(),
+            client_secret: String::new(),
+            pub_cert_file: PathBuf::new(),
+            org_name: String::new(),
+            app_name: String::new(),
+            admin_group: "admin".to_owned(),
+            pub_cert: String::new(),
+        }
+    }
+}
+
+impl IamConfig {
+    pub fn decoding_key(&self) -> Result<DecodingKey, jsonwebtoken::errors::Error> {
+        DecodingKey::from_rsa_pem(self.pub_cert.as_bytes())
+    }
+
+    pub fn read_pub_cert(&self) -> std::io::Result<String> {
+        Ok(fs::read_to_string(&self.pub_cert_file)?.replace("CERTIFICATE", "PUBLIC KEY"))
+    }
+}
 
-static JWT_CERT: Lazy<String> = Lazy::new(||{
-    let cert_file_path = load_env_var_or_fail(IAM_PUB_CERT_FILE);
-    fs::read_to_string(&cert_file_path)
-        .unwrap_or_else(|err| panic!("cannot read file {}: {}", cert_file_path, err))
-        .replace("CERTIFICATE", "PUBLIC KEY")
-});
-
-pub static JWT_DECODING_KEY: Lazy<DecodingKey> = Lazy::new(||{
-    DecodingKey::from_rsa_pem(JWT_CERT.as_bytes()).unwrap()
-});
-
-pub static CASDOOR_CONF: Lazy<CasdoorConfig> = Lazy::new(|| {
-    let url = load_env_var_or_fail(IAM_URL);
-    let client_id = load_env_var_or_fail(IAM_CLIENT_ID);
-    let clietn_secret = load_env_var_or_fail(IAM_CLIENT_SECRET);
-    let org = load_env_var_or_fail(IAM_ORG_NAME);
-    let app_name = load_env_var_or_fail(IAM_APP_NAME);
-
-    CasdoorConfig::new(url, 
-        client_id, clietn_secret, 
-        JWT_CERT.clone(), org, Some(app_name))
-});
-
-pub fn load_env_var(var: &str, default: &str) -> String {
-    env::var(var).unwrap_or_else(|err| {
-        tracing::warn!("cannot load {} env var: {}", var, err);
-        default.to_owned()
-    })
-}
-
-pub fn load_env_var_or_fail(var: &str) -> String {
-    env::var(var).unwrap_or_else(|err| {
-        panic!("cannot load {} env var: {}", var, err);
-    })
-}
\ No newline at end of file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    pub maps_dir: PathBuf,
+    pub ws_idle_timeout_ms: u64,
+    /// Layers every level must have, checked when maps are validated.
+    pub required_layers: Vec<String>,
+    /// Entities players collect by walking into them.
+    pub pickups: Vec<String>,
+    /// Where rendered level previews are cached.
+    pub preview_dir: PathBuf,
+    /// Longest side of level preview thumbnails, in pixels.
+    pub thumbnail_size: u32,
+    /// Designer mode: watch `maps_dir` and reload the running world when its files change.
+    pub hot_reload: bool,
+    /// Load compiled worlds from `world_cache_dir` instead of parsing unchanged projects again.
+    pub world_cache: bool,
+    pub world_cache_dir: PathBuf,
+    pub store: MapStoreConfig,
+    pub collision: CollisionConfig,
+    pub pathfinding: PathfindingConfig,
+    pub visibility: VisibilityConfig,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            maps_dir: PathBuf::from("test_storange/maps"),
+            ws_idle_timeout_ms: 5000,
+            required_layers: vec!["Collisions".to_owned(), "Entities".to_owned()],
+            pickups: vec!["Key".to_owned(), "Heal".to_owned()],
+            preview_dir: PathBuf::from("cache/previews"),
+            thumbnail_size: 256,
+            hot_reload: false,
+            world_cache: true,
+            world_cache_dir: PathBuf::from("cache/worlds"),
+            store: MapStoreConfig::default(),
+            collision: CollisionConfig::default(),
+            pathfinding: PathfindingConfig::default(),
+            visibility: VisibilityConfig::default(),
+        }
+    }
+}
+
+impl GameConfig {
+
+    /// Loads only the `[game]` section, for content tools that run without the server
+    /// and so need no database or IAM settings.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut problems = Vec::new();
+        let mut game = Config::from_file(&mut problems).game;
+        game.apply_env(&mut problems);
+        game.validate(&mut problems);
+        if problems.is_empty() {
+            Ok(game)
+        } else {
+            Err(ConfigError { problems })
+        }
+    }
+
+    pub fn ws_idle_timeout(&self) -> Duration {
+        Duration::from_millis(self.ws_idle_timeout_ms)
+    }
+
+    fn apply_env(&mut self, problems: &mut Vec<String>) {
+        override_from_env(GAME_MAPS_DIR, &mut self.maps_dir, problems);
+        override_from_env(GAME_WS_IDLE_TIMEOUT_MS, &mut self.ws_idle_timeout_ms, problems);
+        override_from_env(GAME_HOT_RELOAD, &mut self.hot_reload, problems);
+    }
+
+    fn validate(&self, problems: &mut Vec<String>) {
+        if self.ws_idle_timeout_ms == 0 {
+            problems.push("game.ws_idle_timeout_ms must be greater than 0".to_owned());
+        }
+        if self.thumbnail_size == 0 {
+            problems.push("game.thumbnail_size must be greater than 0".to_owned());
+        }
+        if self.collision.layers.is_empty() {
+            problems.push("game.collision.layers must name at least one IntGrid layer".to_owned());
+        }
+        if !(0.0..=1.0).contains(&self.collision.slow_factor) {
+            problems.push("game.collision.slow_factor must be between 0 and 1".to_owned());
+        }
+        if self.collision.default_grid_size <= 0 {
+            problems.push("game.collision.default_grid_size must be greater than 0".to_owned());
+        }
+        if self.pathfinding.costs.values().any(|&cost| !(cost > 0.0 && cost.is_finite())) {
+            problems.push("game.pathfinding.costs must be finite and greater than 0".to_owned());
+        }
+        if self.pathfinding.max_expanded == 0 {
+            problems.push("game.pathfinding.max_expanded must be greater than 0".to_owned());
+        }
+        if self.store.backend == MapStoreBackend::Filesystem && !self.maps_dir.is_dir() {
+            problems.push(format!("game.maps_dir {} is not a directory", self.maps_dir.display()));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapStoreBackend {
+    /// Projects are the subdirectories of `game.maps_dir`.
+    #[default]
+    Filesystem,
+    /// Projects are stored in the database and checked out to `checkout_dir` on startup.
+    Postgres,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MapStoreConfig {
+    pub backend: MapStoreBackend,
+    /// Local copy the game loads maps from when they are not stored on the filesystem.
+    pub checkout_dir: PathBuf,
+}
+
+impl Default for MapStoreConfig {
+    fn default() -> Self {
+        Self {
+            backend: MapStoreBackend::Filesystem,
+            checkout_dir: PathBuf::from("cache/maps"),
+        }
+    }
+}
+
+/// How IntGrid layers translate into walkable terrain.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollisionConfig {
+    /// IntGrid layer identifiers merged into the collision map.
+    pub layers: Vec<String>,
+    /// Terrain by IntGrid value or value identifier, like `1 = "solid"` or `water = "water"`.
+    pub terrain: HashMap<String, Terrain>,
+    /// Terrain of non-empty values missing from `terrain`.
+    pub unmapped: Terrain,
+    /// Speed multiplier on `slow` terrain.
+    pub slow_factor: f32,
+    /// Grid size used for levels without any collision layer.
+    pub default_grid_size: i32,
+}
+
+impl Default for CollisionConfig {
+    fn default() -> Self {
+        Self {
+            layers: vec!["Collisions".to_owned()],
+            terrain: HashMap::new(),
+            unmapped: Terrain::Solid,
+            slow_factor: 0.5,
+            default_grid_size: 16,
+        }
+    }
+}
+
+/// What blocks line of sight. A cell is opaque when any of the rules matches.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VisibilityConfig {
+    /// Terrains of the collision map that block sight.
+    pub opaque_terrain: Vec<Terrain>,
+    /// IntGrid values or value identifiers of the collision layers that block sight.
+    pub opaque_values: Vec<String>,
+    /// Tileset enum tag values marking tiles that block sight, in any tile layer.
+    pub opaque_tags: Vec<String>,
+}
+
+impl Default for VisibilityConfig {
+    fn default() -> Self {
+        Self {
+            opaque_terrain: vec![Terrain::Solid],
+            opaque_values: Vec::new(),
+            opaque_tags: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathfindingConfig {
+    pub diagonal: DiagonalPolicy,
+    /// Cost of entering a cell by terrain. Terrains without a cost are impassable.
+    pub costs: HashMap<Terrain, f32>,
+    /// Number of recent paths kept in the cache, 0 disables it.
+    pub cache_size: usize,
+    /// Search gives up after expanding this many cells.
+    pub max_expanded: usize,
+}
+
+impl Default for PathfindingConfig {
+    fn default() -> Self {
+        Self {
+            diagonal: DiagonalPolicy::default(),
+            costs: HashMap::from([(Terrain::Floor, 1.0), (Terrain::Slow, 2.0)]),
+            cache_size: 256,
+            max_expanded: 20_000,
+        }
+    }
+}
+
+impl PathfindingConfig {
+    pub fn min_cost(&self) -> f32 {
+        self.costs.values().copied().fold(f32::INFINITY, f32::min)
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    problems: Vec<String>,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for problem in &self.problems {
+            writeln!(f, "  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut problems = Vec::new();
+        let mut config = Self::from_file(&mut problems);
+        config.apply_env(&mut problems);
+        config.validate(&mut problems);
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError { problems })
+        }
+    }
+
+    fn from_file(problems: &mut Vec<String>) -> Self {
+        let (path, required) = match env::var(CONFIG_FILE) {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_CONFIG_FILE), false),
+        };
+        if !required && !path.exists() {
+            return Self::default();
+        }
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) => {
+                problems.push(format!("cannot read config file {}: {}", path.display(), err));
+                return Self::default();
+            }
+        };
+        toml::from_str(&content).unwrap_or_else(|err| {
+            problems.push(format!("cannot parse config file {}: {}", path.display(), err));
+            Self::default()
+        })
+    }
+
+    fn apply_env(&mut self, problems: &mut Vec<String>) {
+        override_from_env(HOST, &mut self.server.host, problems);
+        override_from_env(PORT, &mut self.server.port, problems);
+        override_from_env(SERVER_SHUTDOWN_DRAIN_MS, &mut self.server.shutdown_drain_ms, problems);
+        override_from_env(DATABASE_URL, &mut self.database.url, problems);
+        override_from_env(DB_MAX_CONNECTIONS, &mut self.database.max_connections, problems);
+        override_from_env(DB_ACQUIRE_TIMEOUT_MS, &mut self.database.acquire_timeout_ms, problems);
+        override_from_env(IAM_URL, &mut self.iam.url, problems);
+        override_from_env(IAM_CLIENT_ID, &mut self.iam.client_id, problems);
+        override_from_env(IAM_CLIENT_SECRET, &mut self.iam.client_secret, problems);
+        override_from_env(IAM_PUB_CERT_FILE, &mut self.iam.pub_cert_file, problems);
+        override_from_env(IAM_ORG_NAME, &mut self.iam.org_name, problems);
+        override_from_env(IAM_APP_NAME, &mut self.iam.app_name, problems);
+        override_from_env(IAM_ADMIN_GROUP, &mut self.iam.admin_group, problems);
+        self.game.apply_env(problems);
+    }
+
+    fn validate(&mut self, problems: &mut Vec<String>) {
+        require(&self.database.url, "database.url", DATABASE_URL, problems);
+        require(&self.iam.url, "iam.url", IAM_URL, problems);
+        require(&self.iam.client_id, "iam.client_id", IAM_CLIENT_ID, problems);
+        require(&self.iam.client_secret, "iam.client_secret", IAM_CLIENT_SECRET, problems);
+        require(&self.iam.org_name, "iam.org_name", IAM_ORG_NAME, problems);
+        require(&self.iam.app_name, "iam.app_name", IAM_APP_NAME, problems);
+        require(&self.iam.admin_group, "iam.admin_group", IAM_ADMIN_GROUP, problems);
+
+        if self.database.max_connections == 0 {
+            problems.push("database.max_connections must be greater than 0".to_owned());
+        }
+        if self.database.acquire_timeout_ms == 0 {
+            problems.push("database.acquire_timeout_ms must be greater than 0".to_owned());
+        }
+        self.game.validate(problems);
+
+        if self.iam.pub_cert_file.as_os_str().is_empty() {
+            problems.push(format!("iam.pub_cert_file is not set (env {})", IAM_PUB_CERT_FILE));
+        } else {
+            match self.iam.read_pub_cert() {
+                Ok(cert) => {
+                    self.iam.pub_cert = cert;
+                    if let Err(err) = self.iam.decoding_key() {
+                        problems.push(format!("iam.pub_cert_file {} is not a valid RSA key: {}",
+                            self.iam.pub_cert_file.display(), err));
+                    }
+                }
+                Err(err) => problems.push(format!("cannot read iam.pub_cert_file {}: {}",
+                    self.iam.pub_cert_file.display(), err)),
+            }
+        }
+    }
+}
+
+fn override_from_env<T>(var: &str, target: &mut T, problems: &mut Vec<String>)
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let Ok(value) = env::var(var) else {
+        return;
+    };
+    match value.parse() {
+        Ok(parsed) => *target = parsed,
+        Err(err) => problems.push(format!("invalid value of {} env var: {}", var, err)),
+    }
+}
+
+fn require(value: &str, key: &str, var: &str, problems: &mut Vec<String>) {
+    if value.trim().is_empty() {
+        problems.push(format!("{} is not set (env {})", key, var));
+    }
+}
@@ -0,0 +1,60 @@
+use sqlx::{
+    migrate::{Migrate, MigrateError, Migrator},
+    postgres::PgPoolOptions,
+    Pool, Postgres,
+};
+use std::collections::HashMap;
+
+use crate::config::DatabaseConfig;
+
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+pub async fn connect(config: &DatabaseConfig) -> Pool<Postgres> {
+    PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .acquire_timeout(config.acquire_timeout())
+        .connect(&config.url)
+        .await
+        .unwrap_or_else(|err| panic!("Cannot connect to database: {}", err))
+}
+
+pub async fn migrate_up(pool: &Pool<Postgres>) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await
+}
+
+/// Reverts the last `steps` applied migrations.
+pub async fn migrate_down(pool: &Pool<Postgres>, steps: usize) -> Result<(), MigrateError> {
+    let mut applied: Vec<i64> = applied_migrations(pool).await?.into_keys().collect();
+    applied.sort_unstable();
+    let keep = applied.len().saturating_sub(steps);
+    let target = if keep == 0 { 0 } else { applied[keep - 1] };
+    MIGRATOR.undo(pool, target).await
+}
+
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+pub async fn migration_status(pool: &Pool<Postgres>) -> Result<Vec<MigrationStatus>, MigrateError> {
+    let applied = applied_migrations(pool).await?;
+    Ok(MIGRATOR.iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied: applied.contains_key(&migration.version),
+        })
+        .collect())
+}
+
+async fn applied_migrations(pool: &Pool<Postgres>) -> Result<HashMap<i64, Vec<u8>>, MigrateError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    Ok(conn.list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| (migration.version, migration.checksum.into_owned()))
+        .collect())
+}
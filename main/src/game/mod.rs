@@ -1,4 +1,598 @@
+use std::{collections::{HashMap, HashSet}, error::Error, path::PathBuf, sync::Arc};
+
+use uuid::Uuid;
+
+use crate::{
+    config::GameConfig,
+    world::{
+        cache::{self, CacheError},
+        collision::{CollisionMap, Terrain},
+        field::EntityRef,
+        find_projects,
+        geom::{Aabb, Cell, Point, Vec2},
+        model::Level,
+        pathfinding::{NavGraph, NavPoint, Path, Pathfinder},
+        refs::{EntityHandle, RefError},
+        spatial::SpatialIndex,
+        visibility::Visibility,
+        IidIndex, World,
+    },
+};
+
+pub mod player;
+pub mod protocol;
+pub mod reload;
+pub mod streaming;
+
+use player::{Player, PLAYER_SIZE};
+use protocol::ServerMessage;
+use streaming::{LevelSim, LevelStreamer};
+
+/// Entity marking where new players appear.
+pub const PLAYER_START: &str = "PlayerStart";
+/// Longest distance a single move message can cover, in pixels.
+const MAX_STEP: f32 = 8.0;
+/// Cell size of the per-level spatial indexes, in pixels.
+const SPATIAL_CELL_SIZE: f32 = 64.0;
+
+/// What the spatial index of a level holds.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Occupant {
+    Player(Uuid),
+    /// Entity instance, by iid.
+    Entity(String),
+}
 
 pub struct GameSession {
-    
-}
\ No newline at end of file
+    /// Project file the world was loaded from.
+    project: PathBuf,
+    world: Arc<World>,
+    /// Entities of the running world by iid, to resolve `EntityRef` fields.
+    iids: IidIndex,
+    collisions: HashMap<String, CollisionMap>,
+    visibility: HashMap<String, Visibility>,
+    pathfinder: Pathfinder,
+    players: HashMap<Uuid, Player>,
+    /// Loaded levels, each with the spatial index of its players and entities.
+    streamer: LevelStreamer,
+    /// Entity identifiers players collect, see [`GameConfig::pickups`].
+    pickups: HashSet<String>,
+    /// Iids of collected entities, kept out of the level when the world is reloaded.
+    picked_up: HashSet<String>,
+}
+
+impl GameSession {
+
+    pub fn new(project: PathBuf, world: Arc<World>, config: &GameConfig) -> Self {
+        let collisions = collision_maps(&world, config);
+        Self::with_collisions(project, world, collisions, config)
+    }
+
+    /// Like [`GameSession::new`], with collision maps already built, e.g. read from the world cache.
+    pub fn with_collisions(project: PathBuf, world: Arc<World>, collisions: HashMap<String, CollisionMap>,
+        config: &GameConfig) -> Self {
+        let visibility = visibility_maps(&world, &collisions, config);
+        Self {
+            project,
+            iids: IidIndex::shared(vec![world.clone()]),
+            world,
+            collisions,
+            visibility,
+            pathfinder: Pathfinder::new(config.pathfinding.clone()),
+            players: HashMap::new(),
+            streamer: LevelStreamer::new(),
+            pickups: config.pickups.iter().cloned().collect(),
+            picked_up: HashSet::new(),
+        }
+    }
+
+    /// Validates and compiles every project of `maps_dir`, going through the world cache,
+    /// then runs the first world of the first one.
+    pub fn load(config: &GameConfig) -> Result<Self, Box<dyn Error>> {
+        let mut errors = Vec::new();
+        let mut running = None;
+        for project in find_projects(&config.maps_dir)? {
+            match cache::load(&project, config) {
+                Ok(compiled) => if running.is_none() {
+                    running = Some((project, compiled));
+                }
+                Err(CacheError::Invalid(issues)) => errors.extend(issues),
+                Err(err) => errors.push(format!("{}: {}", project.display(), err)),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(format!("invalid maps:\n{}", errors.join("\n")).into());
+        }
+
+        let (project, mut compiled) = running
+            .ok_or_else(|| format!("no project in {}", config.maps_dir.display()))?;
+        if compiled.worlds.is_empty() {
+            return Err(format!("no world in {}", project.display()).into());
+        }
+        let world = compiled.worlds.swap_remove(0);
+        let collisions = world.levels.iter()
+            .filter_map(|level| compiled.collisions.remove_entry(&level.iid))
+            .collect();
+        tracing::info!("Running world {} of {}", world.identifier, project.display());
+        Ok(Self::with_collisions(project, Arc::new(world), collisions, config))
+    }
+
+    pub fn project(&self) -> &std::path::Path {
+        &self.project
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    /// Swaps in a reloaded world. Players on a level listed in `changed` are told to
+    /// reload it; players whose level is gone respawn. Returns the messages to push.
+    pub fn replace_world(&mut self, world: World, changed: &HashSet<String>, config: &GameConfig)
+        -> Vec<(Uuid, ServerMessage)> {
+        self.world = Arc::new(world);
+        self.iids = IidIndex::shared(vec![self.world.clone()]);
+        self.collisions = collision_maps(&self.world, config);
+        self.visibility = visibility_maps(&self.world, &self.collisions, config);
+        self.pathfinder.clear_cache();
+        self.streamer = LevelStreamer::new();
+        self.pickups = config.pickups.iter().cloned().collect();
+
+        let mut messages = Vec::new();
+        let ids: Vec<Uuid> = self.players.keys().copied().collect();
+        for id in ids {
+            let level_iid = self.players[&id].level_iid.clone();
+            if self.world.level(&level_iid).is_some() {
+                self.streamer.enter(&self.world, &level_iid, id, &self.picked_up);
+                self.index_player(id);
+                if changed.contains(&level_iid) {
+                    messages.push((id, ServerMessage::LevelChanged { level_iid }));
+                }
+                continue;
+            }
+            let Some((level, pos)) = self.spawn_point() else {
+                continue;
+            };
+            let level_iid = level.iid.clone();
+            tracing::info!("Level of player {} was removed, respawning in {}", id, level.identifier);
+            self.streamer.enter(&self.world, &level_iid, id, &self.picked_up);
+            let player = self.players.get_mut(&id).expect("player listed above");
+            player.level_iid = level_iid;
+            player.pos = pos;
+            self.index_player(id);
+            messages.extend(self.enter_level_message(&self.players[&id]).map(|message| (id, message)));
+        }
+        messages
+    }
+
+    /// Resolves an `EntityRef` field value. Only the running world is indexed, so
+    /// references into other worlds of the project are not found.
+    pub fn resolve(&self, reference: &EntityRef) -> Result<EntityHandle<'_>, RefError> {
+        self.iids.resolve(reference)
+    }
+
+    pub fn collision(&self, level_iid: &str) -> Option<&CollisionMap> {
+        self.collisions.get(level_iid)
+    }
+
+    pub fn visibility(&self, level_iid: &str) -> Option<&Visibility> {
+        self.visibility.get(level_iid)
+    }
+
+    pub fn streamer(&self) -> &LevelStreamer {
+        &self.streamer
+    }
+
+    pub fn player(&self, id: Uuid) -> Option<&Player> {
+        self.players.get(&id)
+    }
+
+    /// Spatial index of a loaded level, `None` while no player is on or next to it.
+    pub fn space(&self, level_iid: &str) -> Option<&SpatialIndex<Occupant>> {
+        self.streamer.level(level_iid).map(LevelSim::space)
+    }
+
+    /// Players of a level whose box comes within `radius` of `center`.
+    pub fn players_near(&self, level_iid: &str, center: Vec2, radius: f32) -> Vec<Uuid> {
+        self.space(level_iid)
+            .map(|space| space.query_radius(center, radius))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|occupant| match occupant {
+                Occupant::Player(id) => Some(id),
+                Occupant::Entity(_) => None,
+            })
+            .collect()
+    }
+
+    /// Players within `radius` of `eye` with nothing opaque in between, for NPC detection.
+    pub fn players_in_sight(&self, level_iid: &str, eye: Vec2, radius: f32) -> Vec<Uuid> {
+        let Some(visibility) = self.visibility.get(level_iid) else {
+            return Vec::new();
+        };
+        self.players_near(level_iid, eye, radius)
+            .into_iter()
+            .filter(|id| visibility.line_of_sight(eye, self.players[id].aabb().center()))
+            .collect()
+    }
+
+    /// Other players and entities a player sees within `radius`, to filter what it is sent.
+    pub fn visible_to(&self, id: Uuid, radius: f32) -> Vec<Occupant> {
+        let Some(player) = self.players.get(&id) else {
+            return Vec::new();
+        };
+        let (Some(visibility), Some(space)) = (self.visibility.get(&player.level_iid), self.space(&player.level_iid)) else {
+            return Vec::new();
+        };
+        let eye = player.aabb().center();
+        let fov = visibility.field_of_view(eye, radius);
+        space.query_radius(eye, radius)
+            .into_iter()
+            .filter(|occupant| *occupant != Occupant::Player(id))
+            .filter(|occupant| space.get(occupant).is_some_and(|bounds| visibility.sees(&fov, bounds.center())))
+            .collect()
+    }
+
+    /// Whether a tile under the center of a player is tagged with an enum value, e.g. `Ladder`.
+    pub fn on_tagged_tile(&self, id: Uuid, tag: &str) -> bool {
+        self.tile_under(id).is_some_and(|(level, point)| level.tile_tagged(&self.world.defs, point, tag))
+    }
+
+    /// Custom data value of the tile under the center of a player, e.g. its footstep sound.
+    pub fn ground_value(&self, id: Uuid, key: &str) -> Option<&serde_json::Value> {
+        let (level, point) = self.tile_under(id)?;
+        level.tile_value(&self.world.defs, point, key)
+    }
+
+    fn tile_under(&self, id: Uuid) -> Option<(&Level, Point)> {
+        let player = self.players.get(&id)?;
+        let center = player.aabb().center();
+        Some((self.world.level(&player.level_iid)?, Point::new(center.x.floor() as i32, center.y.floor() as i32)))
+    }
+
+    /// Iids of the entities overlapping a player.
+    pub fn touching(&self, id: Uuid) -> Vec<String> {
+        let Some(player) = self.players.get(&id) else {
+            return Vec::new();
+        };
+        self.space(&player.level_iid)
+            .map(|space| space.query_rect(player.aabb()))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|occupant| match occupant {
+                Occupant::Entity(iid) => Some(iid),
+                Occupant::Player(_) => None,
+            })
+            .collect()
+    }
+
+    /// Adds a runtime entity to a loaded level, or moves it. Returns false for a level
+    /// that is unknown or not loaded; the entity goes away when its level unloads.
+    pub fn spawn_entity(&mut self, level_iid: &str, entity_iid: String, bounds: Aabb) -> bool {
+        let Some(sim) = self.streamer.level_mut(level_iid) else {
+            return false;
+        };
+        sim.space_mut().insert(Occupant::Entity(entity_iid), bounds);
+        true
+    }
+
+    pub fn despawn_entity(&mut self, level_iid: &str, entity_iid: &str) -> bool {
+        self.streamer.level_mut(level_iid)
+            .and_then(|sim| sim.space_mut().remove(&Occupant::Entity(entity_iid.to_owned())))
+            .is_some()
+    }
+
+    /// Changes the terrain of a cell at runtime, e.g. for a door opening, and drops
+    /// cached paths that may cross it. Returns false for an unknown level.
+    pub fn set_terrain(&mut self, level_iid: &str, cell: Cell, terrain: Terrain) -> bool {
+        let Some(map) = self.collisions.get_mut(level_iid) else {
+            return false;
+        };
+        map.set_terrain(cell, terrain);
+        self.pathfinder.clear_cache();
+        true
+    }
+
+    /// Shortest path between two cells, possibly crossing into neighbour levels.
+    pub fn find_path(&self, from: &NavPoint, to: &NavPoint) -> Option<Arc<Path>> {
+        let graph = NavGraph::new(&self.world, &self.collisions);
+        self.pathfinder.find_path(&graph, from, to)
+    }
+
+    /// Places a new player at the start position and tells the client which level to render.
+    pub fn join(&mut self, id: Uuid) -> Option<ServerMessage> {
+        let (level, pos) = self.spawn_point()?;
+        let level_iid = level.iid.clone();
+        self.streamer.enter(&self.world, &level_iid, id, &self.picked_up);
+        let player = Player::new(id, level_iid, pos);
+        let message = self.enter_level_message(&player);
+        self.players.insert(id, player);
+        self.index_player(id);
+        message
+    }
+
+    pub fn leave(&mut self, id: Uuid) {
+        if let Some(player) = self.players.remove(&id) {
+            if let Some(sim) = self.streamer.level_mut(&player.level_iid) {
+                sim.space_mut().remove(&Occupant::Player(id));
+            }
+            self.streamer.leave(&self.world, &player.level_iid, id, &self.picked_up);
+        }
+    }
+
+    /// Moves a player, sliding along walls. Crossing the edge of the level
+    /// into a neighbour moves the player into that level. A delta that is not
+    /// finite, e.g. `1e39` read as an `f32`, is answered with an error.
+    pub fn move_player(&mut self, id: Uuid, delta: Vec2) -> Vec<ServerMessage> {
+        if !delta.x.is_finite() || !delta.y.is_finite() {
+            return vec![ServerMessage::Error { message: "move delta must be finite".to_owned() }];
+        }
+        let Some(player) = self.players.get(&id) else {
+            return Vec::new();
+        };
+        let (Some(level), Some(map)) = (self.world.level(&player.level_iid), self.collisions.get(&player.level_iid)) else {
+            return Vec::new();
+        };
+
+        let aabb = player.aabb();
+        let length = delta.length();
+        let scale = if length > MAX_STEP { MAX_STEP / length } else { 1.0 } * map.speed_factor(aabb.center());
+        let delta = Vec2::new(delta.x * scale, delta.y * scale);
+        let moved = map.move_box_by(aabb, delta, |cell| self.is_walkable(level, map, cell));
+
+        let center = Vec2::new(moved.pos.x + PLAYER_SIZE / 2.0, moved.pos.y + PLAYER_SIZE / 2.0);
+        let center = Point::new(center.x.floor() as i32, center.y.floor() as i32);
+        let next = if level.bounds().contains(center) {
+            None
+        } else {
+            self.world.neighbour_at(level, level.to_world(center))
+        };
+
+        let mut messages = Vec::new();
+        match next {
+            Some(next) => {
+                let pos = Vec2::new(
+                    moved.pos.x + (level.world_pos.x - next.world_pos.x) as f32,
+                    moved.pos.y + (level.world_pos.y - next.world_pos.y) as f32);
+                let (from, to) = (level.iid.clone(), next.iid.clone());
+                tracing::debug!("Player {} moves from level {} to {}", id, level.identifier, next.identifier);
+                if let Some(sim) = self.streamer.level_mut(&from) {
+                    sim.space_mut().remove(&Occupant::Player(id));
+                }
+                self.streamer.leave(&self.world, &from, id, &self.picked_up);
+                self.streamer.enter(&self.world, &to, id, &self.picked_up);
+                let player = self.players.get_mut(&id).expect("player checked above");
+                player.level_iid = to;
+                player.pos = pos;
+                self.index_player(id);
+                messages.extend(self.enter_level_message(&self.players[&id]));
+            }
+            None => {
+                self.players.get_mut(&id).expect("player checked above").pos = moved.pos;
+                self.index_player(id);
+                messages.push(ServerMessage::Position { x: moved.pos.x, y: moved.pos.y });
+            }
+        }
+        messages.extend(self.pick_up(id));
+        messages
+    }
+
+    /// Removes the pickups a player touches from its level.
+    fn pick_up(&mut self, id: Uuid) -> Vec<ServerMessage> {
+        let Some(level_iid) = self.players.get(&id).map(|player| player.level_iid.clone()) else {
+            return Vec::new();
+        };
+        let world = self.world.clone();
+        let mut messages = Vec::new();
+        for entity_iid in self.touching(id) {
+            let Some(entity) = world.level(&level_iid).and_then(|level| level.entity(&entity_iid)) else {
+                continue;
+            };
+            if self.pickups.contains(&entity.identifier) && self.despawn_entity(&level_iid, &entity_iid) {
+                tracing::debug!("Player {} picked up {} {}", id, entity.identifier, entity_iid);
+                self.picked_up.insert(entity_iid.clone());
+                messages.push(ServerMessage::PickedUp { entity_iid, identifier: entity.identifier.clone() });
+            }
+        }
+        messages
+    }
+
+    /// Puts a player into the spatial index of its current level.
+    fn index_player(&mut self, id: Uuid) {
+        let Some(player) = self.players.get(&id) else {
+            return;
+        };
+        if let Some(sim) = self.streamer.level_mut(&player.level_iid) {
+            sim.space_mut().insert(Occupant::Player(id), player.aabb());
+        }
+    }
+
+    /// Walkability of a cell of `level`, looking into neighbour levels for cells outside its grid.
+    fn is_walkable(&self, level: &Level, map: &CollisionMap, cell: Cell) -> bool {
+        if map.contains(cell) {
+            return map.is_walkable(cell);
+        }
+        let center = map.cell_center(cell);
+        let world = level.to_world(Point::new(center.x.floor() as i32, center.y.floor() as i32));
+        self.world.neighbour_at(level, world)
+            .and_then(|next| self.collisions.get(&next.iid).map(|next_map| (next, next_map)))
+            .is_some_and(|(next, next_map)| next_map.is_walkable(next_map.cell_at(next.to_local(world).into())))
+    }
+
+    fn spawn_point(&self) -> Option<(&Level, Vec2)> {
+        let start = self.world.levels.iter()
+            .find_map(|level| level.entities().find(|entity| entity.identifier == PLAYER_START).map(|entity| (level, entity)));
+        if let Some((level, entity)) = start {
+            let bounds = entity.bounds();
+            return Some((level, Vec2::new(bounds.x as f32, bounds.y as f32)));
+        }
+        let level = self.world.levels.first()?;
+        let map = self.collisions.get(&level.iid)?;
+        (0..map.size().h)
+            .flat_map(|cy| (0..map.size().w).map(move |cx| Cell::new(cx, cy)))
+            .find(|&cell| map.is_walkable(cell))
+            .map(|cell| {
+                let center = map.cell_center(cell);
+                (level, Vec2::new(center.x - PLAYER_SIZE / 2.0, center.y - PLAYER_SIZE / 2.0))
+            })
+    }
+
+    fn enter_level_message(&self, player: &Player) -> Option<ServerMessage> {
+        let level = self.world.level(&player.level_iid)?;
+        Some(ServerMessage::EnterLevel {
+            level_iid: level.iid.clone(),
+            identifier: level.identifier.clone(),
+            world_x: level.world_pos.x,
+            world_y: level.world_pos.y,
+            x: player.pos.x,
+            y: player.pos.y,
+        })
+    }
+}
+
+fn collision_maps(world: &World, config: &GameConfig) -> HashMap<String, CollisionMap> {
+    world.levels.iter()
+        .map(|level| (level.iid.clone(), CollisionMap::from_level(level, &world.defs, &config.collision)))
+        .collect()
+}
+
+fn visibility_maps(world: &World, collisions: &HashMap<String, CollisionMap>, config: &GameConfig)
+    -> HashMap<String, Visibility> {
+    world.levels.iter()
+        .filter_map(|level| {
+            let map = collisions.get(&level.iid)?;
+            let visibility = Visibility::from_level(level, &world.defs, map, &config.collision, &config.visibility);
+            Some((level.iid.clone(), visibility))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{geom::Size, model::WorldLayout};
+
+    /// A session on one empty 4x1-cell level, whose collision map is all floor.
+    fn session() -> GameSession {
+        let level = Level {
+            uid: 0,
+            iid: "a".to_owned(),
+            identifier: "A".to_owned(),
+            world_pos: Point::default(),
+            world_depth: 0,
+            size: Size::new(64, 16),
+            bg_color: String::new(),
+            neighbours: Vec::new(),
+            layers: Vec::new(),
+        };
+        let world = World::new("world".to_owned(), "World".to_owned(), WorldLayout::Free, Size::new(64, 16),
+            vec![level], Default::default());
+        GameSession::new(PathBuf::from("test.ldtk"), Arc::new(world), &GameConfig::default())
+    }
+
+    fn sample_world() -> World {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../test_storange/maps/map1/map1.ldtk");
+        crate::world::load_worlds(&path).unwrap().swap_remove(0)
+    }
+
+    #[test]
+    fn resolves_entity_refs_of_the_running_world() {
+        let world = sample_world();
+        let level = &world.levels[0];
+        let entity = level.entities().next().unwrap();
+        let mut reference = EntityRef {
+            entity_iid: entity.iid.clone(),
+            layer_iid: String::new(),
+            level_iid: level.iid.clone(),
+            world_iid: world.iid.clone(),
+        };
+        let mut session = GameSession::new(PathBuf::from("map1.ldtk"), Arc::new(world.clone()), &GameConfig::default());
+        assert_eq!(session.resolve(&reference).unwrap().entity.iid, reference.entity_iid);
+
+        reference.level_iid = "elsewhere".to_owned();
+        assert!(matches!(session.resolve(&reference), Err(RefError::Moved { .. })));
+
+        let mut reloaded = world.clone();
+        reloaded.levels.retain(|other| other.entity(&reference.entity_iid).is_none());
+        let reloaded = World::new(reloaded.iid, reloaded.identifier, reloaded.layout, reloaded.grid_size,
+            reloaded.levels, reloaded.defs);
+        session.replace_world(reloaded, &HashSet::new(), &GameConfig::default());
+        assert_eq!(session.resolve(&reference).unwrap_err(), RefError::NotFound);
+    }
+
+    #[test]
+    fn rejects_non_finite_moves() {
+        let mut session = session();
+        let id = Uuid::new_v4();
+        session.join(id);
+        let pos = session.player(id).unwrap().pos;
+        for delta in [Vec2::new(f32::NAN, 0.0), Vec2::new(0.0, f32::INFINITY), Vec2::new(f32::NEG_INFINITY, 1.0)] {
+            assert!(matches!(session.move_player(id, delta).as_slice(), [ServerMessage::Error { .. }]));
+            assert_eq!(session.player(id).unwrap().pos, pos);
+        }
+        assert!(matches!(session.move_player(id, Vec2::new(1.0, 0.0)).as_slice(), [ServerMessage::Position { .. }]));
+    }
+
+    #[test]
+    fn pickups_leave_the_level_for_good() {
+        let world = sample_world();
+        let level = &world.levels[0];
+        let key = level.entities().find(|entity| entity.identifier == "Key").unwrap();
+        let (level_iid, key_iid) = (level.iid.clone(), key.iid.clone());
+        let occupant = Occupant::Entity(key_iid.clone());
+        let mut session = GameSession::new(PathBuf::from("map1.ldtk"), Arc::new(world.clone()), &GameConfig::default());
+        let id = Uuid::new_v4();
+        session.join(id);
+        assert!(session.space(&level_iid).unwrap().get(&occupant).is_some());
+        let player = session.players.get_mut(&id).unwrap();
+        player.level_iid = level_iid.clone();
+        player.pos = Vec2::new(key.bounds().x as f32, key.bounds().y as f32);
+        let messages = session.move_player(id, Vec2::new(0.0, 0.0));
+        assert!(messages.iter().any(|message| matches!(message,
+            ServerMessage::PickedUp { entity_iid, identifier } if *entity_iid == key_iid && identifier == "Key")));
+        assert!(session.space(&level_iid).unwrap().get(&occupant).is_none());
+        assert!(!session.touching(id).contains(&key_iid));
+
+        session.replace_world(world, &HashSet::new(), &GameConfig::default());
+        assert!(session.space(&level_iid).unwrap().get(&occupant).is_none());
+    }
+
+    #[test]
+    fn levels_load_with_players_and_unload_without() {
+        let world = sample_world();
+        let (first, second) = (world.levels[0].iid.clone(), world.levels[1].iid.clone());
+        let entities = world.levels[0].entities().count();
+        let mut session = GameSession::new(PathBuf::from("map1.ldtk"), Arc::new(world), &GameConfig::default());
+        assert!(session.space(&first).is_none() && session.space(&second).is_none());
+        assert!(!session.spawn_entity(&first, "runtime".to_owned(), Aabb::new(0.0, 0.0, 8.0, 8.0)));
+
+        let id = Uuid::new_v4();
+        session.join(id);
+        let level_iid = session.player(id).unwrap().level_iid.clone();
+        let neighbour = if level_iid == first { &second } else { &first };
+        // The level of the player loads with its editor entities, and so does its neighbour.
+        assert!(session.space(neighbour).is_some());
+        assert!(session.spawn_entity(&first, "runtime".to_owned(), Aabb::new(0.0, 0.0, 8.0, 8.0)));
+        assert_eq!(session.space(&first).unwrap().len(), entities + 1 + usize::from(level_iid == first));
+
+        session.leave(id);
+        assert!(session.space(&first).is_none() && session.space(&second).is_none());
+        assert!(session.streamer().loaded().next().is_none());
+
+        // Reloading starts from the editor entities again, without what was spawned at runtime.
+        session.join(id);
+        let space = session.space(&first).unwrap();
+        assert!(space.get(&Occupant::Entity("runtime".to_owned())).is_none());
+        assert_eq!(space.len(), entities + usize::from(level_iid == first));
+    }
+
+    #[test]
+    fn set_terrain_invalidates_cached_paths() {
+        let mut session = session();
+        let (from, to) = (NavPoint::new("a", Cell::new(0, 0)), NavPoint::new("a", Cell::new(3, 0)));
+        assert!(session.find_path(&from, &to).is_some());
+
+        assert!(session.set_terrain("a", Cell::new(1, 0), Terrain::Solid));
+        assert!(session.find_path(&from, &to).is_none());
+        assert!(!session.set_terrain("b", Cell::new(1, 0), Terrain::Solid));
+    }
+}
@@ -0,0 +1,24 @@
+use uuid::Uuid;
+
+use crate::world::geom::{Aabb, Vec2};
+
+pub const PLAYER_SIZE: f32 = 12.0;
+
+#[derive(Debug, Clone)]
+pub struct Player {
+    pub id: Uuid,
+    pub level_iid: String,
+    /// Level-local top-left corner of the player box.
+    pub pos: Vec2,
+}
+
+impl Player {
+
+    pub fn new(id: Uuid, level_iid: String, pos: Vec2) -> Self {
+        Self { id, level_iid, pos }
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::new(self.pos.x, self.pos.y, PLAYER_SIZE, PLAYER_SIZE)
+    }
+}
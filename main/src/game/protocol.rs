@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+/// Messages sent by clients as JSON text frames.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Requested displacement in pixels; the server caps and scales it.
+    Move {
+        dx: f32,
+        dy: f32,
+    },
+}
+
+/// Messages sent to clients as JSON text frames.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// The client must render this level from now on.
+    EnterLevel {
+        level_iid: String,
+        identifier: String,
+        world_x: i32,
+        world_y: i32,
+        x: f32,
+        y: f32,
+    },
+    /// Level-local position of the player after a move.
+    Position {
+        x: f32,
+        y: f32,
+    },
+    /// The level the client renders was edited and must be fetched again.
+    LevelChanged {
+        level_iid: String,
+    },
+    /// The player walked into a pickup, which is gone from the level.
+    PickedUp {
+        entity_iid: String,
+        identifier: String,
+    },
+    Error {
+        message: String,
+    },
+    /// An admin disconnected the client; the socket closes right after.
+    Kicked,
+}
@@ -0,0 +1,195 @@
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+use crate::{
+    app_state::AppState,
+    config::GameConfig,
+    world::{cache, tiled, validate, worlds_from_project, Project, Severity, World},
+};
+
+/// LDtk writes the project and its level files one after the other on save;
+/// changes closer together than this are reloaded at once.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+const LEVEL_EXTENSION: &str = "ldtkl";
+
+/// Hash of the JSON of each level, by level iid, to tell which levels an edit touched.
+type LevelHashes = HashMap<String, Vec<u8>>;
+
+/// Watches `game.maps_dir` and swaps a freshly loaded world into the running session
+/// whenever the project, one of its external levels or a tileset image changes.
+/// A project that fails to load or validate is reported and the running world kept.
+pub fn watch(state: &AppState) -> notify::Result<()> {
+    let maps_dir = fs::canonicalize(&state.config().game.maps_dir)?;
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let _ = sender.send(event);
+    })?;
+    watcher.watch(&maps_dir, RecursiveMode::Recursive)?;
+    tracing::info!("Watching {} for map changes", maps_dir.display());
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        // Dropping the watcher stops it, so it lives as long as the task.
+        let _watcher = watcher;
+        let project = state.game().lock().await.project().to_owned();
+        let mut hashes = tokio::task::spawn_blocking(move || current_hashes(&project))
+            .await
+            .unwrap_or_default();
+
+        while let Some(event) = receiver.recv().await {
+            let mut paths = changed_paths(event);
+            while let Ok(Some(event)) = tokio::time::timeout(DEBOUNCE, receiver.recv()).await {
+                paths.extend(changed_paths(event));
+            }
+            if !paths.is_empty() {
+                reload(&state, &paths, &mut hashes).await;
+            }
+        }
+    });
+    Ok(())
+}
+
+fn changed_paths(event: notify::Result<Event>) -> Vec<PathBuf> {
+    match event {
+        Ok(event) => match event.kind {
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => event.paths,
+            _ => Vec::new(),
+        },
+        Err(err) => {
+            tracing::warn!("Map watcher error: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+async fn reload(state: &AppState, paths: &[PathBuf], hashes: &mut LevelHashes) {
+    let game = state.game();
+    let (project, world_iid, tilesets) = {
+        let session = game.lock().await;
+        (session.project().to_owned(), session.world().iid.clone(), tileset_paths(session.project(), session.world()))
+    };
+
+    let project_file = fs::canonicalize(&project).unwrap_or_else(|_| project.clone());
+    let project_dir = project_file.parent().unwrap_or(Path::new(""));
+    let mut relevant = false;
+    let mut changed_tilesets = HashSet::new();
+    for path in paths {
+        let in_project_dir = path.starts_with(project_dir);
+        if *path == project_file
+            || (in_project_dir && path.extension().is_some_and(|ext| ext == LEVEL_EXTENSION))
+            || (in_project_dir && path.extension().is_some_and(|ext| ext == tiled::TSX_EXTENSION || ext == tiled::TSJ_EXTENSION)) {
+            relevant = true;
+        } else if let Some(uid) = fs::canonicalize(path).ok().and_then(|path| tilesets.get(&path)) {
+            changed_tilesets.insert(*uid);
+            relevant = true;
+        }
+    }
+    if !relevant {
+        return;
+    }
+
+    let config = state.config().game.clone();
+    let loaded = tokio::task::spawn_blocking(move || load(&project, &world_iid, &config)).await;
+    let (world, new_hashes) = match loaded {
+        Ok(Ok(loaded)) => loaded,
+        Ok(Err(err)) => {
+            tracing::warn!("Keeping the running world, reload failed: {}", err);
+            return;
+        }
+        Err(err) => {
+            tracing::error!("Map reload panicked: {}", err);
+            return;
+        }
+    };
+
+    let changed: HashSet<String> = world.levels.iter()
+        .filter(|level| hashes.get(&level.iid) != new_hashes.get(&level.iid)
+            || level.layers.iter().any(|layer| layer.tileset_uid.is_some_and(|uid| changed_tilesets.contains(&uid))))
+        .map(|level| level.iid.clone())
+        .collect();
+    *hashes = new_hashes;
+
+    let identifier = world.identifier.clone();
+    let messages = game.lock().await.replace_world(world, &changed, &state.config().game);
+    tracing::info!("Reloaded world {}, {} level(s) changed", identifier, changed.len());
+
+    let clients = state.clients();
+    for (id, message) in messages {
+        if let Some(client) = clients.get_client(&id.to_string()).await {
+            client.lock().await.push(message);
+        }
+    }
+}
+
+/// Parses and validates the project again, then builds the world that was running.
+fn load(project_path: &Path, world_iid: &str, config: &GameConfig) -> Result<(World, LevelHashes), String> {
+    if tiled::is_tiled_map(project_path) {
+        let mut worlds = cache::compile(project_path, config).map_err(|err| err.to_string())?.worlds;
+        let world = worlds.pop().ok_or_else(|| format!("no world in {}", project_path.display()))?;
+        let hashes = tiled_hashes(project_path, &world);
+        return Ok((world, hashes));
+    }
+    let project = Project::load(project_path).map_err(|err| err.to_string())?;
+    let issues = validate::validate(&project, &config.required_layers);
+    let mut errors = Vec::new();
+    for issue in issues {
+        match issue.severity {
+            Severity::Warning => tracing::warn!("{}", issue),
+            Severity::Error => errors.push(issue.to_string()),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(format!("invalid map:\n{}", errors.join("\n")));
+    }
+
+    let mut worlds = worlds_from_project(&project).map_err(|err| err.to_string())?;
+    if worlds.is_empty() {
+        return Err(format!("no world in {}", project_path.display()));
+    }
+    let index = worlds.iter().position(|world| world.iid == world_iid).unwrap_or(0);
+    Ok((worlds.swap_remove(index), level_hashes(&project)))
+}
+
+fn current_hashes(project: &Path) -> LevelHashes {
+    if tiled::is_tiled_map(project) {
+        return tiled::load(project).map(|world| tiled_hashes(project, &world)).unwrap_or_default();
+    }
+    Project::load(project).map(|project| level_hashes(&project)).unwrap_or_default()
+}
+
+fn level_hashes(project: &Project) -> LevelHashes {
+    project.levels()
+        .map(|level| {
+            let json = serde_json::to_vec(level).expect("levels serialize");
+            (level.iid.clone(), Sha256::digest(&json).to_vec())
+        })
+        .collect()
+}
+
+/// A Tiled map holds a single level, which changes with the map or its tileset files.
+fn tiled_hashes(path: &Path, world: &World) -> LevelHashes {
+    let mut hasher = Sha256::new();
+    for file in std::iter::once(path.to_owned()).chain(tiled::dependencies(path).unwrap_or_default()) {
+        hasher.update(fs::read(&file).unwrap_or_default());
+    }
+    let hash = hasher.finalize().to_vec();
+    world.levels.iter().map(|level| (level.iid.clone(), hash.clone())).collect()
+}
+
+/// Canonical image path of each tileset of the world.
+fn tileset_paths(project: &Path, world: &World) -> HashMap<PathBuf, i64> {
+    let dir = project.parent().unwrap_or(Path::new(""));
+    world.defs.tilesets.values()
+        .filter_map(|tileset| {
+            let path = fs::canonicalize(dir.join(tileset.rel_path.as_deref()?)).ok()?;
+            Some((path, tileset.uid))
+        })
+        .collect()
+}
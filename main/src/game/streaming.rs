@@ -0,0 +1,123 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::world::{model::Level, spatial::SpatialIndex, World};
+
+use super::{Occupant, SPATIAL_CELL_SIZE};
+
+/// Simulation state of a loaded level: its players and the spatial index of its players
+/// and entities. Built from the level's editor entities when it loads, dropped with
+/// everything spawned into it when it unloads.
+#[derive(Debug)]
+pub struct LevelSim {
+    players: HashSet<Uuid>,
+    space: SpatialIndex<Occupant>,
+}
+
+impl LevelSim {
+
+    /// Indexes the entities placed in the editor, except the ones already collected.
+    fn load(level: &Level, picked_up: &HashSet<String>) -> Self {
+        let mut space = SpatialIndex::new(SPATIAL_CELL_SIZE);
+        for entity in level.entities().filter(|entity| !picked_up.contains(&entity.iid)) {
+            space.insert(Occupant::Entity(entity.iid.clone()), entity.bounds().into());
+        }
+        Self {
+            players: HashSet::new(),
+            space,
+        }
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = &Uuid> {
+        self.players.iter()
+    }
+
+    pub fn space(&self) -> &SpatialIndex<Occupant> {
+        &self.space
+    }
+
+    pub fn space_mut(&mut self) -> &mut SpatialIndex<Occupant> {
+        &mut self.space
+    }
+}
+
+/// Keeps the levels with players loaded, plus their neighbours so a player
+/// crossing an edge never waits for a level to load.
+#[derive(Debug, Default)]
+pub struct LevelStreamer {
+    active: HashMap<String, LevelSim>,
+}
+
+impl LevelStreamer {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_loaded(&self, level_iid: &str) -> bool {
+        self.active.contains_key(level_iid)
+    }
+
+    pub fn level(&self, level_iid: &str) -> Option<&LevelSim> {
+        self.active.get(level_iid)
+    }
+
+    pub fn level_mut(&mut self, level_iid: &str) -> Option<&mut LevelSim> {
+        self.active.get_mut(level_iid)
+    }
+
+    pub fn loaded(&self) -> impl Iterator<Item = &String> {
+        self.active.keys()
+    }
+
+    /// Adds a player to a level, loading it and its neighbours. Entities in `picked_up`
+    /// are left out of levels that load.
+    pub fn enter(&mut self, world: &World, level_iid: &str, player: Uuid, picked_up: &HashSet<String>) {
+        let Some(level) = world.level(level_iid) else {
+            return;
+        };
+        self.active.entry(level_iid.to_owned())
+            .or_insert_with(|| LevelSim::load(level, picked_up))
+            .players
+            .insert(player);
+        self.refresh(world, picked_up);
+    }
+
+    pub fn leave(&mut self, world: &World, level_iid: &str, player: Uuid, picked_up: &HashSet<String>) {
+        if let Some(sim) = self.active.get_mut(level_iid) {
+            sim.players.remove(&player);
+        }
+        self.refresh(world, picked_up);
+    }
+
+    fn refresh(&mut self, world: &World, picked_up: &HashSet<String>) {
+        let mut wanted: HashSet<String> = HashSet::new();
+        for (iid, sim) in &self.active {
+            if sim.players.is_empty() {
+                continue;
+            }
+            wanted.insert(iid.clone());
+            if let Some(level) = world.level(iid) {
+                wanted.extend(level.neighbours.iter().map(|neighbour| neighbour.level_iid.clone()));
+            }
+        }
+
+        self.active.retain(|iid, _| {
+            let keep = wanted.contains(iid);
+            if !keep {
+                tracing::debug!("Unloading level {}", iid);
+            }
+            keep
+        });
+        for iid in wanted {
+            if self.active.contains_key(&iid) {
+                continue;
+            }
+            if let Some(level) = world.level(&iid) {
+                tracing::debug!("Loading level {}", iid);
+                self.active.insert(iid, LevelSim::load(level, picked_up));
+            }
+        }
+    }
+}
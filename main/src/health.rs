@@ -0,0 +1,113 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+
+use crate::{
+    app_state::AppState,
+    world::{self, Severity},
+};
+
+#[derive(Serialize)]
+pub struct Readiness {
+    ready: bool,
+    checks: Vec<Check>,
+}
+
+#[derive(Serialize)]
+pub struct Check {
+    name: &'static str,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    fn from_result(name: &'static str, result: Result<(), String>) -> Self {
+        Self {
+            name,
+            ok: result.is_ok(),
+            error: result.err(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct BuildInfo {
+    name: &'static str,
+    version: &'static str,
+    git_sha: &'static str,
+    build_timestamp: &'static str,
+    profile: &'static str,
+}
+
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let checks = vec![
+        Check::from_result("draining", if state.is_draining() {
+            Err("server is shutting down".to_owned())
+        } else {
+            Ok(())
+        }),
+        Check::from_result("database", check_database(&state).await),
+        Check::from_result("maps", check_maps(&state).await),
+        Check::from_result("auth_key", check_auth_key(&state)),
+    ];
+    let ready = checks.iter().all(|check| check.ok);
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(Readiness { ready, checks }))
+}
+
+pub async fn version() -> Json<BuildInfo> {
+    Json(BuildInfo {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("BUILD_GIT_SHA"),
+        build_timestamp: env!("BUILD_TIMESTAMP"),
+        profile: env!("BUILD_PROFILE"),
+    })
+}
+
+async fn check_database(state: &AppState) -> Result<(), String> {
+    sqlx::query("SELECT 1")
+        .execute(state.db_pool())
+        .await
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+/// The running world must have levels, and its project must still load and pass the
+/// content checks, so a map broken on disk makes the server unready.
+async fn check_maps(state: &AppState) -> Result<(), String> {
+    let project = {
+        let game = state.game();
+        let game = game.lock().await;
+        if game.world().levels.is_empty() {
+            return Err(format!("world {} has no levels", game.world().identifier));
+        }
+        game.project().to_owned()
+    };
+    let required_layers = state.config().game.required_layers.clone();
+    let issues = tokio::task::spawn_blocking(move || world::validate_project(&project, &required_layers)
+        .map_err(|err| err.to_string()))
+        .await
+        .map_err(|err| err.to_string())??;
+    let errors: Vec<String> = issues.iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .map(ToString::to_string)
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+fn check_auth_key(state: &AppState) -> Result<(), String> {
+    let iam = &state.config().iam;
+    let cert = iam.read_pub_cert().map_err(|err| err.to_string())?;
+    jsonwebtoken::DecodingKey::from_rsa_pem(cert.as_bytes())
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
@@ -0,0 +1,16 @@
+pub mod admin;
+pub mod api_key;
+pub mod app_state;
+pub mod audit;
+pub mod auth;
+pub mod cli;
+pub mod client;
+pub mod config;
+pub mod db;
+pub mod game;
+pub mod health;
+pub mod maps;
+pub mod metrics;
+pub mod route;
+pub mod world;
+pub mod ws;
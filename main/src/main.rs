@@ -1,55 +1,104 @@
-use app_state::AppState;
-use sqlx::{self, postgres::PgPoolOptions};
-use std::{net::SocketAddr, time::Duration};
+use clap::Parser;
+use langrpg::{
+    app_state::AppState,
+    cli::{self, Cli, Command, ServeArgs},
+    config::{Config, MapStoreBackend},
+    db,
+    game::reload,
+    route,
+    world::store,
+};
+use std::net::SocketAddr;
 
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-mod client;
-mod auth;
-mod ws;
-mod config;
-mod app_state;
-mod route;
-mod world;
-
 #[tokio::main]
 async fn main() {
 
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "main=debug,tower_http=debug".into()),
+                .unwrap_or_else(|_| "main=debug,langrpg=debug,tower_http=debug".into()),
         )
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    if let Err(_) = dotenvy::dotenv() {
+    if dotenvy::dotenv().is_err() {
         tracing::warn!("Cannot load .env file")
     }
-    
-    let state = create_state().await;
-    let app = route::routes(state);
-
-    let host = config::load_env_var(config::HOST, "127.0.0.1");
-    let port = config::load_env_var(config::PORT, "3000");
-    let listener = tokio::net::TcpListener::bind(format!("{host}:{port}")).await.unwrap();
-    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+
+    match Cli::parse().command.unwrap_or(Command::Serve(ServeArgs::default())) {
+        Command::Serve(args) => serve(args).await,
+        command => if let Err(err) = cli::run(command).await {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
 }
 
-async fn create_state() -> AppState {
-    let db_url = config::load_env_var_or_fail(config::DATABASE_URL);
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .acquire_timeout(Duration::from_secs(3))
-        .connect(&db_url)
+async fn serve(args: ServeArgs) {
+    let config = Config::load()
+        .unwrap_or_else(|err| panic!("Invalid configuration:\n{}", err));
+    let addr = format!("{}:{}", config.server.host, config.server.port);
+
+    let state = create_state(config, !args.skip_migrations).await;
+    if state.config().game.hot_reload {
+        if let Err(err) = reload::watch(&state) {
+            panic!("Cannot watch maps for changes: {}", err);
+        }
+    }
+    let app = route::routes(state.clone());
+
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal(state))
         .await
-        .inspect_err(|err| {
-            panic!("Cannot connect to database: {}", err);
-        })
         .unwrap();
-    if let Err(err) = sqlx::migrate!().run(&pool).await {
-        panic!("Cannot run migrations: {}", err);
+}
+
+/// Resolves once the process is asked to stop. Readiness is failed first and
+/// kept failing for the drain period so load balancers stop routing to us.
+async fn shutdown_signal(state: AppState) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("Cannot install Ctrl+C handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Cannot install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    let drain = state.config().server.shutdown_drain();
+    tracing::info!("Shutdown requested, draining for {:?}", drain);
+    state.start_draining();
+    tokio::time::sleep(drain).await;
+}
+
+async fn create_state(mut config: Config, run_migrations: bool) -> AppState {
+    let pool = db::connect(&config.database).await;
+    if run_migrations {
+        if let Err(err) = db::migrate_up(&pool).await {
+            panic!("Cannot run migrations: {}", err);
+        }
+    }
+
+    let store = store::open(&config.game, pool.clone());
+    if config.game.store.backend == MapStoreBackend::Postgres {
+        let dir = config.game.store.checkout_dir.clone();
+        let written = store::checkout(store.as_ref(), &dir).await
+            .unwrap_or_else(|err| panic!("Cannot check out maps: {}", err));
+        tracing::info!("Checked out maps to {}, {} file(s) updated", dir.display(), written);
+        config.game.maps_dir = dir;
     }
 
-    AppState::new(pool)
-}
\ No newline at end of file
+    AppState::new(config, pool, store)
+}
@@ -0,0 +1,426 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::FromRow;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path as FsPath, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    auth::Claims,
+    world::{
+        self,
+        field::FieldValue,
+        model::{Entity, Layer, LayerKind, Level},
+        preview::PreviewSize,
+        World,
+    },
+};
+
+/// Level data changes with the map files, so clients always revalidate it.
+const LEVEL_CACHE_CONTROL: &str = "private, no-cache";
+const TILESET_CACHE_CONTROL: &str = "private, max-age=3600";
+const PREVIEW_CACHE_CONTROL: &str = "private, no-cache";
+
+type ApiError = (StatusCode, String);
+
+fn internal(what: &str, err: impl std::fmt::Display) -> ApiError {
+    tracing::error!("{}: {}", what, err);
+    (StatusCode::INTERNAL_SERVER_ERROR, what.to_owned())
+}
+
+fn not_found(what: &str) -> ApiError {
+    (StatusCode::NOT_FOUND, what.to_owned())
+}
+
+/// Worlds of a project file, as served to clients.
+pub struct LoadedMap {
+    pub path: PathBuf,
+    pub dir: PathBuf,
+    pub worlds: Vec<World>,
+}
+
+impl LoadedMap {
+    fn level(&self, iid: &str) -> Option<(&World, &Level)> {
+        self.worlds.iter().find_map(|world| world.level(iid).map(|level| (world, level)))
+    }
+}
+
+/// Parsed project files, reloaded when the file on disk is newer.
+#[derive(Default)]
+pub struct MapCache {
+    entries: RwLock<HashMap<PathBuf, (SystemTime, Arc<LoadedMap>)>>,
+}
+
+impl MapCache {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn get(&self, path: &FsPath) -> Result<Arc<LoadedMap>, String> {
+        let modified = tokio::fs::metadata(path).await
+            .and_then(|metadata| metadata.modified())
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        if let Some((cached_at, map)) = self.entries.read().await.get(path) {
+            if *cached_at == modified {
+                return Ok(map.clone());
+            }
+        }
+
+        let project = path.to_owned();
+        let worlds = tokio::task::spawn_blocking(move || world::load_worlds(&project))
+            .await
+            .map_err(|err| err.to_string())?
+            .map_err(|err| err.to_string())?;
+        let map = Arc::new(LoadedMap {
+            path: path.to_owned(),
+            dir: path.parent().unwrap_or(FsPath::new("")).to_owned(),
+            worlds,
+        });
+        self.entries.write().await.insert(path.to_owned(), (modified, map.clone()));
+        Ok(map)
+    }
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct WorldRecord {
+    pub id: Uuid,
+    pub iid: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct LevelRecord {
+    pub id: Uuid,
+    pub iid: Option<String>,
+    pub identifier: Option<String>,
+    pub file_name: Option<String>,
+    pub world_x: Option<i32>,
+    pub world_y: Option<i32>,
+    pub world_depth: Option<i32>,
+    pub px_wid: Option<i32>,
+    pub px_hei: Option<i32>,
+}
+
+pub async fn list_worlds(_: Claims, State(state): State<AppState>) -> Result<Json<Vec<WorldRecord>>, ApiError> {
+    sqlx::query_as::<_, WorldRecord>("SELECT id, iid, name FROM world ORDER BY name")
+        .fetch_all(state.db_pool())
+        .await
+        .map(Json)
+        .map_err(|err| internal("Cannot list worlds", err))
+}
+
+pub async fn list_levels(
+    _: Claims,
+    State(state): State<AppState>,
+    Path(world_id): Path<Uuid>,
+) -> Result<Json<Vec<LevelRecord>>, ApiError> {
+    find_world(&state, world_id).await?;
+    sqlx::query_as::<_, LevelRecord>(
+        "SELECT id, iid, identifier, file_name, world_x, world_y, world_depth, px_wid, px_hei \
+         FROM lvl WHERE world = $1 ORDER BY identifier")
+        .bind(world_id)
+        .fetch_all(state.db_pool())
+        .await
+        .map(Json)
+        .map_err(|err| internal("Cannot list levels", err))
+}
+
+pub async fn level_data(
+    _: Claims,
+    State(state): State<AppState>,
+    Path((world_id, level_iid)): Path<(Uuid, String)>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let map = load_map(&state, world_id).await?;
+    let known: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM lvl WHERE world = $1 AND iid = $2)")
+        .bind(world_id)
+        .bind(&level_iid)
+        .fetch_one(state.db_pool())
+        .await
+        .map_err(|err| internal("Cannot find level", err))?;
+    let (world, level) = map.level(&level_iid)
+        .filter(|_| known)
+        .ok_or_else(|| not_found("Level not found"))?;
+
+    let body = serde_json::to_vec(&LevelData::new(world_id, world, level))
+        .map_err(|err| internal("Cannot serialize level", err))?;
+    Ok(cached_response(&headers, body, "application/json", LEVEL_CACHE_CONTROL))
+}
+
+pub async fn tileset_image(
+    _: Claims,
+    State(state): State<AppState>,
+    Path((world_id, tileset_uid)): Path<(Uuid, i64)>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let map = load_map(&state, world_id).await?;
+    let rel_path = map.worlds.first()
+        .and_then(|world| world.defs.tilesets.get(&tileset_uid))
+        .and_then(|tileset| tileset.rel_path.clone())
+        .ok_or_else(|| not_found("Tileset not found"))?;
+    let path = map.dir.join(&rel_path);
+    let body = tokio::fs::read(&path).await
+        .map_err(|err| internal("Cannot read tileset image", format!("{}: {}", path.display(), err)))?;
+    Ok(cached_response(&headers, body, content_type(&path), TILESET_CACHE_CONTROL))
+}
+
+#[derive(Deserialize)]
+pub struct PreviewQuery {
+    /// `thumb` (default) or `full`.
+    size: Option<String>,
+}
+
+pub async fn level_preview(
+    _: Claims,
+    State(state): State<AppState>,
+    Path((world_id, level_iid)): Path<(Uuid, String)>,
+    Query(query): Query<PreviewQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let size = match query.size.as_deref() {
+        None | Some("thumb") => PreviewSize::Thumbnail,
+        Some("full") => PreviewSize::Full,
+        Some(other) => return Err((StatusCode::BAD_REQUEST, format!("Unknown preview size {}", other))),
+    };
+    let map = load_map(&state, world_id).await?;
+    if map.level(&level_iid).is_none() {
+        return Err(not_found("Level not found"));
+    }
+
+    let previews = state.previews();
+    let body = tokio::task::spawn_blocking(move || {
+        let (world, level) = map.level(&level_iid).expect("level checked above");
+        previews.get(&map.path, level, &world.defs, size)
+    })
+        .await
+        .map_err(|err| internal("Cannot render preview", err))?
+        .map_err(|err| internal("Cannot render preview", err))?;
+    Ok(cached_response(&headers, body, "image/png", PREVIEW_CACHE_CONTROL))
+}
+
+async fn find_world(state: &AppState, world_id: Uuid) -> Result<WorldRecord, ApiError> {
+    sqlx::query_as::<_, WorldRecord>("SELECT id, iid, name FROM world WHERE id = $1")
+        .bind(world_id)
+        .fetch_optional(state.db_pool())
+        .await
+        .map_err(|err| internal("Cannot find world", err))?
+        .ok_or_else(|| not_found("World not found"))
+}
+
+/// Imported worlds record their project file relative to the maps directory, see
+/// [`world::import::import_project`]. Older rows are named after the project file.
+async fn load_map(state: &AppState, world_id: Uuid) -> Result<Arc<LoadedMap>, ApiError> {
+    let (name, project): (Option<String>, Option<String>) =
+        sqlx::query_as("SELECT name, project FROM world WHERE id = $1")
+            .bind(world_id)
+            .fetch_optional(state.db_pool())
+            .await
+            .map_err(|err| internal("Cannot find world", err))?
+            .ok_or_else(|| not_found("World not found"))?;
+    let maps_dir = &state.config().game.maps_dir;
+    // Rows created before worlds had a project path are named after the project file.
+    // Importing them sets the path and renames them after the world identifier.
+    let path = match (project, name) {
+        (Some(project), _) => maps_dir.join(project),
+        (None, Some(name)) => world::find_projects(maps_dir)
+            .map_err(|err| internal("Cannot list maps", err))?
+            .into_iter()
+            .find(|path| path.file_stem().is_some_and(|stem| stem.to_string_lossy() == name))
+            .ok_or_else(|| not_found("World has no project file"))?,
+        (None, None) => return Err(not_found("World has no project file")),
+    };
+    state.maps().get(&path).await.map_err(|err| internal("Cannot load map", err))
+}
+
+fn cached_response(headers: &HeaderMap, body: Vec<u8>, content_type: &'static str, cache_control: &'static str)
+    -> Response {
+    let digest: String = Sha256::digest(&body)[..16].iter().map(|byte| format!("{:02x}", byte)).collect();
+    let etag = format!("\"{}\"", digest);
+    let matches = headers.get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|candidate| {
+            let candidate = candidate.trim();
+            candidate == "*" || candidate.trim_start_matches("W/") == etag
+        });
+    let etag = HeaderValue::from_str(&etag).expect("hex etag is a valid header");
+    let cache_headers = [(header::ETAG, etag), (header::CACHE_CONTROL, HeaderValue::from_static(cache_control))];
+    if matches {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+    (StatusCode::OK, cache_headers, [(header::CONTENT_TYPE, content_type)], Body::from(body)).into_response()
+}
+
+fn content_type(path: &FsPath) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("bmp") => "image/bmp",
+        _ => "application/octet-stream",
+    }
+}
+
+#[derive(Serialize)]
+struct LevelData<'a> {
+    iid: &'a str,
+    identifier: &'a str,
+    world_x: i32,
+    world_y: i32,
+    width: i32,
+    height: i32,
+    bg_color: &'a str,
+    neighbours: Vec<NeighbourData<'a>>,
+    /// Top-most layer first, like in LDtk.
+    layers: Vec<LayerData<'a>>,
+    tilesets: Vec<TilesetData<'a>>,
+}
+
+#[derive(Serialize)]
+struct NeighbourData<'a> {
+    iid: &'a str,
+    dir: &'static str,
+}
+
+#[derive(Serialize)]
+struct LayerData<'a> {
+    identifier: &'a str,
+    #[serde(rename = "type")]
+    layer_type: &'static str,
+    grid_size: i32,
+    c_wid: i32,
+    c_hei: i32,
+    px_offset: [i32; 2],
+    opacity: f32,
+    visible: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tileset_uid: Option<i64>,
+    /// Row-major IntGrid values.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    int_grid: Option<&'a [i32]>,
+    /// `[px_x, px_y, src_x, src_y, flip_bits, alpha]` per tile.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tiles: Vec<(i32, i32, i32, i32, u8, f32)>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    entities: Vec<EntityData<'a>>,
+}
+
+#[derive(Serialize)]
+struct EntityData<'a> {
+    iid: &'a str,
+    identifier: &'a str,
+    px: [i32; 2],
+    size: [i32; 2],
+    pivot: [f32; 2],
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    fields: BTreeMap<&'a str, &'a FieldValue>,
+}
+
+#[derive(Serialize)]
+struct TilesetData<'a> {
+    uid: i64,
+    identifier: &'a str,
+    width: i32,
+    height: i32,
+    tile_grid_size: i32,
+    spacing: i32,
+    padding: i32,
+    url: String,
+}
+
+impl<'a> LevelData<'a> {
+    fn new(world_id: Uuid, world: &'a World, level: &'a Level) -> Self {
+        let mut tileset_uids: Vec<i64> = level.layers.iter().filter_map(|layer| layer.tileset_uid).collect();
+        tileset_uids.sort_unstable();
+        tileset_uids.dedup();
+
+        Self {
+            iid: &level.iid,
+            identifier: &level.identifier,
+            world_x: level.world_pos.x,
+            world_y: level.world_pos.y,
+            width: level.size.w,
+            height: level.size.h,
+            bg_color: &level.bg_color,
+            neighbours: level.neighbours.iter()
+                .map(|neighbour| NeighbourData { iid: &neighbour.level_iid, dir: neighbour.dir.as_str() })
+                .collect(),
+            layers: level.layers.iter().map(LayerData::new).collect(),
+            tilesets: tileset_uids.into_iter()
+                .filter_map(|uid| world.defs.tilesets.get(&uid))
+                .filter(|tileset| tileset.rel_path.is_some())
+                .map(|tileset| TilesetData {
+                    uid: tileset.uid,
+                    identifier: &tileset.identifier,
+                    width: tileset.size.w,
+                    height: tileset.size.h,
+                    tile_grid_size: tileset.tile_grid_size,
+                    spacing: tileset.spacing,
+                    padding: tileset.padding,
+                    url: crate::route::PATH_TILESET
+                        .replace("{world_id}", &world_id.to_string())
+                        .replace("{uid}", &tileset.uid.to_string()),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<'a> LayerData<'a> {
+    fn new(layer: &'a Layer) -> Self {
+        let (layer_type, int_grid, entities) = match &layer.kind {
+            LayerKind::IntGrid { values, .. } => ("IntGrid", Some(values.as_slice()), Vec::new()),
+            LayerKind::Tiles { .. } => ("Tiles", None, Vec::new()),
+            LayerKind::AutoLayer { .. } => ("AutoLayer", None, Vec::new()),
+            LayerKind::Entities { entities } => ("Entities", None, entities.iter().map(EntityData::new).collect()),
+        };
+        Self {
+            identifier: &layer.identifier,
+            layer_type,
+            grid_size: layer.grid_size,
+            c_wid: layer.grid.w,
+            c_hei: layer.grid.h,
+            px_offset: [layer.px_offset.x, layer.px_offset.y],
+            opacity: layer.opacity,
+            visible: layer.visible,
+            tileset_uid: layer.tileset_uid,
+            int_grid,
+            tiles: layer.tiles().iter()
+                .map(|tile| (tile.px.x, tile.px.y, tile.src.x, tile.src.y,
+                    tile.flip_x as u8 | (tile.flip_y as u8) << 1, tile.alpha))
+                .collect(),
+            entities,
+        }
+    }
+}
+
+impl<'a> EntityData<'a> {
+    fn new(entity: &'a Entity) -> Self {
+        Self {
+            iid: &entity.iid,
+            identifier: &entity.identifier,
+            px: [entity.px.x, entity.px.y],
+            size: [entity.size.w, entity.size.h],
+            pivot: [entity.pivot.0, entity.pivot.1],
+            tags: &entity.tags,
+            fields: entity.fields.iter().map(|field| (field.identifier.as_str(), &field.value)).collect(),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use axum::{extract::State, http::{header, StatusCode}, response::IntoResponse};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+
+use crate::app_state::AppState;
+
+pub const AUTH_MISSING_TOKEN: &str = "missing_token";
+pub const AUTH_INVALID_TOKEN: &str = "invalid_token";
+pub const AUTH_INVALID_API_KEY: &str = "invalid_api_key";
+pub const AUTH_FORBIDDEN: &str = "forbidden";
+pub const AUTH_LOGIN_FAILED: &str = "login_failed";
+
+pub struct Metrics {
+    registry: Registry,
+    pub connected_clients: IntGauge,
+    pub ws_messages: IntCounterVec,
+    pub ws_message_duration: HistogramVec,
+    pub ws_ping_rtt: Histogram,
+    pub db_pool_connections: IntGauge,
+    pub db_pool_idle_connections: IntGauge,
+    pub db_pool_max_connections: IntGauge,
+    pub auth_failures: IntCounterVec,
+    pub active_games: IntGauge,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("langrpg".to_owned()), None)
+            .expect("valid metrics prefix");
+
+        let connected_clients = IntGauge::new("ws_connected_clients", "Open WebSocket connections").unwrap();
+        let ws_messages = IntCounterVec::new(
+            Opts::new("ws_messages_total", "WebSocket messages received by type"),
+            &["type"]).unwrap();
+        let ws_message_duration = HistogramVec::new(
+            HistogramOpts::new("ws_message_duration_seconds", "Time spent processing a WebSocket message")
+                .buckets(vec![0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5]),
+            &["type"]).unwrap();
+        let ws_ping_rtt = Histogram::with_opts(
+            HistogramOpts::new("ws_ping_rtt_seconds", "Round trip time of server pings")
+                .buckets(vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5])).unwrap();
+        let db_pool_connections = IntGauge::new("db_pool_connections", "Open database connections").unwrap();
+        let db_pool_idle_connections = IntGauge::new("db_pool_idle_connections", "Idle database connections").unwrap();
+        let db_pool_max_connections = IntGauge::new("db_pool_max_connections", "Configured database pool size").unwrap();
+        let auth_failures = IntCounterVec::new(
+            Opts::new("auth_failures_total", "Rejected authentication attempts by reason"),
+            &["reason"]).unwrap();
+        let active_games = IntGauge::new("active_games", "Games marked as active").unwrap();
+
+        registry.register(Box::new(connected_clients.clone())).unwrap();
+        registry.register(Box::new(ws_messages.clone())).unwrap();
+        registry.register(Box::new(ws_message_duration.clone())).unwrap();
+        registry.register(Box::new(ws_ping_rtt.clone())).unwrap();
+        registry.register(Box::new(db_pool_connections.clone())).unwrap();
+        registry.register(Box::new(db_pool_idle_connections.clone())).unwrap();
+        registry.register(Box::new(db_pool_max_connections.clone())).unwrap();
+        registry.register(Box::new(auth_failures.clone())).unwrap();
+        registry.register(Box::new(active_games.clone())).unwrap();
+
+        Self {
+            registry,
+            connected_clients,
+            ws_messages,
+            ws_message_duration,
+            ws_ping_rtt,
+            db_pool_connections,
+            db_pool_idle_connections,
+            db_pool_max_connections,
+            auth_failures,
+            active_games,
+        }
+    }
+
+    pub fn auth_failure(&self, reason: &str) {
+        self.auth_failures.with_label_values(&[reason]).inc();
+    }
+
+    fn encode(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+/// Gauges that mirror state owned elsewhere are sampled at scrape time.
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let metrics = state.metrics();
+    let pool = state.db_pool();
+    metrics.connected_clients.set(state.clients().count().await as i64);
+    metrics.db_pool_connections.set(pool.size() as i64);
+    metrics.db_pool_idle_connections.set(pool.num_idle() as i64);
+    metrics.db_pool_max_connections.set(state.config().database.max_connections as i64);
+    match sqlx::query_scalar::<_, i64>("SELECT count(*) FROM game WHERE active")
+        .fetch_one(pool)
+        .await {
+        Ok(count) => metrics.active_games.set(count),
+        Err(err) => tracing::warn!("Cannot count active games: {}", err),
+    }
+
+    match metrics.encode() {
+        Ok(body) => (StatusCode::OK, [(header::CONTENT_TYPE, TextEncoder::new().format_type().to_owned())], body),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, [(header::CONTENT_TYPE, "text/plain".to_owned())], err.to_string()),
+    }
+}
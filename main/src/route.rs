@@ -1,18 +1,42 @@
-use axum::{middleware, routing::get, Router};
+use axum::{routing::{get, post}, Router};
 
-use crate::{app_state::AppState, auth, ws};
+use crate::{admin, app_state::AppState, auth, health, maps, metrics, ws};
 
 pub const PATH_WS: &str = "/ws";
 pub const PATH_AUTH: &str = "/auth";
+pub const PATH_HEALTHZ: &str = "/healthz";
+pub const PATH_READYZ: &str = "/readyz";
+pub const PATH_VERSION: &str = "/version";
+pub const PATH_METRICS: &str = "/metrics";
+pub const PATH_ADMIN_AUDIT: &str = "/admin/audit";
+pub const PATH_ADMIN_KICK: &str = "/admin/clients/{id}/kick";
+pub const PATH_ADMIN_IMPORT_WORLD: &str = "/admin/worlds/import";
+pub const PATH_WORLDS: &str = "/worlds";
+pub const PATH_WORLD_LEVELS: &str = "/worlds/{world_id}/levels";
+pub const PATH_LEVEL: &str = "/worlds/{world_id}/levels/{iid}";
+pub const PATH_LEVEL_PREVIEW: &str = "/worlds/{world_id}/levels/{iid}/preview";
+pub const PATH_TILESET: &str = "/worlds/{world_id}/tilesets/{uid}";
 
 pub fn routes(app_state: AppState) -> Router {
     let ws = Router::new()
         .route(PATH_WS, get(ws::ws_handler));
         //.layer(middleware::from_fn_with_state(app_state.clone(), auth::ws_auth));
     let restricted = Router::new()
-        .route("/rs", get(restricted));
+        .route("/rs", get(restricted))
+        .route(PATH_ADMIN_AUDIT, get(admin::audit_log))
+        .route(PATH_ADMIN_KICK, post(admin::kick_client))
+        .route(PATH_ADMIN_IMPORT_WORLD, post(admin::import_world))
+        .route(PATH_WORLDS, get(maps::list_worlds))
+        .route(PATH_WORLD_LEVELS, get(maps::list_levels))
+        .route(PATH_LEVEL, get(maps::level_data))
+        .route(PATH_LEVEL_PREVIEW, get(maps::level_preview))
+        .route(PATH_TILESET, get(maps::tileset_image));
     let accessible = Router::new()
-        .route(PATH_AUTH, get(auth::auth_by_code));
+        .route(PATH_AUTH, get(auth::auth_by_code))
+        .route(PATH_HEALTHZ, get(health::healthz))
+        .route(PATH_READYZ, get(health::readyz))
+        .route(PATH_VERSION, get(health::version))
+        .route(PATH_METRICS, get(metrics::metrics));
     Router::new()
         .merge(ws)
         .merge(restricted)
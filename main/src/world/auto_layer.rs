@@ -0,0 +1,376 @@
+use std::{collections::{BTreeMap, HashSet}, fmt::Display};
+
+use serde_json::Value;
+
+use super::ldtk_json::{
+    AutoLayerRuleDefinition, AutoLayerRuleGroup, Checker, Definitions, FieldInstance, LayerDefinition, LayerInstance,
+    Level, TileInstance, TileMode, TilesetDefinition,
+};
+
+/// Pattern value matching any non-zero IntGrid value, or negated, only zero.
+const ANYTHING: i64 = 1_000_001;
+/// Pattern values above this match an IntGrid value group, `(group uid + 1) * 1000`.
+const GROUP_STEP: i64 = 1000;
+const FLIP_X: i64 = 1;
+const FLIP_Y: i64 = 2;
+
+#[derive(Debug)]
+pub enum RuleError {
+    MissingLayerDef { layer: String },
+    /// The IntGrid layer an auto-layer reads is not in the level.
+    MissingSource { layer: String },
+    MissingTileset { layer: String },
+}
+
+impl Display for RuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleError::MissingLayerDef { layer } => write!(f, "layer {} has no definition", layer),
+            RuleError::MissingSource { layer } => write!(f, "source IntGrid layer of {} is not in the level", layer),
+            RuleError::MissingTileset { layer } => write!(f, "layer {} has rules but no tileset", layer),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+/// Recomputes `auto_layer_tiles` of every layer of the level that has rules, from the
+/// current IntGrid values, as the editor does when saving.
+pub fn apply_rules(defs: &Definitions, level: &mut Level) -> Result<(), RuleError> {
+    let Some(layers) = level.layer_instances.as_mut() else {
+        return Ok(());
+    };
+    for index in 0..layers.len() {
+        let def = layer_def(defs, &layers[index])?;
+        if def.auto_rule_groups.is_empty() {
+            continue;
+        }
+        let source = match def.auto_source_layer_def_uid {
+            Some(uid) if def.layer_definition_type != "IntGrid" => layers.iter()
+                .find(|layer| layer.layer_def_uid == uid)
+                .ok_or_else(|| RuleError::MissingSource { layer: layers[index].identifier.clone() })?,
+            _ => &layers[index],
+        };
+        let tiles = rule_tiles(defs, &level.field_instances, &layers[index], source)?;
+        layers[index].auto_layer_tiles = tiles;
+    }
+    Ok(())
+}
+
+/// Tiles the rules of `layer` produce from the IntGrid values of `source`, which is the
+/// layer itself for an IntGrid layer. `level_fields` select biome rule groups.
+///
+/// Rules run group by group in definition order; a cell matched by a rule that breaks on
+/// match is skipped by the rules after it. Tiles come out in the editor's order: last rule
+/// first, then by cell. Perlin filters use this crate's own gradient noise, not the
+/// editor's, so rules with one can land on other cells than in the editor.
+pub fn rule_tiles(defs: &Definitions, level_fields: &[FieldInstance], layer: &LayerInstance,
+    source: &LayerInstance) -> Result<Vec<TileInstance>, RuleError> {
+    let def = layer_def(defs, layer)?;
+    let source_def = layer_def(defs, source)?;
+    let tileset_uid = layer.override_tileset_uid.or(layer.tileset_def_uid).or(def.tileset_def_uid);
+    let tileset = tileset_uid
+        .and_then(|uid| defs.tilesets.iter().find(|tileset| tileset.uid == uid))
+        .ok_or_else(|| RuleError::MissingTileset { layer: layer.identifier.clone() })?;
+
+    let rules: Vec<&AutoLayerRuleDefinition> = def.auto_rule_groups.iter()
+        .filter(|group| group_applies(group, def, layer, level_fields))
+        .flat_map(|group| group.rules.iter().filter(|rule| rule.active))
+        .collect();
+    let evaluator = Evaluator { layer, source, source_def, tileset };
+    // By rule then cell id, so the tiles of a rule come out sorted by cell.
+    let mut applied: Vec<BTreeMap<i64, Vec<TileInstance>>> = vec![BTreeMap::new(); rules.len()];
+    let mut done = HashSet::new();
+    for (rule, tiles) in rules.iter().zip(applied.iter_mut()) {
+        for cy in 0..layer.c_hei {
+            for cx in 0..layer.c_wid {
+                let coord = cx + cy * layer.c_wid;
+                if done.contains(&coord) || !on_modulo(rule, cx, cy) {
+                    continue;
+                }
+                let mut matched = false;
+                for flips in [0, FLIP_X, FLIP_Y, FLIP_X | FLIP_Y] {
+                    if matched && rule.break_on_match {
+                        break;
+                    }
+                    if flips & FLIP_X != 0 && !rule.flip_x || flips & FLIP_Y != 0 && !rule.flip_y {
+                        continue;
+                    }
+                    if evaluator.matches(rule, cx, cy, flips) {
+                        tiles.entry(coord).or_default().extend(evaluator.tiles(rule, cx, cy, flips));
+                        matched = true;
+                    }
+                }
+                if matched && rule.break_on_match {
+                    done.insert(coord);
+                }
+            }
+        }
+    }
+    Ok(applied.into_iter().rev().flat_map(|tiles| tiles.into_values().flatten()).collect())
+}
+
+fn layer_def<'a>(defs: &'a Definitions, layer: &LayerInstance) -> Result<&'a LayerDefinition, RuleError> {
+    defs.layers.iter()
+        .find(|def| def.uid == layer.layer_def_uid)
+        .ok_or_else(|| RuleError::MissingLayerDef { layer: layer.identifier.clone() })
+}
+
+/// Optional groups only run when enabled on the layer instance, biome groups when the
+/// level's biome field holds any, or with mode 1 all, of their values.
+fn group_applies(group: &AutoLayerRuleGroup, def: &LayerDefinition, layer: &LayerInstance,
+    level_fields: &[FieldInstance]) -> bool {
+    if !group.active || group.is_optional && !layer.optional_rules.contains(&group.uid) {
+        return false;
+    }
+    if group.required_biome_values.is_empty() {
+        return true;
+    }
+    let biomes: Vec<&str> = def.biome_field_uid
+        .and_then(|uid| level_fields.iter().find(|field| field.def_uid == uid))
+        .and_then(|field| field.value.as_ref())
+        .map(|value| match value {
+            Value::Array(values) => values.iter().filter_map(Value::as_str).collect(),
+            value => value.as_str().into_iter().collect(),
+        })
+        .unwrap_or_default();
+    let mut required = group.required_biome_values.iter();
+    if group.biome_requirement_mode == 1 {
+        required.all(|value| biomes.contains(&value.as_str()))
+    } else {
+        required.any(|value| biomes.contains(&value.as_str()))
+    }
+}
+
+/// Cell modulos and their offsets; checker modes shift every other column or row by one.
+fn on_modulo(rule: &AutoLayerRuleDefinition, cx: i64, cy: i64) -> bool {
+    let (x_modulo, y_modulo) = (rule.x_modulo.max(1), rule.y_modulo.max(1));
+    let (x, y) = (cx - rule.x_offset, cy - rule.y_offset);
+    let y_ok = match rule.checker {
+        Checker::Vertical => (y + (x / x_modulo) % 2) % y_modulo == 0,
+        _ => y % y_modulo == 0,
+    };
+    let x_ok = match rule.checker {
+        Checker::Horizontal => (x + (y / y_modulo) % 2) % x_modulo == 0,
+        _ => x % x_modulo == 0,
+    };
+    y_ok && x_ok
+}
+
+struct Evaluator<'a> {
+    layer: &'a LayerInstance,
+    source: &'a LayerInstance,
+    source_def: &'a LayerDefinition,
+    tileset: &'a TilesetDefinition,
+}
+
+impl Evaluator<'_> {
+
+    /// Whether the pattern, mirrored by `flips`, matches around a cell and the rule's
+    /// chance passes there.
+    fn matches(&self, rule: &AutoLayerRuleDefinition, cx: i64, cy: i64, flips: i64) -> bool {
+        if rule.tile_rects_ids.is_empty() {
+            return false;
+        }
+        if rule.chance <= 0.0
+            || rule.chance < 1.0 && rand_coords(self.layer.seed + rule.uid, cx, cy, 100) as f64 >= rule.chance * 100.0 {
+            return false;
+        }
+        if rule.perlin_active && perlin(rule.perlin_seed as i64, cx as f64 * rule.perlin_scale,
+            cy as f64 * rule.perlin_scale, rule.perlin_octaves as u32) < 0.0 {
+            return false;
+        }
+        let (dir_x, dir_y) = (if flips & FLIP_X != 0 { -1 } else { 1 }, if flips & FLIP_Y != 0 { -1 } else { 1 });
+        let radius = rule.size / 2;
+        for py in 0..rule.size {
+            for px in 0..rule.size {
+                let expected = rule.pattern.get((px + py * rule.size) as usize).copied().unwrap_or(0);
+                if expected == 0 {
+                    continue;
+                }
+                let Some(value) = self.value(cx + dir_x * (px - radius), cy + dir_y * (py - radius))
+                    .or(rule.out_of_bounds_value) else {
+                    return false;
+                };
+                let is_match = match expected.abs() {
+                    ANYTHING => value != 0,
+                    required if required >= GROUP_STEP => self.source_def.int_grid_values.iter()
+                        .find(|def| def.value == value)
+                        .is_some_and(|def| def.group_uid == required / GROUP_STEP - 1),
+                    required => value == required,
+                };
+                if is_match != (expected > 0) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// `None` outside of the source layer.
+    fn value(&self, cx: i64, cy: i64) -> Option<i64> {
+        if cx < 0 || cy < 0 || cx >= self.source.c_wid || cy >= self.source.c_hei {
+            return None;
+        }
+        self.source.int_grid_csv.get((cx + cy * self.source.c_wid) as usize).copied()
+    }
+
+    /// One tile, or a whole stamp placed around the cell by the rule's pivot.
+    fn tiles(&self, rule: &AutoLayerRuleDefinition, cx: i64, cy: i64, flips: i64) -> Vec<TileInstance> {
+        let seed = self.layer.seed + rule.uid + flips;
+        let tile_ids = &rule.tile_rects_ids[rand_coords(seed, cx, cy, rule.tile_rects_ids.len() as i64) as usize];
+        let grid_size = self.layer.grid_size;
+        let (sign_x, sign_y) = (if flips & FLIP_X != 0 { -1 } else { 1 }, if flips & FLIP_Y != 0 { -1 } else { 1 });
+        let random = |min: i64, max: i64, seed: i64| if min == 0 && max == 0 {
+            0
+        } else {
+            rand_coords(seed, cx, cy, max - min + 1) + min
+        };
+        let offset_x = sign_x * (rule.tile_x_offset + random(rule.tile_random_x_min, rule.tile_random_x_max, seed));
+        let offset_y = sign_y * (rule.tile_y_offset + random(rule.tile_random_y_min, rule.tile_random_y_max, seed + 1));
+
+        let tile_cell = |tile_id: i64| (tile_id % self.tileset.c_wid.max(1), tile_id / self.tileset.c_wid.max(1));
+        let (mut left, mut top, mut right, mut bottom) = (i64::MAX, i64::MAX, 0, 0);
+        for &tile_id in tile_ids {
+            let (tx, ty) = tile_cell(tile_id);
+            (left, top, right, bottom) = (left.min(tx), top.min(ty), right.max(tx), bottom.max(ty));
+        }
+        tile_ids.iter()
+            .map(|&tile_id| {
+                let (tx, ty) = tile_cell(tile_id);
+                let (stamp_x, stamp_y) = match rule.tile_mode {
+                    TileMode::Single => (0, 0),
+                    TileMode::Stamp => (
+                        sign_x * (((tx - left) as f64 - rule.pivot_x * (right - left) as f64) * grid_size as f64) as i64,
+                        sign_y * (((ty - top) as f64 - rule.pivot_y * (bottom - top) as f64) * grid_size as f64) as i64,
+                    ),
+                };
+                let step = self.tileset.tile_grid_size + self.tileset.spacing;
+                TileInstance {
+                    a: rule.alpha,
+                    d: vec![rule.uid, cx + cy * self.layer.c_wid],
+                    f: flips,
+                    px: vec![cx * grid_size + stamp_x + offset_x, cy * grid_size + stamp_y + offset_y],
+                    src: vec![self.tileset.padding + tx * step, self.tileset.padding + ty * step],
+                    t: tile_id,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Fractal gradient noise in `-1.0..=1.0`: octaves of doubling frequency and halving
+/// amplitude, normalized by the total amplitude. Like the editor, a rule only applies where
+/// it is not negative. The editor uses Heaps' `hxd.Perlin`, which this is not a port of:
+/// the filtered areas differ, see the ignored `reproduces_the_editor_tiles` test.
+fn perlin(seed: i64, x: f64, y: f64, octaves: u32) -> f64 {
+    let (mut total, mut amplitude, mut max) = (0.0, 1.0, 0.0);
+    let (mut x, mut y) = (x, y);
+    for octave in 0..octaves.max(1) {
+        total += gradient_noise(seed + octave as i64, x, y) * amplitude;
+        max += amplitude;
+        amplitude *= 0.5;
+        x *= 2.0;
+        y *= 2.0;
+    }
+    // Gradient noise of unit gradients stays within ±√2/2.
+    (total / max * std::f64::consts::SQRT_2).clamp(-1.0, 1.0)
+}
+
+/// Perlin noise with a pseudo-random unit gradient at each integer point, 0 on them.
+fn gradient_noise(seed: i64, x: f64, y: f64) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let corner = |dx: f64, dy: f64| {
+        let angle = rand_coords(seed, (x0 + dx) as i64, (y0 + dy) as i64, 65_536) as f64
+            * std::f64::consts::TAU / 65_536.0;
+        angle.cos() * (fx - dx) + angle.sin() * (fy - dy)
+    };
+    let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+    let (u, v) = (fade(fx), fade(fy));
+    lerp(lerp(corner(0.0, 0.0), corner(1.0, 0.0), u), lerp(corner(0.0, 1.0), corner(1.0, 1.0), u), v)
+}
+
+/// The editor's coordinate hash, in `0..max`. It runs on JavaScript numbers, so the
+/// products are floats that only get cut to 32 bits by the bitwise operators; this
+/// repeats that exactly so seeds give the editor's tiles.
+fn rand_coords(seed: i64, cx: i64, cy: i64, max: i64) -> i64 {
+    let h = to_int32((seed + cx * 374_761_393 + cy * 668_265_263) as f64);
+    let h = to_int32((h ^ (h >> 13)) as f64 * 1_274_126_177.0);
+    // Never negative: the sign bit cancels out in the xor.
+    ((h ^ (h >> 16)) as i64) % max.max(1)
+}
+
+/// JavaScript's ToInt32 for integral floats.
+fn to_int32(value: f64) -> i32 {
+    value as i128 as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{test_util, Project};
+
+    fn sample_project() -> super::super::ldtk_json::LdtkJson {
+        Project::load(&test_util::sample_project()).unwrap().into_json()
+    }
+
+    /// Rule uid and cell of each tile, with the tile itself as JSON.
+    fn summary(tiles: &[TileInstance]) -> Vec<(i64, i64, Value)> {
+        tiles.iter().map(|tile| (tile.d[0], tile.d[1], serde_json::to_value(tile).unwrap())).collect()
+    }
+
+    /// Runs the rules of every level of the sample and compares each layer accepted by
+    /// `compared` with the tiles the editor saved. Returns how many tiles were compared.
+    fn compare_with_editor(compared: impl Fn(&LayerDefinition) -> bool) -> usize {
+        let project = sample_project();
+        let mut count = 0;
+        for original in &project.levels {
+            let mut level = original.clone();
+            apply_rules(&project.defs, &mut level).unwrap();
+            for (stored, computed) in original.layer_instances.iter().flatten().zip(level.layer_instances.iter().flatten()) {
+                if !compared(layer_def(&project.defs, stored).unwrap()) {
+                    continue;
+                }
+                assert_eq!(summary(&computed.auto_layer_tiles), summary(&stored.auto_layer_tiles),
+                    "level {} layer {}", original.identifier, stored.identifier);
+                count += stored.auto_layer_tiles.len();
+            }
+        }
+        count
+    }
+
+    fn has_perlin_rules(def: &LayerDefinition) -> bool {
+        def.auto_rule_groups.iter().flat_map(|group| &group.rules).any(|rule| rule.perlin_active)
+    }
+
+    #[test]
+    fn reproduces_the_editor_tiles_of_layers_without_perlin_filters() {
+        assert!(compare_with_editor(|def| !has_perlin_rules(def)) > 0);
+    }
+
+    /// The Collisions layer of the sample has a rule with a Perlin filter. `perlin` is not
+    /// Heaps' `hxd.Perlin`, so the filter keeps other cells than the editor's and this
+    /// fails until it is ported.
+    #[test]
+    #[ignore = "needs a port of hxd.Perlin to match the editor's Perlin-filtered tiles"]
+    fn reproduces_the_editor_tiles() {
+        assert!(sample_project().defs.layers.iter().any(has_perlin_rules));
+        assert!(compare_with_editor(|_| true) > 0);
+    }
+
+    #[test]
+    fn perlin_noise_is_seeded_and_bounded() {
+        let samples = |seed: i64| -> Vec<f64> {
+            (0..20).flat_map(|cy| (0..20).map(move |cx| perlin(seed, cx as f64 * 0.2, cy as f64 * 0.2, 2))).collect()
+        };
+        let noise = samples(8_801_278);
+        assert_eq!(noise, samples(8_801_278));
+        assert_ne!(noise, samples(1));
+        assert!(noise.iter().all(|value| (-1.0..=1.0).contains(value)));
+        // Both signs occur, so a filter keeps some cells and rejects others.
+        assert!(noise.iter().any(|&value| value < 0.0) && noise.iter().any(|&value| value > 0.0));
+        assert_eq!(perlin(3, 2.0, 5.0, 1), 0.0);
+    }
+}
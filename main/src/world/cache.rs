@@ -0,0 +1,482 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::config::GameConfig;
+
+use super::{
+    collision::CollisionMap,
+    geom::Size,
+    model::{Definitions, Level, WorldLayout},
+    tiled, validate, load_worlds, worlds_from_project, LoadError, Project, Severity, World, WorldError,
+};
+
+/// Start of every cache file.
+const MAGIC: &[u8; 4] = b"LRWC";
+/// Bump whenever a cached type changes shape, so older caches are rebuilt instead of misread.
+pub const CACHE_VERSION: u32 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 4 + 32;
+const CACHE_EXTENSION: &str = "bin";
+pub(super) const LEVEL_EXTENSION: &str = "ldtkl";
+
+type Key = [u8; 32];
+
+/// The worlds of a project with the collision grids of their levels, as the game runs them.
+#[derive(Debug, Clone)]
+pub struct CompiledProject {
+    pub worlds: Vec<World>,
+    /// By level iid.
+    pub collisions: HashMap<String, CollisionMap>,
+}
+
+#[derive(Debug)]
+pub enum CacheError {
+    Io(io::Error),
+    Load(LoadError),
+    /// Validation errors of the project.
+    Invalid(Vec<String>),
+    World(WorldError),
+    Encode(postcard::Error),
+}
+
+impl Display for CacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::Io(err) => write!(f, "{}", err),
+            CacheError::Load(err) => write!(f, "{}", err),
+            CacheError::Invalid(errors) => write!(f, "invalid map:\n{}", errors.join("\n")),
+            CacheError::World(err) => write!(f, "{}", err),
+            CacheError::Encode(err) => write!(f, "cannot encode world cache: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+impl From<io::Error> for CacheError {
+    fn from(err: io::Error) -> Self {
+        CacheError::Io(err)
+    }
+}
+
+impl From<LoadError> for CacheError {
+    fn from(err: LoadError) -> Self {
+        CacheError::Load(err)
+    }
+}
+
+impl From<WorldError> for CacheError {
+    fn from(err: WorldError) -> Self {
+        CacheError::World(err)
+    }
+}
+
+/// Worlds share their definitions, which are stored once.
+#[derive(Deserialize)]
+struct Payload {
+    defs: Definitions,
+    worlds: Vec<CachedWorld>,
+    collisions: HashMap<String, CollisionMap>,
+}
+
+#[derive(Deserialize)]
+struct CachedWorld {
+    iid: String,
+    identifier: String,
+    layout: WorldLayout,
+    grid_size: Size,
+    levels: Vec<Level>,
+}
+
+#[derive(Serialize)]
+struct PayloadRef<'a> {
+    defs: &'a Definitions,
+    worlds: Vec<CachedWorldRef<'a>>,
+    collisions: &'a HashMap<String, CollisionMap>,
+}
+
+#[derive(Serialize)]
+struct CachedWorldRef<'a> {
+    iid: &'a str,
+    identifier: &'a str,
+    layout: WorldLayout,
+    grid_size: Size,
+    levels: &'a [Level],
+}
+
+/// Loads a project from its cache in `game.world_cache_dir` when the cache matches the
+/// current sources and settings and the tileset images are still there. Otherwise parses,
+/// validates and compiles the JSON, then writes the cache for next time. Validation
+/// warnings are only logged on a rebuild.
+pub fn load(path: &Path, config: &GameConfig) -> Result<CompiledProject, CacheError> {
+    if !config.world_cache {
+        return compile(path, config);
+    }
+    let key = source_key(path, config)?;
+    let cache = cache_path(&config.world_cache_dir, path, &key);
+    match read(&cache, &key) {
+        Ok(Some(compiled)) if tileset_images_exist(path, &compiled) => {
+            tracing::info!("Loaded {} from {}", path.display(), cache.display());
+            return Ok(compiled);
+        }
+        // A missing image fails validation, which the rebuild reports.
+        Ok(_) => {}
+        Err(err) => tracing::warn!("Ignoring world cache {}: {}", cache.display(), err),
+    }
+
+    let compiled = compile(path, config)?;
+    match write(&cache, &key, &compiled) {
+        Ok(()) => tracing::info!("Cached {} to {}", path.display(), cache.display()),
+        Err(err) => tracing::warn!("Cannot write world cache {}: {}", cache.display(), err),
+    }
+    Ok(compiled)
+}
+
+/// Writes the cache of a project unless an up to date one exists, or always with `force`.
+/// Returns the cache file and whether it was written.
+pub fn prebuild(path: &Path, config: &GameConfig, force: bool) -> Result<(PathBuf, bool), CacheError> {
+    let key = source_key(path, config)?;
+    let cache = cache_path(&config.world_cache_dir, path, &key);
+    if !force && header_matches(&cache, &key) {
+        return Ok((cache, false));
+    }
+    write(&cache, &key, &compile(path, config)?)?;
+    Ok((cache, true))
+}
+
+/// Parses and validates an LDtk project or a Tiled map, then builds its runtime model.
+pub fn compile(path: &Path, config: &GameConfig) -> Result<CompiledProject, CacheError> {
+    let (worlds, issues) = if tiled::is_tiled_map(path) {
+        let worlds = load_worlds(path)?;
+        let issues = validate::validate_worlds(path, &worlds, &config.required_layers);
+        (worlds, issues)
+    } else {
+        let project = Project::load(path)?;
+        let issues = validate::validate(&project, &config.required_layers);
+        // Models are only built from valid projects.
+        (if validate::has_errors(&issues) { Vec::new() } else { worlds_from_project(&project)? }, issues)
+    };
+    let mut errors = Vec::new();
+    for issue in issues {
+        match issue.severity {
+            Severity::Warning => tracing::warn!("{}", issue),
+            Severity::Error => errors.push(issue.to_string()),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(CacheError::Invalid(errors));
+    }
+
+    let collisions = worlds.iter()
+        .flat_map(|world| world.levels.iter().map(|level| {
+            (level.iid.clone(), CollisionMap::from_level(level, &world.defs, &config.collision))
+        }))
+        .collect();
+    Ok(CompiledProject { worlds, collisions })
+}
+
+/// Hash of the project file, its external level or tileset files and the settings the
+/// compiled model depends on. Tileset images are not part of it: they are only checked
+/// to exist, see [`tileset_images_exist`].
+fn source_key(path: &Path, config: &GameConfig) -> Result<Key, CacheError> {
+    let mut hasher = Sha256::new();
+    hash_file(&mut hasher, path)?;
+    if tiled::is_tiled_map(path) {
+        for tileset in tiled::dependencies(path).map_err(WorldError::from)? {
+            hash_file(&mut hasher, &tileset)?;
+        }
+    }
+
+    // LDtk saves external levels next to the project, in a directory named after it.
+    let levels_dir = path.with_extension("");
+    if levels_dir.is_dir() {
+        let mut levels: Vec<PathBuf> = fs::read_dir(&levels_dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<_>>()?;
+        levels.retain(|level| level.extension().is_some_and(|ext| ext == LEVEL_EXTENSION));
+        levels.sort();
+        for level in levels {
+            hasher.update(level.file_name().unwrap_or_default().as_encoded_bytes());
+            hash_file(&mut hasher, &level)?;
+        }
+    }
+
+    let collision = &config.collision;
+    let mut terrain: Vec<String> = collision.terrain.iter()
+        .map(|(value, terrain)| format!("{}={:?}", value, terrain))
+        .collect();
+    terrain.sort();
+    hasher.update(format!("{:?} {:?} {:?} {:?} {} {}", config.required_layers, collision.layers, terrain,
+        collision.unmapped, collision.slow_factor, collision.default_grid_size));
+    Ok(hasher.finalize().into())
+}
+
+fn hash_file(hasher: &mut Sha256, path: &Path) -> io::Result<()> {
+    let data = fs::read(path)?;
+    hasher.update((data.len() as u64).to_le_bytes());
+    hasher.update(&data);
+    Ok(())
+}
+
+/// The existence checks of validation on tileset images, the only sources outside the key.
+fn tileset_images_exist(path: &Path, compiled: &CompiledProject) -> bool {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    compiled.worlds.iter()
+        .flat_map(|world| world.defs.tilesets.values())
+        .filter_map(|tileset| tileset.rel_path.as_deref())
+        .all(|rel_path| dir.join(rel_path).is_file())
+}
+
+/// `<project>-<key>.bin`, where `<project>` hashes the project path so the caches of
+/// one project can be told apart from other projects' when old ones are removed.
+fn cache_path(dir: &Path, project: &Path, key: &Key) -> PathBuf {
+    let project = fs::canonicalize(project).unwrap_or_else(|_| project.to_owned());
+    let project = Sha256::digest(project.as_os_str().as_encoded_bytes());
+    dir.join(format!("{}-{}", hex(&project[..8]), hex(key))).with_extension(CACHE_EXTENSION)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Removes the other caches of the project `cache` belongs to, left by earlier sources,
+/// and caches named by key alone, which no version reads anymore.
+fn remove_stale(cache: &Path) -> io::Result<()> {
+    let (Some(dir), Some(name)) = (cache.parent(), cache.file_name().and_then(|name| name.to_str())) else {
+        return Ok(());
+    };
+    let Some((project, _)) = name.split_once('-') else {
+        return Ok(());
+    };
+    for entry in fs::read_dir(dir)? {
+        let other = entry?.path();
+        let stale = other != cache
+            && other.extension().is_some_and(|ext| ext == CACHE_EXTENSION)
+            && other.file_stem().and_then(|name| name.to_str())
+                .is_some_and(|name| !name.contains('-') || name.strip_prefix(project).is_some_and(|rest| rest.starts_with('-')));
+        if stale {
+            fs::remove_file(&other)?;
+        }
+    }
+    Ok(())
+}
+
+fn header(key: &Key) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+    header.extend_from_slice(key);
+    header
+}
+
+fn header_matches(path: &Path, key: &Key) -> bool {
+    let mut found = [0; HEADER_LEN];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut found))
+        .is_ok_and(|()| found[..] == header(key)[..])
+}
+
+/// `None` when there is no cache for `key` or it was written by another version.
+fn read(path: &Path, key: &Key) -> Result<Option<CompiledProject>, CacheError> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    if data.len() < HEADER_LEN || data[..HEADER_LEN] != header(key)[..] {
+        return Ok(None);
+    }
+    let payload: Payload = postcard::from_bytes(&data[HEADER_LEN..]).map_err(CacheError::Encode)?;
+    let defs = Arc::new(payload.defs);
+    let worlds = payload.worlds.into_iter()
+        .map(|world| World::new(world.iid, world.identifier, world.layout, world.grid_size, world.levels, defs.clone()))
+        .collect();
+    Ok(Some(CompiledProject {
+        worlds,
+        collisions: payload.collisions,
+    }))
+}
+
+fn write(path: &Path, key: &Key, compiled: &CompiledProject) -> Result<(), CacheError> {
+    let empty = Definitions::default();
+    let payload = PayloadRef {
+        defs: compiled.worlds.first().map(|world| world.defs.as_ref()).unwrap_or(&empty),
+        worlds: compiled.worlds.iter()
+            .map(|world| CachedWorldRef {
+                iid: &world.iid,
+                identifier: &world.identifier,
+                layout: world.layout,
+                grid_size: world.grid_size,
+                levels: &world.levels,
+            })
+            .collect(),
+        collisions: &compiled.collisions,
+    };
+    let mut data = header(key);
+    data.extend(postcard::to_stdvec(&payload).map_err(CacheError::Encode)?);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Written aside first so a crash never leaves a truncated cache behind.
+    let partial = path.with_extension("partial");
+    fs::write(&partial, &data)?;
+    fs::rename(&partial, path)?;
+    if let Err(err) = remove_stale(path) {
+        tracing::warn!("Cannot remove old world caches next to {}: {}", path.display(), err);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::world::{
+        collision::Terrain,
+        test_util::{copy_sample_project, sample_project, TempDir},
+    };
+
+    fn json(value: &impl Serialize) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn compiled_projects_round_trip() {
+        let dir = TempDir::new("cache-round-trip");
+        let config = GameConfig::default();
+        let compiled = compile(&sample_project(), &config).unwrap();
+        let key = source_key(&sample_project(), &config).unwrap();
+        let path = cache_path(&dir, &sample_project(), &key);
+        write(&path, &key, &compiled).unwrap();
+
+        let cached = read(&path, &key).unwrap().expect("cache matches its key");
+        assert_eq!(cached.worlds.len(), compiled.worlds.len());
+        for (cached, compiled) in cached.worlds.iter().zip(&compiled.worlds) {
+            assert_eq!((&cached.iid, &cached.identifier, cached.layout, cached.grid_size),
+                (&compiled.iid, &compiled.identifier, compiled.layout, compiled.grid_size));
+            assert_eq!(json(&cached.levels), json(&compiled.levels));
+            assert_eq!(json(cached.defs.as_ref()), json(compiled.defs.as_ref()));
+            for level in &compiled.levels {
+                assert!(cached.level(&level.iid).is_some(), "level index is rebuilt");
+            }
+        }
+        assert_eq!(json(&cached.collisions), json(&compiled.collisions));
+    }
+
+    #[test]
+    fn mismatched_or_damaged_caches_are_not_read() {
+        let dir = TempDir::new("cache-damaged");
+        let config = GameConfig::default();
+        let key = source_key(&sample_project(), &config).unwrap();
+        let path = cache_path(&dir, &sample_project(), &key);
+        assert!(read(&path, &key).unwrap().is_none(), "missing cache");
+
+        write(&path, &key, &compile(&sample_project(), &config).unwrap()).unwrap();
+        assert!(header_matches(&path, &key));
+        let other_key = [0; 32];
+        assert!(!header_matches(&path, &other_key));
+        assert!(read(&path, &other_key).unwrap().is_none());
+
+        let mut data = fs::read(&path).unwrap();
+        data[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(CACHE_VERSION + 1).to_le_bytes());
+        fs::write(&path, &data).unwrap();
+        assert!(read(&path, &key).unwrap().is_none(), "other cache version");
+
+        let mut data = header(&key);
+        data.extend_from_slice(&[0xff; 16]);
+        fs::write(&path, &data).unwrap();
+        assert!(matches!(read(&path, &key), Err(CacheError::Encode(_))));
+    }
+
+    #[test]
+    fn key_follows_the_sources_and_settings() {
+        let config = GameConfig::default();
+        let key = source_key(&sample_project(), &config).unwrap();
+        assert_eq!(source_key(&sample_project(), &config).unwrap(), key);
+
+        let mut slower = config.clone();
+        slower.collision.slow_factor = 0.25;
+        assert_ne!(source_key(&sample_project(), &slower).unwrap(), key);
+        let mut water = config.clone();
+        water.collision.terrain.insert("walls".to_owned(), Terrain::Water);
+        assert_ne!(source_key(&sample_project(), &water).unwrap(), key);
+
+        let dir = TempDir::new("cache-key");
+        fs::create_dir_all(&dir).unwrap();
+        let project = dir.join("map1.ldtk");
+        fs::copy(sample_project(), &project).unwrap();
+        let copied = source_key(&project, &config).unwrap();
+        assert_eq!(copied, key, "the key depends on contents, not on paths");
+        fs::create_dir_all(dir.join("map1")).unwrap();
+        fs::write(dir.join("map1/Level_0.ldtkl"), "{}").unwrap();
+        assert_ne!(source_key(&project, &config).unwrap(), copied);
+    }
+
+    #[test]
+    fn load_writes_the_cache_once() {
+        let dir = TempDir::new("cache-load");
+        let config = GameConfig { world_cache_dir: dir.to_path_buf(), ..GameConfig::default() };
+        let first = load(&sample_project(), &config).unwrap();
+        let (path, written) = prebuild(&sample_project(), &config, false).unwrap();
+        assert!(!written, "load already wrote an up to date cache");
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+
+        let second = load(&sample_project(), &config).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+        assert_eq!(json(&second.collisions), json(&first.collisions));
+        assert!(prebuild(&sample_project(), &config, true).unwrap().1);
+    }
+
+    #[test]
+    fn missing_tileset_images_bypass_the_cache() {
+        let dir = TempDir::new("cache-images");
+        let project = copy_sample_project(&dir.join("project"));
+        let config = GameConfig { world_cache_dir: dir.join("cache"), ..GameConfig::default() };
+        load(&project, &config).unwrap();
+
+        fs::remove_file(dir.join("project/TopDown_by_deepnight.png")).unwrap();
+        match load(&project, &config) {
+            Err(CacheError::Invalid(errors)) => assert!(errors.iter().any(|error| error.contains("does not exist"))),
+            other => panic!("expected a validation error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn writing_a_cache_removes_the_older_ones_of_its_project() {
+        let dir = TempDir::new("cache-stale");
+        let project = copy_sample_project(&dir.join("project"));
+        let other = copy_sample_project(&dir.join("other"));
+        let config = GameConfig { world_cache_dir: dir.join("cache"), ..GameConfig::default() };
+        let caches = || {
+            let mut caches: Vec<PathBuf> = fs::read_dir(&config.world_cache_dir).unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            caches.sort();
+            caches
+        };
+        let (other_cache, _) = prebuild(&other, &config, false).unwrap();
+        let (old, _) = prebuild(&project, &config, false).unwrap();
+        assert_ne!(old, other_cache, "projects with the same contents get their own cache");
+        assert_eq!(caches().len(), 2);
+
+        let mut json: Value = serde_json::from_str(&fs::read_to_string(&project).unwrap()).unwrap();
+        json["bgColor"] = Value::from("#000000");
+        fs::write(&project, json.to_string()).unwrap();
+        load(&project, &config).unwrap();
+        let (new, written) = prebuild(&project, &config, false).unwrap();
+        assert!(!written);
+        assert_ne!(new, old);
+        let mut expected = vec![other_cache, new];
+        expected.sort();
+        assert_eq!(caches(), expected);
+    }
+}
@@ -0,0 +1,479 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::CollisionConfig;
+
+use super::{
+    geom::{Aabb, Cell, Point, Size, Vec2},
+    model::{Definitions, Layer, Level},
+};
+
+const EPSILON: f32 = 1e-3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Terrain {
+    #[default]
+    Floor,
+    Slow,
+    Water,
+    Solid,
+}
+
+impl Terrain {
+    pub fn is_walkable(self) -> bool {
+        matches!(self, Terrain::Floor | Terrain::Slow)
+    }
+
+    /// Used to merge several collision layers: the most restrictive terrain wins.
+    fn severity(self) -> u8 {
+        match self {
+            Terrain::Floor => 0,
+            Terrain::Slow => 1,
+            Terrain::Water => 2,
+            Terrain::Solid => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveResult {
+    pub pos: Vec2,
+    pub blocked_x: bool,
+    pub blocked_y: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub cell: Cell,
+    pub point: Vec2,
+    pub distance: f32,
+    pub terrain: Terrain,
+}
+
+/// Walkability grid of a level, built from its collision IntGrid layers.
+/// Cells outside the grid are solid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollisionMap {
+    grid_size: i32,
+    offset: Point,
+    size: Size,
+    cells: Vec<Terrain>,
+    slow_factor: f32,
+}
+
+impl CollisionMap {
+
+    pub fn from_level(level: &Level, defs: &Definitions, config: &CollisionConfig) -> Self {
+        let layers: Vec<&Layer> = config.layers.iter()
+            .filter_map(|identifier| level.layer(identifier))
+            .collect();
+        let Some(base) = layers.first() else {
+            let grid_size = level.layers.first().map(|layer| layer.grid_size).unwrap_or(config.default_grid_size);
+            let size = Size::new(
+                (level.size.w + grid_size - 1) / grid_size,
+                (level.size.h + grid_size - 1) / grid_size);
+            return Self::new(grid_size, Point::default(), size, vec![Terrain::Floor; (size.w * size.h) as usize],
+                config.slow_factor);
+        };
+
+        let mut map = Self::new(base.grid_size, base.px_offset, base.grid,
+            vec![Terrain::Floor; (base.grid.w * base.grid.h) as usize], config.slow_factor);
+        for layer in layers {
+            let terrains = terrain_lookup(layer, defs, config);
+            for cy in 0..map.size.h {
+                for cx in 0..map.size.w {
+                    let cell = Cell::new(cx, cy);
+                    let center = map.cell_center(cell);
+                    let value = layer.int_value(layer.cell_at(Point::new(center.x as i32, center.y as i32)))
+                        .unwrap_or(0);
+                    if value == 0 {
+                        continue;
+                    }
+                    let terrain = terrains.get(&value).copied().unwrap_or(config.unmapped);
+                    let index = map.index(cell);
+                    if terrain.severity() > map.cells[index].severity() {
+                        map.cells[index] = terrain;
+                    }
+                }
+            }
+        }
+        map
+    }
+
+    pub fn new(grid_size: i32, offset: Point, size: Size, cells: Vec<Terrain>, slow_factor: f32) -> Self {
+        assert_eq!(cells.len(), (size.w * size.h) as usize, "cells must cover the whole grid");
+        Self {
+            grid_size,
+            offset,
+            size,
+            cells,
+            slow_factor,
+        }
+    }
+
+    pub fn grid_size(&self) -> i32 {
+        self.grid_size
+    }
+
+    pub fn offset(&self) -> Point {
+        self.offset
+    }
+
+    /// Size of the grid in cells.
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn contains(&self, cell: Cell) -> bool {
+        cell.cx >= 0 && cell.cy >= 0 && cell.cx < self.size.w && cell.cy < self.size.h
+    }
+
+    pub fn terrain(&self, cell: Cell) -> Terrain {
+        if self.contains(cell) {
+            self.cells[self.index(cell)]
+        } else {
+            Terrain::Solid
+        }
+    }
+
+    /// Paths cached by a [`super::pathfinding::Pathfinder`] over this map go stale;
+    /// `GameSession::set_terrain` clears them.
+    pub fn set_terrain(&mut self, cell: Cell, terrain: Terrain) {
+        if self.contains(cell) {
+            let index = self.index(cell);
+            self.cells[index] = terrain;
+        }
+    }
+
+    pub fn is_walkable(&self, cell: Cell) -> bool {
+        self.terrain(cell).is_walkable()
+    }
+
+    pub fn cell_at(&self, point: Vec2) -> Cell {
+        Cell::new(self.column(point.x), self.row(point.y))
+    }
+
+    pub fn cell_center(&self, cell: Cell) -> Vec2 {
+        let half = self.grid_size as f32 / 2.0;
+        Vec2::new(
+            (cell.cx * self.grid_size + self.offset.x) as f32 + half,
+            (cell.cy * self.grid_size + self.offset.y) as f32 + half)
+    }
+
+    /// Movement speed multiplier at a level-local position.
+    pub fn speed_factor(&self, point: Vec2) -> f32 {
+        match self.terrain(self.cell_at(point)) {
+            Terrain::Slow => self.slow_factor,
+            _ => 1.0,
+        }
+    }
+
+    /// Moves a box by `delta`, X axis first, then Y. A blocked axis stops at
+    /// the wall while the other keeps moving, so the box slides along walls.
+    pub fn move_box(&self, aabb: Aabb, delta: Vec2) -> MoveResult {
+        self.move_box_by(aabb, delta, |cell| self.is_walkable(cell))
+    }
+
+    /// Like [`Self::move_box`], with walkability decided by `walkable`, for
+    /// example to let cells outside the grid come from a neighbour level.
+    pub fn move_box_by(&self, aabb: Aabb, delta: Vec2, walkable: impl Fn(Cell) -> bool) -> MoveResult {
+        let mut moved = aabb;
+        let (x, blocked_x) = self.sweep_x(&moved, delta.x, &walkable);
+        moved.x = x;
+        let (y, blocked_y) = self.sweep_y(&moved, delta.y, &walkable);
+        moved.y = y;
+        MoveResult {
+            pos: moved.pos(),
+            blocked_x,
+            blocked_y,
+        }
+    }
+
+    /// Returns the first non-walkable cell crossed by the segment.
+    pub fn ray_cast(&self, from: Vec2, to: Vec2) -> Option<RayHit> {
+        self.ray_cast_by(from, to, |terrain| !terrain.is_walkable())
+    }
+
+    /// Walks the cells crossed by the segment in order and returns the first
+    /// one for which `blocks` is true.
+    pub fn ray_cast_by(&self, from: Vec2, to: Vec2, blocks: impl Fn(Terrain) -> bool) -> Option<RayHit> {
+        self.ray_cast_cells(from, to, |cell| blocks(self.terrain(cell)))
+    }
+
+    /// Like [`Self::ray_cast_by`], with the decision made per cell rather than per terrain.
+    pub fn ray_cast_cells(&self, from: Vec2, to: Vec2, blocks: impl Fn(Cell) -> bool) -> Option<RayHit> {
+        let grid_size = self.grid_size as f32;
+        let start = Vec2::new((from.x - self.offset.x as f32) / grid_size, (from.y - self.offset.y as f32) / grid_size);
+        let end = Vec2::new((to.x - self.offset.x as f32) / grid_size, (to.y - self.offset.y as f32) / grid_size);
+        let (dx, dy) = (end.x - start.x, end.y - start.y);
+
+        let mut cell = Cell::new(start.x.floor() as i32, start.y.floor() as i32);
+        let end_cell = Cell::new(end.x.floor() as i32, end.y.floor() as i32);
+        let step_x = if dx > 0.0 { 1 } else { -1 };
+        let step_y = if dy > 0.0 { 1 } else { -1 };
+        let t_delta_x = if dx != 0.0 { 1.0 / dx.abs() } else { f32::INFINITY };
+        let t_delta_y = if dy != 0.0 { 1.0 / dy.abs() } else { f32::INFINITY };
+        let mut t_max_x = if dx > 0.0 {
+            (cell.cx as f32 + 1.0 - start.x) / dx
+        } else if dx < 0.0 {
+            (start.x - cell.cx as f32) / -dx
+        } else {
+            f32::INFINITY
+        };
+        let mut t_max_y = if dy > 0.0 {
+            (cell.cy as f32 + 1.0 - start.y) / dy
+        } else if dy < 0.0 {
+            (start.y - cell.cy as f32) / -dy
+        } else {
+            f32::INFINITY
+        };
+
+        let mut t = 0.0f32;
+        loop {
+            if blocks(cell) {
+                let point = Vec2::new(from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t);
+                return Some(RayHit {
+                    cell,
+                    point,
+                    distance: from.distance(point),
+                    terrain: self.terrain(cell),
+                });
+            }
+            if cell == end_cell {
+                return None;
+            }
+            if t_max_x < t_max_y {
+                t = t_max_x;
+                t_max_x += t_delta_x;
+                cell.cx += step_x;
+            } else {
+                t = t_max_y;
+                t_max_y += t_delta_y;
+                cell.cy += step_y;
+            }
+            if t > 1.0 {
+                return None;
+            }
+        }
+    }
+
+    fn sweep_x(&self, aabb: &Aabb, dx: f32, walkable: &impl Fn(Cell) -> bool) -> (f32, bool) {
+        if dx == 0.0 {
+            return (aabb.x, false);
+        }
+        let (first_row, last_row) = (self.row(aabb.y), self.row(aabb.y + aabb.h - EPSILON));
+        let blocked = |column: i32| (first_row..=last_row).any(|row| !walkable(Cell::new(column, row)));
+        if dx > 0.0 {
+            let from = self.column(aabb.x + aabb.w - EPSILON);
+            let to = self.column(aabb.x + aabb.w + dx - EPSILON);
+            if let Some(column) = (from + 1..=to).find(|&column| blocked(column)) {
+                return (self.column_x(column) - aabb.w, true);
+            }
+        } else {
+            let from = self.column(aabb.x);
+            let to = self.column(aabb.x + dx);
+            if let Some(column) = (to..from).rev().find(|&column| blocked(column)) {
+                return (self.column_x(column + 1), true);
+            }
+        }
+        (aabb.x + dx, false)
+    }
+
+    fn sweep_y(&self, aabb: &Aabb, dy: f32, walkable: &impl Fn(Cell) -> bool) -> (f32, bool) {
+        if dy == 0.0 {
+            return (aabb.y, false);
+        }
+        let (first_column, last_column) = (self.column(aabb.x), self.column(aabb.x + aabb.w - EPSILON));
+        let blocked = |row: i32| (first_column..=last_column).any(|column| !walkable(Cell::new(column, row)));
+        if dy > 0.0 {
+            let from = self.row(aabb.y + aabb.h - EPSILON);
+            let to = self.row(aabb.y + aabb.h + dy - EPSILON);
+            if let Some(row) = (from + 1..=to).find(|&row| blocked(row)) {
+                return (self.row_y(row) - aabb.h, true);
+            }
+        } else {
+            let from = self.row(aabb.y);
+            let to = self.row(aabb.y + dy);
+            if let Some(row) = (to..from).rev().find(|&row| blocked(row)) {
+                return (self.row_y(row + 1), true);
+            }
+        }
+        (aabb.y + dy, false)
+    }
+
+    fn column(&self, x: f32) -> i32 {
+        ((x - self.offset.x as f32) / self.grid_size as f32).floor() as i32
+    }
+
+    fn row(&self, y: f32) -> i32 {
+        ((y - self.offset.y as f32) / self.grid_size as f32).floor() as i32
+    }
+
+    fn column_x(&self, column: i32) -> f32 {
+        (column * self.grid_size + self.offset.x) as f32
+    }
+
+    fn row_y(&self, row: i32) -> f32 {
+        (row * self.grid_size + self.offset.y) as f32
+    }
+
+    fn index(&self, cell: Cell) -> usize {
+        (cell.cy * self.size.w + cell.cx) as usize
+    }
+}
+
+/// Maps IntGrid values of a layer to terrains. Config keys are either the
+/// numeric value or the value identifier set in LDtk.
+fn terrain_lookup(layer: &Layer, defs: &Definitions, config: &CollisionConfig) -> HashMap<i32, Terrain> {
+    let Some(layer_def) = defs.layers.get(&layer.def_uid) else {
+        return HashMap::new();
+    };
+    layer_def.int_grid_values.iter()
+        .filter_map(|value| {
+            config.terrain.get(&value.value.to_string())
+                .or_else(|| value.identifier.as_ref().and_then(|identifier| config.terrain.get(identifier)))
+                .map(|&terrain| (value.value, terrain))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a 16px grid from rows of `.` (floor), `~` (slow) and `#` (solid).
+    fn map(rows: &[&str]) -> CollisionMap {
+        let cells: Vec<Terrain> = rows.iter()
+            .flat_map(|row| row.chars())
+            .map(|c| match c {
+                '#' => Terrain::Solid,
+                '~' => Terrain::Slow,
+                _ => Terrain::Floor,
+            })
+            .collect();
+        let size = Size::new(rows[0].len() as i32, rows.len() as i32);
+        CollisionMap::new(16, Point::default(), size, cells, 0.5)
+    }
+
+    #[test]
+    fn slides_along_a_wall() {
+        let map = map(&[
+            "...#.",
+            "...#.",
+            "...#.",
+            "...#.",
+        ]);
+        let result = map.move_box(Aabb::new(16.0, 16.0, 12.0, 12.0), Vec2::new(40.0, 10.0));
+        assert_eq!(result, MoveResult { pos: Vec2::new(36.0, 26.0), blocked_x: true, blocked_y: false });
+    }
+
+    #[test]
+    fn corner_hit_stops_only_the_blocked_axis() {
+        let map = map(&[
+            "....",
+            "....",
+            "..#.",
+            "....",
+        ]);
+        let result = map.move_box(Aabb::new(10.0, 10.0, 12.0, 12.0), Vec2::new(20.0, 20.0));
+        assert_eq!(result, MoveResult { pos: Vec2::new(30.0, 20.0), blocked_x: false, blocked_y: true });
+    }
+
+    #[test]
+    fn touching_a_wall_does_not_block_moving_along_it() {
+        let map = map(&[
+            "...#",
+            "...#",
+            "...#",
+        ]);
+        let result = map.move_box(Aabb::new(36.0, 2.0, 12.0, 12.0), Vec2::new(0.0, 20.0));
+        assert_eq!(result, MoveResult { pos: Vec2::new(36.0, 22.0), blocked_x: false, blocked_y: false });
+    }
+
+    #[test]
+    fn outside_the_grid_is_solid() {
+        let map = map(&[
+            "..",
+            "..",
+        ]);
+        assert_eq!(map.terrain(Cell::new(-1, 0)), Terrain::Solid);
+        assert_eq!(map.terrain(Cell::new(0, 2)), Terrain::Solid);
+
+        let result = map.move_box(Aabb::new(2.0, 2.0, 12.0, 12.0), Vec2::new(-10.0, 40.0));
+        assert_eq!(result, MoveResult { pos: Vec2::new(0.0, 20.0), blocked_x: true, blocked_y: true });
+    }
+
+    #[test]
+    fn move_box_by_can_walk_out_of_the_grid() {
+        let map = map(&[
+            "..",
+            "..",
+        ]);
+        let result = map.move_box_by(Aabb::new(18.0, 2.0, 12.0, 12.0), Vec2::new(14.0, 0.0), |cell| {
+            cell.cx == 2 || map.is_walkable(cell)
+        });
+        assert_eq!(result, MoveResult { pos: Vec2::new(32.0, 2.0), blocked_x: false, blocked_y: false });
+    }
+
+    #[test]
+    fn slow_terrain_scales_speed() {
+        let map = map(&[
+            ".~",
+        ]);
+        assert_eq!(map.speed_factor(Vec2::new(8.0, 8.0)), 1.0);
+        assert_eq!(map.speed_factor(Vec2::new(24.0, 8.0)), 0.5);
+    }
+
+    #[test]
+    fn ray_stops_at_the_first_wall() {
+        let map = map(&[
+            "...##",
+        ]);
+        let hit = map.ray_cast(Vec2::new(8.0, 8.0), Vec2::new(72.0, 8.0)).unwrap();
+        assert_eq!(hit.cell, Cell::new(3, 0));
+        assert_eq!(hit.point, Vec2::new(48.0, 8.0));
+        assert_eq!(hit.distance, 40.0);
+        assert_eq!(hit.terrain, Terrain::Solid);
+        assert!(map.ray_cast(Vec2::new(8.0, 8.0), Vec2::new(40.0, 8.0)).is_none());
+    }
+
+    #[test]
+    fn ray_on_a_cell_border_walks_the_cells_below_it() {
+        let top = map(&[
+            "..#.",
+            "....",
+        ]);
+        assert!(top.ray_cast(Vec2::new(0.0, 16.0), Vec2::new(63.0, 16.0)).is_none());
+
+        let bottom = map(&[
+            "....",
+            "..#.",
+        ]);
+        let hit = bottom.ray_cast(Vec2::new(0.0, 16.0), Vec2::new(63.0, 16.0)).unwrap();
+        assert_eq!(hit.cell, Cell::new(2, 1));
+        assert_eq!(hit.point, Vec2::new(32.0, 16.0));
+    }
+
+    #[test]
+    fn ray_through_a_corner_between_walls_is_blocked() {
+        let map = map(&[
+            ".#.",
+            "#..",
+            "...",
+        ]);
+        let hit = map.ray_cast(Vec2::new(8.0, 8.0), Vec2::new(40.0, 40.0)).unwrap();
+        assert_eq!(hit.cell, Cell::new(0, 1));
+        assert_eq!(hit.point, Vec2::new(16.0, 16.0));
+    }
+
+    #[test]
+    fn ray_cast_by_decides_what_blocks() {
+        let map = map(&[
+            ".~#",
+        ]);
+        let hit = map.ray_cast_by(Vec2::new(8.0, 8.0), Vec2::new(40.0, 8.0), |terrain| terrain == Terrain::Slow).unwrap();
+        assert_eq!(hit.cell, Cell::new(1, 0));
+        assert_eq!(hit.terrain, Terrain::Slow);
+    }
+}
@@ -0,0 +1,494 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Display;
+
+use super::{
+    geom::Cell,
+    model::{Definitions, FieldDef},
+};
+
+/// Field type as written in LDtk `__type`, like `Int`, `Enum(Item)` or `Array<Point>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    Int,
+    Float,
+    Bool,
+    String,
+    Multilines,
+    Color,
+    Point,
+    FilePath,
+    EntityRef,
+    Tile,
+    Enum(String),
+    Array(Box<FieldType>),
+}
+
+impl FieldType {
+    pub fn parse(type_name: &str) -> Option<Self> {
+        if let Some(inner) = type_name.strip_prefix("Array<").and_then(|rest| rest.strip_suffix('>')) {
+            return Self::parse(inner).map(|inner| FieldType::Array(Box::new(inner)));
+        }
+        if let Some(name) = type_name.strip_prefix("Enum(").and_then(|rest| rest.strip_suffix(')'))
+            .or_else(|| type_name.strip_prefix("LocalEnum."))
+            .or_else(|| type_name.strip_prefix("ExternEnum.")) {
+            return Some(FieldType::Enum(name.to_owned()));
+        }
+        Some(match type_name {
+            "Int" => FieldType::Int,
+            "Float" => FieldType::Float,
+            "Bool" => FieldType::Bool,
+            "String" => FieldType::String,
+            "Multilines" => FieldType::Multilines,
+            "Color" => FieldType::Color,
+            "Point" => FieldType::Point,
+            "FilePath" => FieldType::FilePath,
+            "EntityRef" => FieldType::EntityRef,
+            "Tile" => FieldType::Tile,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    /// Parses `#rrggbb`.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let digits = hex.strip_prefix('#')?;
+        if digits.len() != 6 {
+            return None;
+        }
+        let rgb = u32::from_str_radix(digits, 16).ok()?;
+        Some(Self {
+            r: (rgb >> 16) as u8,
+            g: (rgb >> 8) as u8,
+            b: rgb as u8,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EntityRef {
+    pub entity_iid: String,
+    pub layer_iid: String,
+    pub level_iid: String,
+    pub world_iid: String,
+}
+
+/// A rectangle of a tileset, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileRect {
+    pub tileset_uid: i64,
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+/// Serializes as plain JSON values: numbers, strings, objects or arrays.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Null,
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// `String` and `Multilines` values.
+    String(String),
+    Color(Color),
+    Point(Cell),
+    FilePath(String),
+    EntityRef(EntityRef),
+    Tile(TileRect),
+    Enum {
+        name: String,
+        value: String,
+    },
+    Array(Vec<FieldValue>),
+}
+
+impl FieldValue {
+
+    /// Decodes a raw `__value` according to the instance `__type`, checked against its definition.
+    pub fn decode(type_name: &str, def: &FieldDef, defs: &Definitions, value: Option<&Value>) -> Result<Self, String> {
+        let field_type = FieldType::parse(type_name)
+            .ok_or_else(|| format!("unsupported field type {}", type_name))?;
+        let def_type = FieldType::parse(&def.type_name)
+            .ok_or_else(|| format!("unsupported field type {} in definition", def.type_name))?;
+        if field_type != def_type {
+            return Err(format!("type {} does not match definition type {}", type_name, def.type_name));
+        }
+        decode(&field_type, def, defs, value.unwrap_or(&Value::Null))
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, FieldValue::Null)
+    }
+}
+
+fn decode(field_type: &FieldType, def: &FieldDef, defs: &Definitions, value: &Value) -> Result<FieldValue, String> {
+    if value.is_null() {
+        return if def.can_be_null {
+            Ok(FieldValue::Null)
+        } else {
+            Err("null value for a non-nullable field".to_owned())
+        };
+    }
+    let unexpected = || format!("expected {:?}, got {}", field_type, value);
+    let string = || value.as_str().map(str::to_owned).ok_or_else(unexpected);
+    let int = |object: &Value, key: &str| object.get(key).and_then(Value::as_i64).ok_or_else(unexpected);
+    let text = |object: &Value, key: &str| object.get(key).and_then(Value::as_str).map(str::to_owned)
+        .ok_or_else(unexpected);
+
+    Ok(match field_type {
+        FieldType::Int => FieldValue::Int(value.as_i64().ok_or_else(unexpected)?),
+        FieldType::Float => FieldValue::Float(value.as_f64().ok_or_else(unexpected)?),
+        FieldType::Bool => FieldValue::Bool(value.as_bool().ok_or_else(unexpected)?),
+        FieldType::String | FieldType::Multilines => FieldValue::String(string()?),
+        FieldType::FilePath => FieldValue::FilePath(string()?),
+        FieldType::Color => FieldValue::Color(Color::from_hex(&string()?).ok_or_else(unexpected)?),
+        FieldType::Point => FieldValue::Point(Cell::new(int(value, "cx")? as i32, int(value, "cy")? as i32)),
+        FieldType::EntityRef => FieldValue::EntityRef(EntityRef {
+            entity_iid: text(value, "entityIid")?,
+            layer_iid: text(value, "layerIid")?,
+            level_iid: text(value, "levelIid")?,
+            world_iid: text(value, "worldIid")?,
+        }),
+        FieldType::Tile => FieldValue::Tile(TileRect {
+            tileset_uid: int(value, "tilesetUid")?,
+            x: int(value, "x")? as i32,
+            y: int(value, "y")? as i32,
+            w: int(value, "w")? as i32,
+            h: int(value, "h")? as i32,
+        }),
+        FieldType::Enum(name) => {
+            let enum_def = defs.enum_by_identifier(name)
+                .ok_or_else(|| format!("unknown enum {}", name))?;
+            let enum_value = string()?;
+            if !enum_def.values.contains(&enum_value) {
+                return Err(format!("{} is not a value of enum {}", enum_value, name));
+            }
+            FieldValue::Enum {
+                name: name.clone(),
+                value: enum_value,
+            }
+        }
+        FieldType::Array(inner) => FieldValue::Array(value.as_array()
+            .ok_or_else(unexpected)?
+            .iter()
+            .enumerate()
+            .map(|(index, item)| decode(inner, def, defs, item).map_err(|err| format!("[{}]: {}", index, err)))
+            .collect::<Result<Vec<_>, _>>()?),
+    })
+}
+
+/// Rust types a field value can be read as, see [`super::model::Entity::field`].
+pub trait FromFieldValue: Sized {
+    const EXPECTED: &'static str;
+
+    fn from_field_value(value: &FieldValue) -> Option<Self>;
+}
+
+macro_rules! from_field_value {
+    ($ty:ty, $expected:literal, $($pattern:pat => $result:expr),+) => {
+        impl FromFieldValue for $ty {
+            const EXPECTED: &'static str = $expected;
+
+            fn from_field_value(value: &FieldValue) -> Option<Self> {
+                match value {
+                    $($pattern => $result,)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+from_field_value!(i64, "Int", FieldValue::Int(value) => Some(*value));
+from_field_value!(i32, "Int", FieldValue::Int(value) => i32::try_from(*value).ok());
+from_field_value!(u32, "Int", FieldValue::Int(value) => u32::try_from(*value).ok());
+from_field_value!(f64, "Float",
+    FieldValue::Float(value) => Some(*value),
+    FieldValue::Int(value) => Some(*value as f64));
+from_field_value!(f32, "Float",
+    FieldValue::Float(value) => Some(*value as f32),
+    FieldValue::Int(value) => Some(*value as f32));
+from_field_value!(bool, "Bool", FieldValue::Bool(value) => Some(*value));
+from_field_value!(String, "String",
+    FieldValue::String(value) | FieldValue::FilePath(value) | FieldValue::Enum { value, .. } => Some(value.clone()));
+from_field_value!(Color, "Color", FieldValue::Color(value) => Some(*value));
+from_field_value!(Cell, "Point", FieldValue::Point(value) => Some(*value));
+from_field_value!(EntityRef, "EntityRef", FieldValue::EntityRef(value) => Some(value.clone()));
+from_field_value!(TileRect, "Tile", FieldValue::Tile(value) => Some(*value));
+
+/// Null values read as `None`.
+impl<T: FromFieldValue> FromFieldValue for Option<T> {
+    const EXPECTED: &'static str = T::EXPECTED;
+
+    fn from_field_value(value: &FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Null => Some(None),
+            value => T::from_field_value(value).map(Some),
+        }
+    }
+}
+
+impl<T: FromFieldValue> FromFieldValue for Vec<T> {
+    const EXPECTED: &'static str = "Array";
+
+    fn from_field_value(value: &FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Array(values) => values.iter().map(T::from_field_value).collect(),
+            _ => None,
+        }
+    }
+}
+
+/// `FieldValue` serializes untagged for the API, which cannot be read back.
+/// Binary formats like the world cache go through this tagged copy instead.
+pub(super) mod tagged {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{Color, EntityRef, FieldValue, TileRect};
+    use crate::world::geom::Cell;
+
+    #[derive(Serialize, Deserialize)]
+    enum Tagged {
+        Null,
+        Int(i64),
+        Float(f64),
+        Bool(bool),
+        String(String),
+        Color(Color),
+        Point(Cell),
+        FilePath(String),
+        EntityRef(EntityRef),
+        Tile(TileRect),
+        Enum {
+            name: String,
+            value: String,
+        },
+        Array(Vec<Tagged>),
+    }
+
+    impl From<&FieldValue> for Tagged {
+        fn from(value: &FieldValue) -> Self {
+            match value {
+                FieldValue::Null => Tagged::Null,
+                FieldValue::Int(value) => Tagged::Int(*value),
+                FieldValue::Float(value) => Tagged::Float(*value),
+                FieldValue::Bool(value) => Tagged::Bool(*value),
+                FieldValue::String(value) => Tagged::String(value.clone()),
+                FieldValue::Color(value) => Tagged::Color(*value),
+                FieldValue::Point(value) => Tagged::Point(*value),
+                FieldValue::FilePath(value) => Tagged::FilePath(value.clone()),
+                FieldValue::EntityRef(value) => Tagged::EntityRef(value.clone()),
+                FieldValue::Tile(value) => Tagged::Tile(*value),
+                FieldValue::Enum { name, value } => Tagged::Enum { name: name.clone(), value: value.clone() },
+                FieldValue::Array(values) => Tagged::Array(values.iter().map(Tagged::from).collect()),
+            }
+        }
+    }
+
+    impl From<Tagged> for FieldValue {
+        fn from(value: Tagged) -> Self {
+            match value {
+                Tagged::Null => FieldValue::Null,
+                Tagged::Int(value) => FieldValue::Int(value),
+                Tagged::Float(value) => FieldValue::Float(value),
+                Tagged::Bool(value) => FieldValue::Bool(value),
+                Tagged::String(value) => FieldValue::String(value),
+                Tagged::Color(value) => FieldValue::Color(value),
+                Tagged::Point(value) => FieldValue::Point(value),
+                Tagged::FilePath(value) => FieldValue::FilePath(value),
+                Tagged::EntityRef(value) => FieldValue::EntityRef(value),
+                Tagged::Tile(value) => FieldValue::Tile(value),
+                Tagged::Enum { name, value } => FieldValue::Enum { name, value },
+                Tagged::Array(values) => FieldValue::Array(values.into_iter().map(FieldValue::from).collect()),
+            }
+        }
+    }
+
+    pub fn serialize<S: Serializer>(value: &FieldValue, serializer: S) -> Result<S::Ok, S::Error> {
+        Tagged::from(value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FieldValue, D::Error> {
+        Tagged::deserialize(deserializer).map(FieldValue::from)
+    }
+}
+
+#[derive(Debug)]
+pub enum FieldError {
+    Missing {
+        entity: String,
+        field: String,
+    },
+    Type {
+        entity: String,
+        field: String,
+        expected: &'static str,
+        found: String,
+    },
+}
+
+impl Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldError::Missing { entity, field } => write!(f, "{} has no field {}", entity, field),
+            FieldError::Type { entity, field, expected, found } =>
+                write!(f, "field {} of {} is {}, expected {}", field, entity, found, expected),
+        }
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::world::model::EnumDef;
+
+    fn field_def(type_name: &str, can_be_null: bool) -> FieldDef {
+        FieldDef {
+            uid: 1,
+            identifier: "field".to_owned(),
+            type_name: type_name.to_owned(),
+            is_array: type_name.starts_with("Array<"),
+            can_be_null,
+        }
+    }
+
+    fn defs() -> Definitions {
+        Definitions {
+            enums: HashMap::from([(7, EnumDef {
+                uid: 7,
+                identifier: "Item".to_owned(),
+                values: vec!["Key".to_owned(), "Heal".to_owned()],
+            })]),
+            ..Default::default()
+        }
+    }
+
+    fn decode(type_name: &str, value: Value) -> Result<FieldValue, String> {
+        FieldValue::decode(type_name, &field_def(type_name, false), &defs(), Some(&value))
+    }
+
+    #[test]
+    fn parses_type_names() {
+        assert_eq!(FieldType::parse("Int"), Some(FieldType::Int));
+        assert_eq!(FieldType::parse("Enum(Item)"), Some(FieldType::Enum("Item".to_owned())));
+        assert_eq!(FieldType::parse("LocalEnum.Item"), Some(FieldType::Enum("Item".to_owned())));
+        assert_eq!(FieldType::parse("ExternEnum.Item"), Some(FieldType::Enum("Item".to_owned())));
+        assert_eq!(FieldType::parse("Array<Point>"), Some(FieldType::Array(Box::new(FieldType::Point))));
+        assert_eq!(FieldType::parse("Array<Enum(Item)>"),
+            Some(FieldType::Array(Box::new(FieldType::Enum("Item".to_owned())))));
+        assert_eq!(FieldType::parse("Matrix"), None);
+        assert_eq!(FieldType::parse("Array<Matrix>"), None);
+    }
+
+    #[test]
+    fn decodes_scalars() {
+        assert_eq!(decode("Int", json!(3)), Ok(FieldValue::Int(3)));
+        assert_eq!(decode("Float", json!(1.5)), Ok(FieldValue::Float(1.5)));
+        assert_eq!(decode("Float", json!(2)), Ok(FieldValue::Float(2.0)));
+        assert_eq!(decode("Bool", json!(true)), Ok(FieldValue::Bool(true)));
+        assert_eq!(decode("Multilines", json!("a\nb")), Ok(FieldValue::String("a\nb".to_owned())));
+        assert_eq!(decode("FilePath", json!("a.png")), Ok(FieldValue::FilePath("a.png".to_owned())));
+        assert_eq!(decode("Color", json!("#ff8000")), Ok(FieldValue::Color(Color { r: 255, g: 128, b: 0 })));
+        assert!(decode("Color", json!("#ff80")).is_err());
+        assert!(decode("Int", json!(1.5)).is_err());
+        assert!(decode("String", json!(1)).is_err());
+    }
+
+    #[test]
+    fn decodes_objects() {
+        assert_eq!(decode("Point", json!({ "cx": 4, "cy": 9 })), Ok(FieldValue::Point(Cell::new(4, 9))));
+        assert_eq!(decode("EntityRef", json!({
+            "entityIid": "e", "layerIid": "l", "levelIid": "v", "worldIid": "w",
+        })), Ok(FieldValue::EntityRef(EntityRef {
+            entity_iid: "e".to_owned(),
+            layer_iid: "l".to_owned(),
+            level_iid: "v".to_owned(),
+            world_iid: "w".to_owned(),
+        })));
+        assert_eq!(decode("Tile", json!({ "tilesetUid": 2, "x": 16, "y": 32, "w": 16, "h": 16 })),
+            Ok(FieldValue::Tile(TileRect { tileset_uid: 2, x: 16, y: 32, w: 16, h: 16 })));
+        assert!(decode("Point", json!({ "cx": 4 })).is_err());
+    }
+
+    #[test]
+    fn decodes_enums_against_their_definition() {
+        assert_eq!(decode("LocalEnum.Item", json!("Key")),
+            Ok(FieldValue::Enum { name: "Item".to_owned(), value: "Key".to_owned() }));
+        assert_eq!(decode("LocalEnum.Item", json!("Sword")), Err("Sword is not a value of enum Item".to_owned()));
+        assert_eq!(decode("LocalEnum.Spell", json!("Fire")), Err("unknown enum Spell".to_owned()));
+    }
+
+    #[test]
+    fn decodes_arrays_and_reports_the_failing_index() {
+        assert_eq!(decode("Array<Int>", json!([1, 2])),
+            Ok(FieldValue::Array(vec![FieldValue::Int(1), FieldValue::Int(2)])));
+        assert_eq!(decode("Array<Int>", json!([1, "two"])), Err("[1]: expected Int, got \"two\"".to_owned()));
+        assert!(decode("Array<Int>", json!(1)).is_err());
+    }
+
+    #[test]
+    fn null_only_for_nullable_fields() {
+        let nullable = field_def("Int", true);
+        assert_eq!(FieldValue::decode("Int", &nullable, &defs(), None), Ok(FieldValue::Null));
+        assert_eq!(FieldValue::decode("Int", &nullable, &defs(), Some(&Value::Null)), Ok(FieldValue::Null));
+        assert!(FieldValue::decode("Int", &field_def("Int", false), &defs(), None).is_err());
+    }
+
+    #[test]
+    fn instance_type_must_match_the_definition() {
+        let err = FieldValue::decode("Float", &field_def("Int", false), &defs(), Some(&json!(1))).unwrap_err();
+        assert_eq!(err, "type Float does not match definition type Int");
+        assert!(FieldValue::decode("Enum(Item)", &field_def("LocalEnum.Item", false), &defs(), Some(&json!("Key")))
+            .is_ok());
+    }
+
+    #[test]
+    fn reads_values_as_rust_types() {
+        assert_eq!(i32::from_field_value(&FieldValue::Int(5)), Some(5));
+        assert_eq!(i32::from_field_value(&FieldValue::Int(i64::MAX)), None);
+        assert_eq!(u32::from_field_value(&FieldValue::Int(-1)), None);
+        assert_eq!(f32::from_field_value(&FieldValue::Int(2)), Some(2.0));
+        assert_eq!(String::from_field_value(&FieldValue::Enum { name: "Item".to_owned(), value: "Key".to_owned() }),
+            Some("Key".to_owned()));
+        assert_eq!(Option::<i64>::from_field_value(&FieldValue::Null), Some(None));
+        assert_eq!(i64::from_field_value(&FieldValue::Null), None);
+        assert_eq!(Vec::<i64>::from_field_value(&FieldValue::Array(vec![FieldValue::Int(1), FieldValue::Int(2)])),
+            Some(vec![1, 2]));
+        assert_eq!(Vec::<i64>::from_field_value(&FieldValue::Array(vec![FieldValue::Int(1), FieldValue::Bool(true)])),
+            None);
+    }
+
+    #[test]
+    fn tagged_values_round_trip() {
+        #[derive(Serialize, Deserialize)]
+        struct Wrapper(#[serde(with = "tagged")] FieldValue);
+
+        let value = FieldValue::Array(vec![
+            FieldValue::Null,
+            FieldValue::Int(1),
+            FieldValue::Float(1.5),
+            FieldValue::String("a".to_owned()),
+            FieldValue::FilePath("a.png".to_owned()),
+            FieldValue::Point(Cell::new(1, 2)),
+            FieldValue::Enum { name: "Item".to_owned(), value: "Key".to_owned() },
+        ]);
+        let bytes = postcard::to_allocvec(&Wrapper(value.clone())).unwrap();
+        assert_eq!(postcard::from_bytes::<Wrapper>(&bytes).unwrap().0, value);
+    }
+}
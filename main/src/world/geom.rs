@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+
+/// A position in pixels, either level-local or in world space depending on context.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    pub fn offset(self, dx: i32, dy: i32) -> Self {
+        Self::new(self.x + dx, self.y + dy)
+    }
+}
+
+/// A grid cell coordinate of a layer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Cell {
+    pub cx: i32,
+    pub cy: i32,
+}
+
+impl Cell {
+    pub const fn new(cx: i32, cy: i32) -> Self {
+        Self { cx, cy }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Size {
+    pub w: i32,
+    pub h: i32,
+}
+
+impl Size {
+    pub const fn new(w: i32, h: i32) -> Self {
+        Self { w, h }
+    }
+}
+
+/// Axis-aligned rectangle; `x`/`y` is the top-left corner, right and bottom edges are exclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    pub fn from_pos_size(pos: Point, size: Size) -> Self {
+        Self::new(pos.x, pos.y, size.w, size.h)
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.w
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.h
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        point.x >= self.x && point.x < self.right() && point.y >= self.y && point.y < self.bottom()
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.right() && other.x < self.right() && self.y < other.bottom() && other.y < self.bottom()
+    }
+
+    pub fn contains_rect(&self, other: &Rect) -> bool {
+        other.x >= self.x && other.y >= self.y && other.right() <= self.right() && other.bottom() <= self.bottom()
+    }
+}
+
+/// Sub-pixel position or displacement, used for movement.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vec2 {
+    pub const fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    pub fn length(self) -> f32 {
+        (self.x * self.x + self.y * self.y).sqrt()
+    }
+
+    pub fn distance(self, other: Vec2) -> f32 {
+        Vec2::new(other.x - self.x, other.y - self.y).length()
+    }
+}
+
+impl From<Point> for Vec2 {
+    fn from(point: Point) -> Self {
+        Self::new(point.x as f32, point.y as f32)
+    }
+}
+
+/// Axis-aligned box with sub-pixel position; right and bottom edges are exclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Aabb {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Aabb {
+    pub const fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    pub fn pos(&self) -> Vec2 {
+        Vec2::new(self.x, self.y)
+    }
+
+    pub fn center(&self) -> Vec2 {
+        Vec2::new(self.x + self.w / 2.0, self.y + self.h / 2.0)
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.x < other.x + other.w && other.x < self.x + self.w
+            && self.y < other.y + other.h && other.y < self.y + self.h
+    }
+
+    /// Distance from `point` to the nearest point of the box, 0 inside it.
+    pub fn distance_to(&self, point: Vec2) -> f32 {
+        let dx = (self.x - point.x).max(point.x - (self.x + self.w)).max(0.0);
+        let dy = (self.y - point.y).max(point.y - (self.y + self.h)).max(0.0);
+        Vec2::new(dx, dy).length()
+    }
+}
+
+impl From<Rect> for Aabb {
+    fn from(rect: Rect) -> Self {
+        Self::new(rect.x as f32, rect.y as f32, rect.w as f32, rect.h as f32)
+    }
+}
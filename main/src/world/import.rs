@@ -0,0 +1,215 @@
+use serde::Serialize;
+use sqlx::{FromRow, Pool, Postgres};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    fs,
+    path::Path,
+};
+use uuid::Uuid;
+
+use super::{load_worlds, tiled, worlds_from_project, LoadError, Project, WorldError};
+
+#[derive(Debug)]
+pub enum ImportError {
+    World(WorldError),
+    Db(sqlx::Error),
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::World(err) => write!(f, "{}", err),
+            ImportError::Db(err) => write!(f, "database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<WorldError> for ImportError {
+    fn from(err: WorldError) -> Self {
+        ImportError::World(err)
+    }
+}
+
+impl From<LoadError> for ImportError {
+    fn from(err: LoadError) -> Self {
+        ImportError::World(err.into())
+    }
+}
+
+impl From<sqlx::Error> for ImportError {
+    fn from(err: sqlx::Error) -> Self {
+        ImportError::Db(err)
+    }
+}
+
+/// Outcome of importing one world of a project.
+#[derive(Debug, Clone, Serialize)]
+pub struct WorldImport {
+    pub world_id: Uuid,
+    pub iid: String,
+    pub name: String,
+    /// False when an existing `world` row was updated.
+    pub created: bool,
+    /// Iids of levels that had no `lvl` row yet.
+    pub added: Vec<String>,
+    /// Levels whose row differed from the project and was rewritten.
+    pub updated: usize,
+    /// Levels whose rows were deleted because the project no longer has them.
+    pub removed: Vec<RemovedLevel>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct RemovedLevel {
+    pub iid: Option<String>,
+    pub identifier: Option<String>,
+}
+
+/// Path of a project file as stored on world rows: relative to `maps_dir` when
+/// the file lies inside it, so the rows stay valid when the directory moves.
+pub fn project_path(maps_dir: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(maps_dir).ok().map(Path::to_owned).or_else(|| {
+        let (maps_dir, path) = (fs::canonicalize(maps_dir).ok()?, fs::canonicalize(path).ok()?);
+        path.strip_prefix(maps_dir).ok().map(Path::to_owned)
+    });
+    match relative {
+        Some(relative) => relative.components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        None => path.to_string_lossy().into_owned(),
+    }
+}
+
+/// Creates or updates one `world` row per world of the project and one `lvl` row
+/// per level, matched by iid so importing the same project again changes nothing.
+/// Rows come from the runtime model, so Tiled maps import like LDtk projects.
+/// `project` is the path stored on the world row, relative to the maps directory.
+pub async fn import_project(pool: &Pool<Postgres>, path: &Path, project_path: &str)
+    -> Result<Vec<WorldImport>, ImportError> {
+    let project_file = path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let project_stem = path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (worlds, level_files, project_iid) = if tiled::is_tiled_map(path) {
+        (load_worlds(path)?, HashMap::new(), None)
+    } else {
+        let project = Project::load(path)?;
+        // Levels saved in their own `.ldtkl` file, by iid.
+        let level_files: HashMap<String, String> = project.levels()
+            .filter_map(|level| Some((level.iid.clone(), level.external_rel_path.clone()?)))
+            .collect();
+        // Single-world projects used to be imported under the project iid instead of
+        // `dummyWorldIid`; such rows are picked up and given the world iid.
+        let project_iid = project.json().worlds.is_empty().then(|| project.json().iid.clone());
+        (worlds_from_project(&project)?, level_files, project_iid)
+    };
+
+    let mut tx = pool.begin().await?;
+    let mut imports = Vec::new();
+    for world in &worlds {
+        // Rows created before worlds had an iid were named after the project file.
+        let existing: Option<Uuid> = sqlx::query_scalar(
+            "SELECT id FROM world WHERE iid = $1 OR iid = $3 OR (iid IS NULL AND name = $2) \
+             ORDER BY iid = $1 DESC, iid IS NULL LIMIT 1")
+            .bind(&world.iid)
+            .bind(&project_stem)
+            .bind(&project_iid)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let world_id = match existing {
+            // The name becomes the world identifier, no longer the file stem that
+            // `maps::load_map` falls back to; `project` is set in the same statement,
+            // so the fallback is never needed for imported rows.
+            Some(id) => {
+                sqlx::query("UPDATE world SET iid = $2, name = $3, project = $4 WHERE id = $1")
+                    .bind(id)
+                    .bind(&world.iid)
+                    .bind(&world.identifier)
+                    .bind(project_path)
+                    .execute(&mut *tx)
+                    .await?;
+                id
+            }
+            None => sqlx::query_scalar("INSERT INTO world(iid, name, project) VALUES ($1, $2, $3) RETURNING id")
+                .bind(&world.iid)
+                .bind(&world.identifier)
+                .bind(project_path)
+                .fetch_one(&mut *tx)
+                .await?,
+        };
+
+        let mut import = WorldImport {
+            world_id,
+            iid: world.iid.clone(),
+            name: world.identifier.clone(),
+            created: existing.is_none(),
+            added: Vec::new(),
+            updated: 0,
+            removed: Vec::new(),
+        };
+        let existing_levels: HashSet<String> =
+            sqlx::query_scalar("SELECT iid FROM lvl WHERE world = $1 AND iid IS NOT NULL")
+            .bind(world_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .collect();
+        for level in &world.levels {
+            let file_name = level_files.get(&level.iid).unwrap_or(&project_file);
+            if existing_levels.contains(&level.iid) {
+                let updated = sqlx::query(
+                    "UPDATE lvl SET file_name = $3, identifier = $4, world_x = $5, world_y = $6, world_depth = $7, \
+                     px_wid = $8, px_hei = $9 WHERE world = $1 AND iid = $2 \
+                     AND (file_name, identifier, world_x, world_y, world_depth, px_wid, px_hei) \
+                     IS DISTINCT FROM ($3, $4, $5, $6, $7, $8, $9)")
+                    .bind(world_id)
+                    .bind(&level.iid)
+                    .bind(file_name)
+                    .bind(&level.identifier)
+                    .bind(level.world_pos.x)
+                    .bind(level.world_pos.y)
+                    .bind(level.world_depth)
+                    .bind(level.size.w)
+                    .bind(level.size.h)
+                    .execute(&mut *tx)
+                    .await?
+                    .rows_affected();
+                if updated > 0 {
+                    import.updated += 1;
+                }
+                continue;
+            }
+            sqlx::query(
+                "INSERT INTO lvl(world, iid, file_name, identifier, world_x, world_y, world_depth, px_wid, px_hei) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)")
+                .bind(world_id)
+                .bind(&level.iid)
+                .bind(file_name)
+                .bind(&level.identifier)
+                .bind(level.world_pos.x)
+                .bind(level.world_pos.y)
+                .bind(level.world_depth)
+                .bind(level.size.w)
+                .bind(level.size.h)
+                .execute(&mut *tx)
+                .await?;
+            import.added.push(level.iid.clone());
+        }
+
+        let iids: Vec<&str> = world.levels.iter().map(|level| level.iid.as_str()).collect();
+        import.removed = sqlx::query_as::<_, RemovedLevel>(
+            "DELETE FROM lvl WHERE world = $1 AND (iid IS NULL OR NOT iid = ANY($2)) RETURNING iid, identifier")
+            .bind(world_id)
+            .bind(&iids)
+            .fetch_all(&mut *tx)
+            .await?;
+        imports.push(import);
+    }
+    tx.commit().await?;
+    Ok(imports)
+}
@@ -0,0 +1,163 @@
+use serde::de::DeserializeOwned;
+use std::{fmt::Display, fs, path::{Path, PathBuf}};
+
+use super::ldtk_json::{LdtkJson, Level, TilesetDefinition, WorldLayout};
+
+/// Identifier LDtk gives the implicit world of projects without multi-worlds.
+pub const DEFAULT_WORLD_IDENTIFIER: &str = "World";
+
+#[derive(Debug)]
+pub enum LoadErrorKind {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+#[derive(Debug)]
+pub struct LoadError {
+    pub file: PathBuf,
+    /// JSON path of the value that failed to deserialize, like `levels[0].layerInstances`.
+    pub json_path: Option<String>,
+    pub kind: LoadErrorKind,
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.kind, &self.json_path) {
+            (LoadErrorKind::Io(err), _) => write!(f, "cannot read {}: {}", self.file.display(), err),
+            (LoadErrorKind::Json(err), Some(path)) =>
+                write!(f, "cannot parse {} at {}: {}", self.file.display(), path, err),
+            (LoadErrorKind::Json(err), None) => write!(f, "cannot parse {}: {}", self.file.display(), err),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// A parsed `.ldtk` project with external `.ldtkl` levels already merged in.
+#[derive(Debug, Clone)]
+pub struct Project {
+    path: PathBuf,
+    json: LdtkJson,
+}
+
+/// One world of a project. Projects without multi-worlds have a single
+/// implicit world built from the root level list.
+pub struct ProjectWorld<'a> {
+    pub iid: &'a str,
+    pub identifier: &'a str,
+    pub layout: Option<&'a WorldLayout>,
+    pub grid_width: i64,
+    pub grid_height: i64,
+    pub levels: &'a [Level],
+}
+
+impl Project {
+
+    pub fn load(path: &Path) -> Result<Self, LoadError> {
+        let mut json: LdtkJson = read_json(path)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        if json.external_levels {
+            for level in json.levels.iter_mut() {
+                load_external_level(dir, level)?;
+            }
+            for world in json.worlds.iter_mut() {
+                for level in world.levels.iter_mut() {
+                    load_external_level(dir, level)?;
+                }
+            }
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            json,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new(""))
+    }
+
+    pub fn json(&self) -> &LdtkJson {
+        &self.json
+    }
+
+    pub fn into_json(self) -> LdtkJson {
+        self.json
+    }
+
+    pub fn worlds(&self) -> Vec<ProjectWorld<'_>> {
+        if !self.json.worlds.is_empty() {
+            return self.json.worlds.iter()
+                .map(|world| ProjectWorld {
+                    iid: &world.iid,
+                    identifier: &world.identifier,
+                    layout: world.world_layout.as_ref(),
+                    grid_width: world.world_grid_width,
+                    grid_height: world.world_grid_height,
+                    levels: &world.levels,
+                })
+                .collect();
+        }
+        vec![ProjectWorld {
+            iid: &self.json.dummy_world_iid,
+            identifier: DEFAULT_WORLD_IDENTIFIER,
+            layout: self.json.world_layout.as_ref(),
+            grid_width: self.json.world_grid_width.unwrap_or_default(),
+            grid_height: self.json.world_grid_height.unwrap_or_default(),
+            levels: &self.json.levels,
+        }]
+    }
+
+    pub fn levels(&self) -> impl Iterator<Item = &Level> {
+        self.json.levels.iter()
+            .chain(self.json.worlds.iter().flat_map(|world| world.levels.iter()))
+    }
+
+    /// Resolves a path stored in the project, which LDtk keeps relative to the `.ldtk` file.
+    pub fn resolve(&self, rel_path: &str) -> PathBuf {
+        self.dir().join(rel_path)
+    }
+
+    pub fn tileset(&self, uid: i64) -> Option<&TilesetDefinition> {
+        self.json.defs.tilesets.iter().find(|tileset| tileset.uid == uid)
+    }
+
+    pub fn tileset_path(&self, tileset: &TilesetDefinition) -> Option<PathBuf> {
+        tileset.rel_path.as_deref().map(|rel_path| self.resolve(rel_path))
+    }
+
+    pub fn level_bg_path(&self, level: &Level) -> Option<PathBuf> {
+        level.bg_rel_path.as_deref().map(|rel_path| self.resolve(rel_path))
+    }
+}
+
+fn load_external_level(dir: &Path, level: &mut Level) -> Result<(), LoadError> {
+    if let Some(rel_path) = &level.external_rel_path {
+        let external: Level = read_json(&dir.join(rel_path))?;
+        *level = Level {
+            external_rel_path: Some(rel_path.clone()),
+            ..external
+        };
+    }
+    Ok(())
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, LoadError> {
+    let content = fs::read(path).map_err(|err| LoadError {
+        file: path.to_owned(),
+        json_path: None,
+        kind: LoadErrorKind::Io(err),
+    })?;
+    let deserializer = &mut serde_json::Deserializer::from_slice(&content);
+    serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        let json_path = err.path().to_string();
+        LoadError {
+            file: path.to_owned(),
+            json_path: Some(json_path),
+            kind: LoadErrorKind::Json(err.into_inner()),
+        }
+    })
+}
@@ -1,2 +1,134 @@
-mod ldtk_json;
+use std::{fmt::Display, fs, path::{Path, PathBuf}};
 
+#[allow(clippy::doc_lazy_continuation, clippy::enum_variant_names)]
+pub mod ldtk_json;
+pub mod auto_layer;
+pub mod cache;
+pub mod collision;
+pub mod field;
+pub mod geom;
+pub mod import;
+pub mod loader;
+pub mod model;
+pub mod pathfinding;
+pub mod preview;
+pub mod procgen;
+pub mod refs;
+pub mod spatial;
+pub mod store;
+#[cfg(test)]
+mod test_util;
+pub mod tile_meta;
+pub mod tiled;
+pub mod validate;
+pub mod visibility;
+
+pub use loader::{LoadError, Project};
+pub use model::{ModelError, World};
+pub use refs::{DanglingRef, IidIndex};
+pub use tiled::TiledError;
+pub use validate::{Issue, Severity};
+
+pub const PROJECT_EXTENSION: &str = "ldtk";
+
+#[derive(Debug)]
+pub enum WorldError {
+    Load(LoadError),
+    Model(ModelError),
+    Tiled(TiledError),
+    DanglingRefs(Vec<DanglingRef>),
+}
+
+impl Display for WorldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorldError::Load(err) => write!(f, "{}", err),
+            WorldError::Model(err) => write!(f, "{}", err),
+            WorldError::Tiled(err) => write!(f, "{}", err),
+            WorldError::DanglingRefs(refs) => {
+                write!(f, "{} dangling entity reference(s)", refs.len())?;
+                for dangling in refs {
+                    write!(f, "\n  {}", dangling)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for WorldError {}
+
+impl From<LoadError> for WorldError {
+    fn from(err: LoadError) -> Self {
+        WorldError::Load(err)
+    }
+}
+
+impl From<ModelError> for WorldError {
+    fn from(err: ModelError) -> Self {
+        WorldError::Model(err)
+    }
+}
+
+impl From<TiledError> for WorldError {
+    fn from(err: TiledError) -> Self {
+        WorldError::Tiled(err)
+    }
+}
+
+/// Loads an LDtk project or a Tiled map and builds the runtime model of all its worlds.
+/// Fails when an entity reference does not resolve.
+pub fn load_worlds(path: &Path) -> Result<Vec<World>, WorldError> {
+    if tiled::is_tiled_map(path) {
+        return check_refs(vec![tiled::load(path)?]);
+    }
+    worlds_from_project(&Project::load(path)?)
+}
+
+/// Builds the runtime model of all worlds of an already loaded project.
+pub fn worlds_from_project(project: &Project) -> Result<Vec<World>, WorldError> {
+    check_refs(World::from_project(project)?)
+}
+
+fn check_refs(worlds: Vec<World>) -> Result<Vec<World>, WorldError> {
+    let index = IidIndex::new(worlds);
+    let dangling = index.dangling_refs();
+    if !dangling.is_empty() {
+        return Err(WorldError::DanglingRefs(dangling));
+    }
+    Ok(index.into_worlds())
+}
+
+/// Loads a project file and runs the content checks of [`validate::validate`] on it,
+/// or those of [`validate::validate_worlds`] for a Tiled map.
+pub fn validate_project(path: &Path, required_layers: &[String]) -> Result<Vec<Issue>, WorldError> {
+    if tiled::is_tiled_map(path) {
+        return Ok(validate::validate_worlds(path, &[tiled::load(path)?], required_layers));
+    }
+    let project = Project::load(path)?;
+    Ok(validate::validate(&project, required_layers))
+}
+
+/// Finds `.ldtk` project files and Tiled maps in `dir` and its direct subdirectories.
+pub fn find_projects(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut projects = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            for sub_entry in fs::read_dir(&path)? {
+                let sub_path = sub_entry?.path();
+                if is_project(&sub_path) {
+                    projects.push(sub_path);
+                }
+            }
+        } else if is_project(&path) {
+            projects.push(path);
+        }
+    }
+    projects.sort();
+    Ok(projects)
+}
+
+fn is_project(path: &Path) -> bool {
+    path.is_file() && (path.extension().is_some_and(|ext| ext == PROJECT_EXTENSION) || tiled::is_tiled_map(path))
+}
@@ -0,0 +1,690 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt::Display, sync::Arc};
+
+use super::{
+    field::{FieldError, FieldValue, FromFieldValue},
+    geom::{Cell, Point, Rect, Size},
+    ldtk_json::{self, LdtkJson},
+    loader::Project,
+    tile_meta::TileMeta,
+};
+
+#[derive(Debug)]
+pub struct ModelError {
+    /// Iid or identifier of the level, layer or entity that could not be converted.
+    pub location: String,
+    pub message: String,
+}
+
+impl ModelError {
+    fn new(location: &str, message: impl Into<String>) -> Self {
+        Self {
+            location: location.to_owned(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ModelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WorldLayout {
+    Free,
+    GridVania,
+    LinearHorizontal,
+    LinearVertical,
+}
+
+impl From<&ldtk_json::WorldLayout> for WorldLayout {
+    fn from(layout: &ldtk_json::WorldLayout) -> Self {
+        match layout {
+            ldtk_json::WorldLayout::Free => WorldLayout::Free,
+            ldtk_json::WorldLayout::GridVania => WorldLayout::GridVania,
+            ldtk_json::WorldLayout::LinearHorizontal => WorldLayout::LinearHorizontal,
+            ldtk_json::WorldLayout::LinearVertical => WorldLayout::LinearVertical,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct World {
+    pub iid: String,
+    pub identifier: String,
+    pub layout: WorldLayout,
+    pub grid_size: Size,
+    pub levels: Vec<Level>,
+    pub defs: Arc<Definitions>,
+    level_index: HashMap<String, usize>,
+}
+
+impl World {
+
+    /// Builds every world of the project. Worlds share one set of definitions.
+    pub fn from_project(project: &Project) -> Result<Vec<Self>, ModelError> {
+        let defs = Arc::new(Definitions::from_ldtk(project.json()));
+        project.worlds()
+            .into_iter()
+            .map(|world| {
+                let levels = world.levels.iter()
+                    .map(|level| Level::from_ldtk(level, &defs))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Self::new(
+                    world.iid.to_owned(),
+                    world.identifier.to_owned(),
+                    world.layout.map(WorldLayout::from).unwrap_or(WorldLayout::Free),
+                    Size::new(world.grid_width as i32, world.grid_height as i32),
+                    levels,
+                    defs.clone()))
+            })
+            .collect()
+    }
+
+    pub fn new(iid: String, identifier: String, layout: WorldLayout, grid_size: Size, levels: Vec<Level>,
+        defs: Arc<Definitions>) -> Self {
+        let level_index = levels.iter()
+            .enumerate()
+            .map(|(index, level)| (level.iid.clone(), index))
+            .collect();
+        Self {
+            iid,
+            identifier,
+            layout,
+            grid_size,
+            levels,
+            defs,
+            level_index,
+        }
+    }
+
+    pub fn level(&self, iid: &str) -> Option<&Level> {
+        self.level_index.get(iid).map(|&index| &self.levels[index])
+    }
+
+    pub fn level_by_identifier(&self, identifier: &str) -> Option<&Level> {
+        self.levels.iter().find(|level| level.identifier == identifier)
+    }
+
+    /// Returns the level whose world-space bounds contain `point`.
+    pub fn level_at(&self, point: Point) -> Option<&Level> {
+        self.levels.iter().find(|level| level.world_bounds().contains(point))
+    }
+
+    /// Returns the neighbour of `level` whose world-space bounds contain `point`.
+    pub fn neighbour_at(&self, level: &Level, point: Point) -> Option<&Level> {
+        level.neighbours.iter()
+            .filter_map(|neighbour| self.level(&neighbour.level_iid))
+            .find(|neighbour| neighbour.world_bounds().contains(point))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    North,
+    South,
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+    Overlap,
+    Above,
+    Below,
+}
+
+impl Direction {
+    pub fn from_ldtk(dir: &str) -> Option<Self> {
+        Some(match dir {
+            "n" => Direction::North,
+            "s" => Direction::South,
+            "e" => Direction::East,
+            "w" => Direction::West,
+            "ne" => Direction::NorthEast,
+            "nw" => Direction::NorthWest,
+            "se" => Direction::SouthEast,
+            "sw" => Direction::SouthWest,
+            "o" => Direction::Overlap,
+            ">" => Direction::Above,
+            "<" => Direction::Below,
+            _ => return None,
+        })
+    }
+
+    /// The LDtk code of the direction, like `n` or `>`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::North => "n",
+            Direction::South => "s",
+            Direction::East => "e",
+            Direction::West => "w",
+            Direction::NorthEast => "ne",
+            Direction::NorthWest => "nw",
+            Direction::SouthEast => "se",
+            Direction::SouthWest => "sw",
+            Direction::Overlap => "o",
+            Direction::Above => ">",
+            Direction::Below => "<",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Neighbour {
+    pub level_iid: String,
+    pub dir: Direction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Level {
+    pub uid: i64,
+    pub iid: String,
+    pub identifier: String,
+    pub world_pos: Point,
+    pub world_depth: i32,
+    pub size: Size,
+    pub bg_color: String,
+    pub neighbours: Vec<Neighbour>,
+    /// Layers in LDtk order, the top-most layer first.
+    pub layers: Vec<Layer>,
+}
+
+impl Level {
+
+    pub fn from_ldtk(level: &ldtk_json::Level, defs: &Definitions) -> Result<Self, ModelError> {
+        let layer_instances = level.layer_instances.as_ref()
+            .ok_or_else(|| ModelError::new(&level.iid, "level has no layer instances, external level not loaded"))?;
+        let neighbours = level.neighbours.iter()
+            .map(|neighbour| Direction::from_ldtk(&neighbour.dir)
+                .map(|dir| Neighbour { level_iid: neighbour.level_iid.clone(), dir })
+                .ok_or_else(|| ModelError::new(&level.iid, format!("unknown neighbour direction {}", neighbour.dir))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let layers = layer_instances.iter()
+            .map(|layer| Layer::from_ldtk(layer, defs))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            uid: level.uid,
+            iid: level.iid.clone(),
+            identifier: level.identifier.clone(),
+            world_pos: Point::new(level.world_x as i32, level.world_y as i32),
+            world_depth: level.world_depth as i32,
+            size: Size::new(level.px_wid as i32, level.px_hei as i32),
+            bg_color: level.bg_color.clone(),
+            neighbours,
+            layers,
+        })
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::from_pos_size(Point::default(), self.size)
+    }
+
+    pub fn world_bounds(&self) -> Rect {
+        Rect::from_pos_size(self.world_pos, self.size)
+    }
+
+    pub fn to_world(&self, local: Point) -> Point {
+        local.offset(self.world_pos.x, self.world_pos.y)
+    }
+
+    pub fn to_local(&self, world: Point) -> Point {
+        world.offset(-self.world_pos.x, -self.world_pos.y)
+    }
+
+    pub fn layer(&self, identifier: &str) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.identifier == identifier)
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = &Entity> {
+        self.layers.iter().flat_map(|layer| match &layer.kind {
+            LayerKind::Entities { entities } => entities.as_slice(),
+            _ => &[],
+        })
+    }
+
+    pub fn entity(&self, iid: &str) -> Option<&Entity> {
+        self.entities().find(|entity| entity.iid == iid)
+    }
+
+    /// Tiles covering a level-local pixel position, top-most layer first.
+    pub fn tiles_at(&self, point: Point) -> impl Iterator<Item = (&Layer, &Tile)> {
+        self.layers.iter().flat_map(move |layer| layer.tiles().iter()
+            .filter(move |tile| layer.tile_rect(tile).contains(point))
+            .map(move |tile| (layer, tile)))
+    }
+
+    /// Whether any tile at a level-local position is tagged with an enum value.
+    pub fn tile_tagged(&self, defs: &Definitions, point: Point, tag: &str) -> bool {
+        self.tiles_at(point).any(|(layer, tile)| layer.tileset_uid
+            .and_then(|uid| defs.tilesets.get(&uid))
+            .is_some_and(|tileset| tileset.meta.has_tag(tile.id, tag)))
+    }
+
+    /// Custom data value of the top-most tile at a level-local position that has one.
+    pub fn tile_value<'a>(&self, defs: &'a Definitions, point: Point, key: &str) -> Option<&'a serde_json::Value> {
+        self.tiles_at(point).find_map(|(layer, tile)| defs.tilesets.get(&layer.tileset_uid?)?.meta.value(tile.id, key))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Layer {
+    pub iid: String,
+    pub identifier: String,
+    pub def_uid: i64,
+    pub grid_size: i32,
+    /// Size of the layer in cells.
+    pub grid: Size,
+    /// Total pixel offset of the layer, definition and instance offsets combined.
+    pub px_offset: Point,
+    pub opacity: f32,
+    pub visible: bool,
+    pub tileset_uid: Option<i64>,
+    pub kind: LayerKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LayerKind {
+    IntGrid {
+        /// Row-major cell values, 0 means empty.
+        values: Vec<i32>,
+        auto_tiles: Vec<Tile>,
+    },
+    Tiles {
+        tiles: Vec<Tile>,
+    },
+    AutoLayer {
+        tiles: Vec<Tile>,
+    },
+    Entities {
+        entities: Vec<Entity>,
+    },
+}
+
+impl Layer {
+
+    pub fn from_ldtk(layer: &ldtk_json::LayerInstance, defs: &Definitions) -> Result<Self, ModelError> {
+        let tiles = |tiles: &[ldtk_json::TileInstance]| tiles.iter()
+            .map(|tile| Tile::from_ldtk(tile, &layer.iid))
+            .collect::<Result<Vec<_>, _>>();
+        let kind = match layer.layer_instance_type.as_str() {
+            "IntGrid" => {
+                let expected = (layer.c_wid * layer.c_hei) as usize;
+                if layer.int_grid_csv.len() != expected {
+                    return Err(ModelError::new(&layer.iid, format!(
+                        "intGridCsv has {} values, expected {}", layer.int_grid_csv.len(), expected)));
+                }
+                LayerKind::IntGrid {
+                    values: layer.int_grid_csv.iter().map(|&value| value as i32).collect(),
+                    auto_tiles: tiles(&layer.auto_layer_tiles)?,
+                }
+            }
+            "Tiles" => LayerKind::Tiles { tiles: tiles(&layer.grid_tiles)? },
+            "AutoLayer" => LayerKind::AutoLayer { tiles: tiles(&layer.auto_layer_tiles)? },
+            "Entities" => LayerKind::Entities {
+                entities: layer.entity_instances.iter()
+                    .map(|entity| Entity::from_ldtk(entity, defs))
+                    .collect::<Result<Vec<_>, _>>()?,
+            },
+            other => return Err(ModelError::new(&layer.iid, format!("unknown layer type {}", other))),
+        };
+
+        Ok(Self {
+            iid: layer.iid.clone(),
+            identifier: layer.identifier.clone(),
+            def_uid: layer.layer_def_uid,
+            grid_size: layer.grid_size as i32,
+            grid: Size::new(layer.c_wid as i32, layer.c_hei as i32),
+            px_offset: Point::new(layer.px_total_offset_x as i32, layer.px_total_offset_y as i32),
+            opacity: layer.opacity as f32,
+            visible: layer.visible,
+            tileset_uid: layer.override_tileset_uid.or(layer.tileset_def_uid),
+            kind,
+        })
+    }
+
+    pub fn contains_cell(&self, cell: Cell) -> bool {
+        cell.cx >= 0 && cell.cy >= 0 && cell.cx < self.grid.w && cell.cy < self.grid.h
+    }
+
+    /// Value of an IntGrid cell; `None` outside the layer or for other layer kinds.
+    pub fn int_value(&self, cell: Cell) -> Option<i32> {
+        match &self.kind {
+            LayerKind::IntGrid { values, .. } if self.contains_cell(cell) =>
+                values.get((cell.cy * self.grid.w + cell.cx) as usize).copied(),
+            _ => None,
+        }
+    }
+
+    /// Cell under a level-local pixel position, taking the layer offset into account.
+    pub fn cell_at(&self, point: Point) -> Cell {
+        Cell::new(
+            (point.x - self.px_offset.x).div_euclid(self.grid_size),
+            (point.y - self.px_offset.y).div_euclid(self.grid_size))
+    }
+
+    /// Level-local pixel rectangle covered by a cell.
+    pub fn cell_rect(&self, cell: Cell) -> Rect {
+        Rect::new(
+            cell.cx * self.grid_size + self.px_offset.x,
+            cell.cy * self.grid_size + self.px_offset.y,
+            self.grid_size,
+            self.grid_size)
+    }
+
+    /// Level-local pixel rectangle covered by a tile.
+    pub fn tile_rect(&self, tile: &Tile) -> Rect {
+        Rect::new(tile.px.x + self.px_offset.x, tile.px.y + self.px_offset.y, self.grid_size, self.grid_size)
+    }
+
+    /// Tiles to draw for this layer, whatever its kind.
+    pub fn tiles(&self) -> &[Tile] {
+        match &self.kind {
+            LayerKind::IntGrid { auto_tiles, .. } => auto_tiles,
+            LayerKind::Tiles { tiles } | LayerKind::AutoLayer { tiles } => tiles,
+            LayerKind::Entities { .. } => &[],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tile {
+    pub id: i32,
+    /// Layer-local pixel position.
+    pub px: Point,
+    /// Pixel position in the tileset image.
+    pub src: Point,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub alpha: f32,
+}
+
+impl Tile {
+    fn from_ldtk(tile: &ldtk_json::TileInstance, location: &str) -> Result<Self, ModelError> {
+        Ok(Self {
+            id: tile.t as i32,
+            px: point(&tile.px, location, "tile px")?,
+            src: point(&tile.src, location, "tile src")?,
+            flip_x: tile.f & 1 != 0,
+            flip_y: tile.f & 2 != 0,
+            alpha: tile.a as f32,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entity {
+    pub iid: String,
+    pub identifier: String,
+    pub def_uid: i64,
+    pub cell: Cell,
+    /// Level-local pixel position of the pivot.
+    pub px: Point,
+    pub size: Size,
+    pub pivot: (f32, f32),
+    pub tags: Vec<String>,
+    pub fields: Vec<Field>,
+}
+
+impl Entity {
+
+    fn from_ldtk(entity: &ldtk_json::EntityInstance, defs: &Definitions) -> Result<Self, ModelError> {
+        let pivot = match entity.pivot.as_slice() {
+            [x, y] => (*x as f32, *y as f32),
+            _ => return Err(ModelError::new(&entity.iid, "pivot must have 2 values")),
+        };
+        let entity_def = defs.entities.get(&entity.def_uid)
+            .ok_or_else(|| ModelError::new(&entity.iid, format!("unknown entity definition {}", entity.def_uid)))?;
+        let fields = entity.field_instances.iter()
+            .map(|field| Field::from_ldtk(field, entity_def, defs)
+                .map_err(|message| ModelError::new(&entity.iid, format!("field {}: {}", field.identifier, message))))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            iid: entity.iid.clone(),
+            identifier: entity.identifier.clone(),
+            def_uid: entity.def_uid,
+            cell: {
+                let grid = point(&entity.grid, &entity.iid, "grid")?;
+                Cell::new(grid.x, grid.y)
+            },
+            px: point(&entity.px, &entity.iid, "px")?,
+            size: Size::new(entity.width as i32, entity.height as i32),
+            pivot,
+            tags: entity.tags.clone(),
+            fields,
+        })
+    }
+
+    /// Level-local bounding box, computed from the pivot position.
+    pub fn bounds(&self) -> Rect {
+        Rect::new(
+            self.px.x - (self.pivot.0 * self.size.w as f32) as i32,
+            self.px.y - (self.pivot.1 * self.size.h as f32) as i32,
+            self.size.w,
+            self.size.h)
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    pub fn field_value(&self, identifier: &str) -> Option<&FieldValue> {
+        self.fields.iter()
+            .find(|field| field.identifier == identifier)
+            .map(|field| &field.value)
+    }
+
+    /// Reads a field as `T`, like `entity.field::<i64>("amount")`.
+    /// Use `Option<T>` for nullable fields.
+    pub fn field<T: FromFieldValue>(&self, identifier: &str) -> Result<T, FieldError> {
+        let field = self.fields.iter()
+            .find(|field| field.identifier == identifier)
+            .ok_or_else(|| FieldError::Missing {
+                entity: self.describe(),
+                field: identifier.to_owned(),
+            })?;
+        T::from_field_value(&field.value).ok_or_else(|| FieldError::Type {
+            entity: self.describe(),
+            field: identifier.to_owned(),
+            expected: T::EXPECTED,
+            found: if field.value.is_null() { "null".to_owned() } else { field.type_name.clone() },
+        })
+    }
+
+    fn describe(&self) -> String {
+        format!("entity {} ({})", self.identifier, self.iid)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Field {
+    pub identifier: String,
+    pub def_uid: i64,
+    pub type_name: String,
+    #[serde(with = "super::field::tagged")]
+    pub value: FieldValue,
+}
+
+impl Field {
+    fn from_ldtk(field: &ldtk_json::FieldInstance, entity_def: &EntityDef, defs: &Definitions) -> Result<Self, String> {
+        let def = entity_def.fields.iter()
+            .find(|def| def.uid == field.def_uid)
+            .ok_or_else(|| format!("no definition with uid {} in {}", field.def_uid, entity_def.identifier))?;
+        Ok(Self {
+            identifier: field.identifier.clone(),
+            def_uid: field.def_uid,
+            type_name: field.field_instance_type.clone(),
+            value: FieldValue::decode(&field.field_instance_type, def, defs, field.value.as_ref())?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LayerType {
+    IntGrid,
+    Tiles,
+    AutoLayer,
+    Entities,
+}
+
+impl From<&ldtk_json::Type> for LayerType {
+    fn from(layer_type: &ldtk_json::Type) -> Self {
+        match layer_type {
+            ldtk_json::Type::IntGrid => LayerType::IntGrid,
+            ldtk_json::Type::Tiles => LayerType::Tiles,
+            ldtk_json::Type::AutoLayer => LayerType::AutoLayer,
+            ldtk_json::Type::Entities => LayerType::Entities,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntGridValueDef {
+    pub value: i32,
+    pub identifier: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerDef {
+    pub uid: i64,
+    pub identifier: String,
+    pub layer_type: LayerType,
+    pub grid_size: i32,
+    pub tileset_uid: Option<i64>,
+    pub int_grid_values: Vec<IntGridValueDef>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldDef {
+    pub uid: i64,
+    pub identifier: String,
+    pub type_name: String,
+    pub is_array: bool,
+    pub can_be_null: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityDef {
+    pub uid: i64,
+    pub identifier: String,
+    pub size: Size,
+    pub tags: Vec<String>,
+    pub fields: Vec<FieldDef>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TilesetDef {
+    pub uid: i64,
+    pub identifier: String,
+    pub rel_path: Option<String>,
+    pub size: Size,
+    pub tile_grid_size: i32,
+    pub spacing: i32,
+    pub padding: i32,
+    /// Size of the tileset in tiles.
+    pub grid: Size,
+    /// Enum tags and custom data of the tiles.
+    pub meta: TileMeta,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnumDef {
+    pub uid: i64,
+    pub identifier: String,
+    pub values: Vec<String>,
+}
+
+/// Project definitions, indexed by uid.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Definitions {
+    pub layers: HashMap<i64, LayerDef>,
+    pub entities: HashMap<i64, EntityDef>,
+    pub tilesets: HashMap<i64, TilesetDef>,
+    pub enums: HashMap<i64, EnumDef>,
+}
+
+impl Definitions {
+
+    pub fn from_ldtk(json: &LdtkJson) -> Self {
+        let defs = &json.defs;
+        let field_def = |field: &ldtk_json::FieldDefinition| FieldDef {
+            uid: field.uid,
+            identifier: field.identifier.clone(),
+            type_name: field.field_definition_type.clone(),
+            is_array: field.is_array,
+            can_be_null: field.can_be_null,
+        };
+
+        Self {
+            layers: defs.layers.iter()
+                .map(|layer| (layer.uid, LayerDef {
+                    uid: layer.uid,
+                    identifier: layer.identifier.clone(),
+                    layer_type: LayerType::from(&layer.purple_type),
+                    grid_size: layer.grid_size as i32,
+                    tileset_uid: layer.tileset_def_uid,
+                    int_grid_values: layer.int_grid_values.iter()
+                        .map(|value| IntGridValueDef {
+                            value: value.value as i32,
+                            identifier: value.identifier.clone(),
+                        })
+                        .collect(),
+                }))
+                .collect(),
+            entities: defs.entities.iter()
+                .map(|entity| (entity.uid, EntityDef {
+                    uid: entity.uid,
+                    identifier: entity.identifier.clone(),
+                    size: Size::new(entity.width as i32, entity.height as i32),
+                    tags: entity.tags.clone(),
+                    fields: entity.field_defs.iter().map(field_def).collect(),
+                }))
+                .collect(),
+            tilesets: defs.tilesets.iter()
+                .map(|tileset| (tileset.uid, TilesetDef {
+                    uid: tileset.uid,
+                    identifier: tileset.identifier.clone(),
+                    rel_path: tileset.rel_path.clone(),
+                    size: Size::new(tileset.px_wid as i32, tileset.px_hei as i32),
+                    tile_grid_size: tileset.tile_grid_size as i32,
+                    spacing: tileset.spacing as i32,
+                    padding: tileset.padding as i32,
+                    grid: Size::new(tileset.c_wid as i32, tileset.c_hei as i32),
+                    meta: TileMeta::from_ldtk(tileset),
+                }))
+                .collect(),
+            enums: defs.enums.iter()
+                .chain(defs.external_enums.iter())
+                .map(|def| (def.uid, EnumDef {
+                    uid: def.uid,
+                    identifier: def.identifier.clone(),
+                    values: def.values.iter().map(|value| value.id.clone()).collect(),
+                }))
+                .collect(),
+        }
+    }
+
+    pub fn layer_by_identifier(&self, identifier: &str) -> Option<&LayerDef> {
+        self.layers.values().find(|layer| layer.identifier == identifier)
+    }
+
+    pub fn entity_by_identifier(&self, identifier: &str) -> Option<&EntityDef> {
+        self.entities.values().find(|entity| entity.identifier == identifier)
+    }
+
+    pub fn enum_by_identifier(&self, identifier: &str) -> Option<&EnumDef> {
+        self.enums.values().find(|def| def.identifier == identifier)
+    }
+}
+
+fn point(values: &[i64], location: &str, what: &str) -> Result<Point, ModelError> {
+    match values {
+        [x, y] => Ok(Point::new(*x as i32, *y as i32)),
+        _ => Err(ModelError::new(location, format!("{} must have 2 values, got {}", what, values.len()))),
+    }
+}
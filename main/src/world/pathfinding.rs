@@ -0,0 +1,497 @@
+use serde::Deserialize;
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use crate::config::PathfindingConfig;
+
+use super::{
+    collision::CollisionMap,
+    geom::{Cell, Vec2},
+    model::{Level, World},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagonalPolicy {
+    Never,
+    /// Diagonal steps only when both adjacent orthogonal cells are passable.
+    #[default]
+    NoCornerCutting,
+    /// Diagonal steps when at least one adjacent orthogonal cell is passable.
+    AllowCornerCutting,
+    Always,
+}
+
+/// A cell of a specific level.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NavPoint {
+    pub level_iid: String,
+    pub cell: Cell,
+}
+
+impl NavPoint {
+    pub fn new(level_iid: impl Into<String>, cell: Cell) -> Self {
+        Self {
+            level_iid: level_iid.into(),
+            cell,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PathStep {
+    pub level_iid: String,
+    pub cell: Cell,
+    /// World-space pixel position of the cell center.
+    pub world: Vec2,
+}
+
+#[derive(Debug, Clone)]
+pub struct Path {
+    pub steps: Vec<PathStep>,
+    pub cost: f32,
+}
+
+/// Levels of a world with their collision maps. Levels without a map are not walkable.
+pub struct NavGraph<'a> {
+    levels: Vec<(&'a Level, &'a CollisionMap)>,
+    index: HashMap<&'a str, usize>,
+}
+
+impl<'a> NavGraph<'a> {
+
+    pub fn new(world: &'a World, maps: &'a HashMap<String, CollisionMap>) -> Self {
+        let levels: Vec<_> = world.levels.iter()
+            .filter_map(|level| maps.get(&level.iid).map(|map| (level, map)))
+            .collect();
+        let index = levels.iter()
+            .enumerate()
+            .map(|(index, (level, _))| (level.iid.as_str(), index))
+            .collect();
+        Self { levels, index }
+    }
+
+    fn world_center(&self, node: Node) -> Vec2 {
+        let (level, map) = self.levels[node.level];
+        let center = map.cell_center(node.cell);
+        Vec2::new(center.x + level.world_pos.x as f32, center.y + level.world_pos.y as f32)
+    }
+
+    /// Finds the node at `cell` of `level`, following GridVania neighbours
+    /// when the cell lies outside the level.
+    fn resolve(&self, level: usize, cell: Cell) -> Option<Node> {
+        let (current, map) = self.levels[level];
+        if map.contains(cell) {
+            return Some(Node { level, cell });
+        }
+        let center = map.cell_center(cell);
+        let world = Vec2::new(center.x + current.world_pos.x as f32, center.y + current.world_pos.y as f32);
+        current.neighbours.iter()
+            .filter_map(|neighbour| self.index.get(neighbour.level_iid.as_str()).copied())
+            .find_map(|index| {
+                let (next, next_map) = self.levels[index];
+                let local = Vec2::new(world.x - next.world_pos.x as f32, world.y - next.world_pos.y as f32);
+                let next_cell = next_map.cell_at(local);
+                next_map.contains(next_cell).then_some(Node { level: index, cell: next_cell })
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Node {
+    level: usize,
+    cell: Cell,
+}
+
+struct OpenEntry {
+    node: Node,
+    f: f32,
+}
+
+impl PartialEq for OpenEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.f == other.f
+    }
+}
+
+impl Eq for OpenEntry {}
+
+impl PartialOrd for OpenEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenEntry {
+    // Reversed so the max-heap pops the lowest f first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.f.total_cmp(&self.f)
+    }
+}
+
+enum Search {
+    Found(Path),
+    /// Every reachable cell was expanded, or an endpoint is not walkable.
+    Unreachable,
+    /// Stopped after `max_expanded` cells; a later search may still succeed.
+    GaveUp,
+}
+
+const ORTHOGONAL: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const DIAGONAL: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+/// A* pathfinder with a bounded cache of recent results. Clear the cache
+/// whenever the collision maps it was used with change.
+pub struct Pathfinder {
+    config: PathfindingConfig,
+    cache: Mutex<PathCache>,
+}
+
+impl Pathfinder {
+
+    pub fn new(config: PathfindingConfig) -> Self {
+        let cache = PathCache::new(config.cache_size);
+        Self {
+            config,
+            cache: Mutex::new(cache),
+        }
+    }
+
+    pub fn clear_cache(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Searches that give up after `max_expanded` cells are not cached, so the
+    /// same request is searched again rather than reported unreachable.
+    pub fn find_path(&self, graph: &NavGraph, from: &NavPoint, to: &NavPoint) -> Option<Arc<Path>> {
+        let key = (from.clone(), to.clone());
+        if let Some(cached) = self.cache.lock().unwrap().get(&key) {
+            return cached;
+        }
+        let path = match self.search(graph, from, to) {
+            Search::Found(path) => Some(Arc::new(path)),
+            Search::Unreachable => None,
+            Search::GaveUp => return None,
+        };
+        self.cache.lock().unwrap().insert(key, path.clone());
+        path
+    }
+
+    fn search(&self, graph: &NavGraph, from: &NavPoint, to: &NavPoint) -> Search {
+        let Some((start, goal)) = self.endpoints(graph, from, to) else {
+            return Search::Unreachable;
+        };
+
+        let goal_world = graph.world_center(goal);
+        let grid_size = graph.levels[goal.level].1.grid_size() as f32;
+        let heuristic = |node: Node| {
+            let world = graph.world_center(node);
+            let dx = (world.x - goal_world.x).abs() / grid_size;
+            let dy = (world.y - goal_world.y).abs() / grid_size;
+            self.distance(dx, dy) * self.config.min_cost()
+        };
+
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<Node, Node> = HashMap::new();
+        let mut g_score: HashMap<Node, f32> = HashMap::from([(start, 0.0)]);
+        open.push(OpenEntry { node: start, f: heuristic(start) });
+        let mut expanded = 0;
+
+        while let Some(OpenEntry { node, f }) = open.pop() {
+            if node == goal {
+                return Search::Found(self.reconstruct(graph, &came_from, goal, g_score[&goal]));
+            }
+            let g = g_score[&node];
+            if f > g + heuristic(node) + f32::EPSILON {
+                continue;
+            }
+            expanded += 1;
+            if expanded > self.config.max_expanded {
+                return Search::GaveUp;
+            }
+            for (next, step_cost) in self.neighbours(graph, node) {
+                let tentative = g + step_cost;
+                if g_score.get(&next).is_none_or(|&known| tentative < known) {
+                    came_from.insert(next, node);
+                    g_score.insert(next, tentative);
+                    open.push(OpenEntry { node: next, f: tentative + heuristic(next) });
+                }
+            }
+        }
+        Search::Unreachable
+    }
+
+    /// Start and goal nodes, `None` when either is not on a walkable cell of the graph.
+    fn endpoints(&self, graph: &NavGraph, from: &NavPoint, to: &NavPoint) -> Option<(Node, Node)> {
+        let start = Node { level: *graph.index.get(from.level_iid.as_str())?, cell: from.cell };
+        let goal = Node { level: *graph.index.get(to.level_iid.as_str())?, cell: to.cell };
+        self.cost(graph, start)?;
+        self.cost(graph, goal)?;
+        Some((start, goal))
+    }
+
+    fn neighbours(&self, graph: &NavGraph, node: Node) -> Vec<(Node, f32)> {
+        let mut result = Vec::with_capacity(8);
+        let step = |dx: i32, dy: i32| graph.resolve(node.level, Cell::new(node.cell.cx + dx, node.cell.cy + dy))
+            .and_then(|next| self.cost(graph, next).map(|cost| (next, cost)));
+
+        for (dx, dy) in ORTHOGONAL {
+            if let Some((next, cost)) = step(dx, dy) {
+                result.push((next, cost));
+            }
+        }
+        if self.config.diagonal == DiagonalPolicy::Never {
+            return result;
+        }
+        for (dx, dy) in DIAGONAL {
+            let Some((next, cost)) = step(dx, dy) else {
+                continue;
+            };
+            let open_sides = [step(dx, 0), step(0, dy)].iter().filter(|side| side.is_some()).count();
+            let allowed = match self.config.diagonal {
+                DiagonalPolicy::Never => false,
+                DiagonalPolicy::NoCornerCutting => open_sides == 2,
+                DiagonalPolicy::AllowCornerCutting => open_sides >= 1,
+                DiagonalPolicy::Always => true,
+            };
+            if allowed {
+                result.push((next, cost * std::f32::consts::SQRT_2));
+            }
+        }
+        result
+    }
+
+    /// Cost of entering a cell, `None` when its terrain has no configured cost.
+    fn cost(&self, graph: &NavGraph, node: Node) -> Option<f32> {
+        let (_, map) = graph.levels[node.level];
+        self.config.costs.get(&map.terrain(node.cell)).copied()
+    }
+
+    fn distance(&self, dx: f32, dy: f32) -> f32 {
+        match self.config.diagonal {
+            DiagonalPolicy::Never => dx + dy,
+            _ => dx.max(dy) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dy),
+        }
+    }
+
+    fn reconstruct(&self, graph: &NavGraph, came_from: &HashMap<Node, Node>, goal: Node, cost: f32) -> Path {
+        let mut nodes = vec![goal];
+        let mut current = goal;
+        while let Some(&previous) = came_from.get(&current) {
+            nodes.push(previous);
+            current = previous;
+        }
+        nodes.reverse();
+        Path {
+            steps: nodes.into_iter()
+                .map(|node| PathStep {
+                    level_iid: graph.levels[node.level].0.iid.clone(),
+                    cell: node.cell,
+                    world: graph.world_center(node),
+                })
+                .collect(),
+            cost,
+        }
+    }
+}
+
+type CacheKey = (NavPoint, NavPoint);
+
+/// Keeps the most recently inserted results, evicting the oldest first.
+struct PathCache {
+    capacity: usize,
+    entries: HashMap<CacheKey, Option<Arc<Path>>>,
+    order: VecDeque<CacheKey>,
+}
+
+impl PathCache {
+
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<Option<Arc<Path>>> {
+        self.entries.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: CacheKey, path: Option<Arc<Path>>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.insert(key.clone(), path).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        collision::Terrain,
+        geom::{Point, Size},
+        model::{Direction, Neighbour, WorldLayout},
+    };
+
+    /// A level at `world_pos` with a 16px collision map from rows of `.`, `~` and `#`.
+    fn level(iid: &str, world_pos: Point, rows: &[&str], neighbours: &[(&str, Direction)]) -> (Level, CollisionMap) {
+        let size = Size::new(rows[0].len() as i32, rows.len() as i32);
+        let cells = rows.iter()
+            .flat_map(|row| row.chars())
+            .map(|c| match c {
+                '#' => Terrain::Solid,
+                '~' => Terrain::Slow,
+                _ => Terrain::Floor,
+            })
+            .collect();
+        let level = Level {
+            uid: 0,
+            iid: iid.to_owned(),
+            identifier: iid.to_owned(),
+            world_pos,
+            world_depth: 0,
+            size: Size::new(size.w * 16, size.h * 16),
+            bg_color: String::new(),
+            neighbours: neighbours.iter()
+                .map(|(level_iid, dir)| Neighbour { level_iid: (*level_iid).to_owned(), dir: *dir })
+                .collect(),
+            layers: Vec::new(),
+        };
+        (level, CollisionMap::new(16, Point::default(), size, cells, 0.5))
+    }
+
+    fn world(levels: Vec<(Level, CollisionMap)>) -> (World, HashMap<String, CollisionMap>) {
+        let maps = levels.iter().map(|(level, map)| (level.iid.clone(), map.clone())).collect();
+        let levels = levels.into_iter().map(|(level, _)| level).collect();
+        (World::new("world".to_owned(), "World".to_owned(), WorldLayout::GridVania, Size::new(32, 32), levels,
+            Default::default()), maps)
+    }
+
+    fn pathfinder(diagonal: DiagonalPolicy) -> Pathfinder {
+        Pathfinder::new(PathfindingConfig { diagonal, ..Default::default() })
+    }
+
+    fn cells(path: &Path) -> Vec<(i32, i32)> {
+        path.steps.iter().map(|step| (step.cell.cx, step.cell.cy)).collect()
+    }
+
+    fn point(cx: i32, cy: i32) -> NavPoint {
+        NavPoint::new("a", Cell::new(cx, cy))
+    }
+
+    #[test]
+    fn diagonal_policies_around_corners() {
+        let (one_wall, one_wall_maps) = world(vec![level("a", Point::default(), &[
+            "..",
+            "#.",
+        ], &[])]);
+        let graph = NavGraph::new(&one_wall, &one_wall_maps);
+        let path = pathfinder(DiagonalPolicy::NoCornerCutting).find_path(&graph, &point(0, 0), &point(1, 1)).unwrap();
+        assert_eq!(cells(&path), [(0, 0), (1, 0), (1, 1)]);
+        assert_eq!(path.cost, 2.0);
+        let path = pathfinder(DiagonalPolicy::AllowCornerCutting).find_path(&graph, &point(0, 0), &point(1, 1)).unwrap();
+        assert_eq!(cells(&path), [(0, 0), (1, 1)]);
+        assert_eq!(path.cost, std::f32::consts::SQRT_2);
+
+        let (two_walls, two_walls_maps) = world(vec![level("a", Point::default(), &[
+            ".#",
+            "#.",
+        ], &[])]);
+        let graph = NavGraph::new(&two_walls, &two_walls_maps);
+        assert!(pathfinder(DiagonalPolicy::AllowCornerCutting).find_path(&graph, &point(0, 0), &point(1, 1)).is_none());
+        assert!(pathfinder(DiagonalPolicy::Always).find_path(&graph, &point(0, 0), &point(1, 1)).is_some());
+    }
+
+    #[test]
+    fn terrain_costs_steer_the_path() {
+        let (world, maps) = world(vec![level("a", Point::default(), &[
+            "...",
+            ".~.",
+            "...",
+        ], &[])]);
+        let graph = NavGraph::new(&world, &maps);
+        let path = pathfinder(DiagonalPolicy::Never).find_path(&graph, &point(0, 1), &point(2, 1)).unwrap();
+        assert_eq!(cells(&path), [(0, 1), (1, 1), (2, 1)]);
+        assert_eq!(path.cost, 3.0);
+
+        let mut config = PathfindingConfig { diagonal: DiagonalPolicy::Never, ..Default::default() };
+        config.costs.insert(Terrain::Slow, 5.0);
+        let path = Pathfinder::new(config).find_path(&graph, &point(0, 1), &point(2, 1)).unwrap();
+        assert!(!cells(&path).contains(&(1, 1)));
+        assert_eq!(path.cost, 4.0);
+    }
+
+    #[test]
+    fn paths_cross_into_neighbour_levels() {
+        let (world, maps) = world(vec![
+            level("a", Point::new(0, 0), &[
+                "..",
+                ".#",
+            ], &[("b", Direction::East)]),
+            level("b", Point::new(32, 0), &[
+                "..",
+                "..",
+            ], &[("a", Direction::West)]),
+        ]);
+        let graph = NavGraph::new(&world, &maps);
+        let path = pathfinder(DiagonalPolicy::Never)
+            .find_path(&graph, &point(0, 1), &NavPoint::new("b", Cell::new(1, 1)))
+            .unwrap();
+        let steps: Vec<(&str, i32, i32)> = path.steps.iter()
+            .map(|step| (step.level_iid.as_str(), step.cell.cx, step.cell.cy))
+            .collect();
+        assert_eq!(steps, [("a", 0, 1), ("a", 0, 0), ("a", 1, 0), ("b", 0, 0), ("b", 1, 0), ("b", 1, 1)]);
+        assert_eq!(path.steps[3].world, Vec2::new(40.0, 8.0));
+        assert_eq!(path.cost, 5.0);
+    }
+
+    #[test]
+    fn searches_that_give_up_are_not_cached() {
+        let pathfinder = Pathfinder::new(PathfindingConfig {
+            diagonal: DiagonalPolicy::Never,
+            max_expanded: 4,
+            ..Default::default()
+        });
+        let (detour, detour_maps) = world(vec![level("a", Point::default(), &[
+            ".#...",
+            ".#.#.",
+            "...#.",
+        ], &[])]);
+        let (open, open_maps) = world(vec![level("a", Point::default(), &[
+            ".....",
+            ".....",
+            ".....",
+        ], &[])]);
+        let (closed, closed_maps) = world(vec![level("a", Point::default(), &[
+            ".#...",
+            "##...",
+            ".....",
+        ], &[])]);
+
+        assert!(pathfinder.find_path(&NavGraph::new(&detour, &detour_maps), &point(0, 0), &point(4, 0)).is_none());
+        assert!(pathfinder.find_path(&NavGraph::new(&open, &open_maps), &point(0, 0), &point(4, 0)).is_some());
+
+        // A completed search that found no path is cached until the cache is cleared.
+        assert!(pathfinder.find_path(&NavGraph::new(&closed, &closed_maps), &point(0, 0), &point(0, 2)).is_none());
+        assert!(pathfinder.find_path(&NavGraph::new(&open, &open_maps), &point(0, 0), &point(0, 2)).is_none());
+        pathfinder.clear_cache();
+        assert!(pathfinder.find_path(&NavGraph::new(&open, &open_maps), &point(0, 0), &point(0, 2)).is_some());
+    }
+}
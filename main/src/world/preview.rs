@@ -0,0 +1,301 @@
+use image::{imageops, ImageFormat, Rgba, RgbaImage};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{hash_map::Entry, BTreeSet, HashMap},
+    fmt::Display,
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use super::{
+    cache::LEVEL_EXTENSION,
+    field::Color,
+    model::{Definitions, Level},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewSize {
+    /// Scaled down so the longest side is at most the configured thumbnail size.
+    Thumbnail,
+    Full,
+}
+
+impl PreviewSize {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PreviewSize::Thumbnail => "thumb",
+            PreviewSize::Full => "full",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PreviewError {
+    Io(PathBuf, std::io::Error),
+    Image(PathBuf, image::ImageError),
+    UnknownTileset(i64),
+}
+
+impl Display for PreviewError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreviewError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            PreviewError::Image(path, err) => write!(f, "{}: {}", path.display(), err),
+            PreviewError::UnknownTileset(uid) => write!(f, "tileset {} has no image", uid),
+        }
+    }
+}
+
+impl std::error::Error for PreviewError {}
+
+/// Composites the visible tile layers of a level, bottom layer first, over its background color.
+/// Tileset paths in `defs` are resolved against `project_dir`.
+pub fn render_level(level: &Level, defs: &Definitions, project_dir: &Path) -> Result<RgbaImage, PreviewError> {
+    let background = Color::from_hex(&level.bg_color)
+        .map(|color| Rgba([color.r, color.g, color.b, 255]))
+        .unwrap_or(Rgba([0, 0, 0, 255]));
+    let mut canvas = RgbaImage::from_pixel(level.size.w.max(1) as u32, level.size.h.max(1) as u32, background);
+    let mut tilesets: HashMap<i64, RgbaImage> = HashMap::new();
+
+    for layer in level.layers.iter().rev().filter(|layer| layer.visible) {
+        let (Some(tileset_uid), tiles) = (layer.tileset_uid, layer.tiles()) else {
+            continue;
+        };
+        if tiles.is_empty() {
+            continue;
+        }
+        let tileset_def = defs.tilesets.get(&tileset_uid).ok_or(PreviewError::UnknownTileset(tileset_uid))?;
+        let tileset = match tilesets.entry(tileset_uid) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let rel_path = tileset_def.rel_path.as_deref().ok_or(PreviewError::UnknownTileset(tileset_uid))?;
+                let path = project_dir.join(rel_path);
+                let image = image::open(&path).map_err(|err| PreviewError::Image(path, err))?;
+                entry.insert(image.into_rgba8())
+            }
+        };
+
+        let size = tileset_def.tile_grid_size as u32;
+        for tile in tiles {
+            if tile.src.x < 0 || tile.src.y < 0 {
+                continue;
+            }
+            let mut source = imageops::crop_imm(&*tileset, tile.src.x as u32, tile.src.y as u32, size, size).to_image();
+            if tile.flip_x {
+                imageops::flip_horizontal_in_place(&mut source);
+            }
+            if tile.flip_y {
+                imageops::flip_vertical_in_place(&mut source);
+            }
+            let alpha = tile.alpha * layer.opacity;
+            blend(&mut canvas, &source,
+                (tile.px.x + layer.px_offset.x) as i64,
+                (tile.px.y + layer.px_offset.y) as i64,
+                alpha);
+        }
+    }
+    Ok(canvas)
+}
+
+/// Source-over blending of `source` onto `canvas`, with an extra alpha multiplier.
+fn blend(canvas: &mut RgbaImage, source: &RgbaImage, x: i64, y: i64, alpha: f32) {
+    for (sx, sy, pixel) in source.enumerate_pixels() {
+        let (cx, cy) = (x + sx as i64, y + sy as i64);
+        if cx < 0 || cy < 0 || cx >= canvas.width() as i64 || cy >= canvas.height() as i64 {
+            continue;
+        }
+        let src_alpha = pixel[3] as f32 / 255.0 * alpha;
+        if src_alpha <= 0.0 {
+            continue;
+        }
+        let target = canvas.get_pixel_mut(cx as u32, cy as u32);
+        let dst_alpha = target[3] as f32 / 255.0;
+        let out_alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
+        for channel in 0..3 {
+            let blended = (pixel[channel] as f32 * src_alpha
+                + target[channel] as f32 * dst_alpha * (1.0 - src_alpha)) / out_alpha;
+            target[channel] = blended.round() as u8;
+        }
+        target[3] = (out_alpha * 255.0).round() as u8;
+    }
+}
+
+/// Rendered previews stored as PNG files, named after the level, the size and
+/// a hash of the files the preview is drawn from: the project file, its external
+/// levels and the tileset images of the level. An edit to any of them renders anew.
+pub struct PreviewCache {
+    dir: PathBuf,
+    thumbnail_size: u32,
+    /// Content hashes of source files, recomputed when their mtime changes.
+    hashes: Mutex<HashMap<PathBuf, (SystemTime, [u8; 8])>>,
+}
+
+impl PreviewCache {
+
+    pub fn new(dir: PathBuf, thumbnail_size: u32) -> Self {
+        Self {
+            dir,
+            thumbnail_size,
+            hashes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the PNG bytes of a preview, rendering and storing it on a cache miss.
+    pub fn get(&self, project_path: &Path, level: &Level, defs: &Definitions, size: PreviewSize)
+        -> Result<Vec<u8>, PreviewError> {
+        let hash = self.source_key(project_path, level, defs)?;
+        let prefix = format!("{}-{}-", level.iid, size.as_str());
+        let path = self.dir.join(format!("{}{}.png", prefix, hash));
+        if let Ok(bytes) = fs::read(&path) {
+            return Ok(bytes);
+        }
+
+        let project_dir = project_path.parent().unwrap_or(Path::new(""));
+        let mut image = render_level(level, defs, project_dir)?;
+        if size == PreviewSize::Thumbnail {
+            let longest = image.width().max(image.height());
+            if longest > self.thumbnail_size {
+                let scale = self.thumbnail_size as f32 / longest as f32;
+                let (width, height) = (
+                    ((image.width() as f32 * scale).round() as u32).max(1),
+                    ((image.height() as f32 * scale).round() as u32).max(1));
+                image = imageops::resize(&image, width, height, imageops::FilterType::Triangle);
+            }
+        }
+        let mut bytes = Vec::new();
+        image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .map_err(|err| PreviewError::Image(path.clone(), err))?;
+
+        self.store(&path, &prefix, &bytes);
+        Ok(bytes)
+    }
+
+    fn source_key(&self, project_path: &Path, level: &Level, defs: &Definitions) -> Result<String, PreviewError> {
+        let project_dir = project_path.parent().unwrap_or(Path::new(""));
+        let mut sources = vec![project_path.to_owned()];
+        // LDtk saves external levels next to the project, in a directory named after it.
+        // Which file holds this level is only known from the project, so all of them count.
+        let levels_dir = project_path.with_extension("");
+        if let Ok(entries) = fs::read_dir(&levels_dir) {
+            let mut levels: Vec<PathBuf> = entries.flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == LEVEL_EXTENSION))
+                .collect();
+            levels.sort();
+            sources.extend(levels);
+        }
+        let tilesets: BTreeSet<i64> = level.layers.iter().filter_map(|layer| layer.tileset_uid).collect();
+        sources.extend(tilesets.into_iter()
+            .filter_map(|uid| defs.tilesets.get(&uid)?.rel_path.as_deref())
+            .map(|rel_path| project_dir.join(rel_path)));
+
+        let mut hasher = Sha256::new();
+        for source in &sources {
+            hasher.update(self.file_hash(source)?);
+        }
+        Ok(hasher.finalize()[..8].iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    fn file_hash(&self, path: &Path) -> Result<[u8; 8], PreviewError> {
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|err| PreviewError::Io(path.to_owned(), err))?;
+        if let Some((hashed_at, hash)) = self.hashes.lock().unwrap().get(path) {
+            if *hashed_at == modified {
+                return Ok(*hash);
+            }
+        }
+        let data = fs::read(path).map_err(|err| PreviewError::Io(path.to_owned(), err))?;
+        let mut hash = [0; 8];
+        hash.copy_from_slice(&Sha256::digest(&data)[..8]);
+        self.hashes.lock().unwrap().insert(path.to_owned(), (modified, hash));
+        Ok(hash)
+    }
+
+    /// Writes a rendered preview and removes older renders of the same level and size.
+    /// Failures only cost a re-render later, so they are logged.
+    fn store(&self, path: &Path, prefix: &str, bytes: &[u8]) {
+        if let Err(err) = fs::create_dir_all(&self.dir) {
+            tracing::warn!("Cannot create preview cache {}: {}", self.dir.display(), err);
+            return;
+        }
+        if let Ok(entries) = fs::read_dir(&self.dir) {
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                if name.to_string_lossy().starts_with(prefix) {
+                    let _ = fs::remove_file(entry.path());
+                }
+            }
+        }
+        if let Err(err) = fs::write(path, bytes) {
+            tracing::warn!("Cannot write preview {}: {}", path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, time::Duration};
+
+    use super::*;
+    use crate::world::{
+        load_worlds,
+        test_util::{copy_sample_project, TempDir},
+    };
+
+    /// Rewrites a file with `data` and moves its mtime forward.
+    fn edit(path: &Path, data: &[u8]) {
+        fs::write(path, data).unwrap();
+        let modified = fs::metadata(path).unwrap().modified().unwrap() + Duration::from_secs(1);
+        File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+    }
+
+    #[test]
+    fn key_follows_the_files_a_preview_is_drawn_from() {
+        let dir = TempDir::new("preview-key");
+        let project = copy_sample_project(&dir);
+        let world = load_worlds(&project).unwrap().swap_remove(0);
+        let level = &world.levels[0];
+        let cache = PreviewCache::new(project.with_file_name("previews"), 64);
+        let key = cache.source_key(&project, level, &world.defs).unwrap();
+        assert_eq!(cache.source_key(&project, level, &world.defs).unwrap(), key);
+
+        let image = project.with_file_name("TopDown_by_deepnight.png");
+        let mut data = fs::read(&image).unwrap();
+        data.push(0);
+        edit(&image, &data);
+        let after_tileset = cache.source_key(&project, level, &world.defs).unwrap();
+        assert_ne!(after_tileset, key);
+
+        let levels_dir = project.with_extension("");
+        fs::create_dir_all(&levels_dir).unwrap();
+        fs::write(levels_dir.join("Level_0.ldtkl"), "{}").unwrap();
+        let after_level = cache.source_key(&project, level, &world.defs).unwrap();
+        assert_ne!(after_level, after_tileset);
+        edit(&levels_dir.join("Level_0.ldtkl"), b"{ }");
+        assert_ne!(cache.source_key(&project, level, &world.defs).unwrap(), after_level);
+    }
+
+    #[test]
+    fn renders_once_per_key() {
+        let dir = TempDir::new("preview-render");
+        let project = copy_sample_project(&dir);
+        let world = load_worlds(&project).unwrap().swap_remove(0);
+        let level = &world.levels[0];
+        let previews = project.with_file_name("previews");
+        let cache = PreviewCache::new(previews.clone(), 64);
+        let thumbnail = cache.get(&project, level, &world.defs, PreviewSize::Thumbnail).unwrap();
+        let image = image::load_from_memory(&thumbnail).unwrap();
+        assert_eq!(image.width().max(image.height()), 64);
+        assert_eq!(fs::read_dir(&previews).unwrap().count(), 1);
+
+        let mut data = fs::read(&project).unwrap();
+        data.push(b'\n');
+        edit(&project, &data);
+        cache.get(&project, level, &world.defs, PreviewSize::Thumbnail).unwrap();
+        assert_eq!(fs::read_dir(&previews).unwrap().count(), 1, "older renders are removed");
+    }
+}
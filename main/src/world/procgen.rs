@@ -0,0 +1,638 @@
+use std::{collections::VecDeque, fmt::Display, str::FromStr};
+
+use serde_json::Value;
+
+use super::{
+    auto_layer::{self, RuleError},
+    ldtk_json::{
+        EntityDefinition, EntityInstance, FieldDefinition, FieldInstance, LayerDefinition, LayerInstance, LdtkJson,
+        Level, WorldLayout,
+    },
+};
+
+/// Entity definitions with this tag are the pool random encounters are drawn from.
+pub const ENCOUNTER_TAG: &str = "encounter";
+pub const MIN_SIZE: i64 = 8;
+
+const MIN_ROOM: i64 = 4;
+const MAX_ROOM: i64 = 10;
+/// Level area per room attempted by [`Algorithm::Rooms`].
+const AREA_PER_ROOM: i64 = 80;
+const CAVE_FILL: f64 = 0.45;
+const CAVE_STEPS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    /// Rectangular rooms joined by L-shaped corridors.
+    #[default]
+    Rooms,
+    /// Cellular automaton caves, reduced to their largest connected region.
+    Caves,
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "rooms" => Ok(Algorithm::Rooms),
+            "caves" => Ok(Algorithm::Caves),
+            other => Err(format!("unknown algorithm {}, expected rooms or caves", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GenParams {
+    pub algorithm: Algorithm,
+    /// The same seed, parameters and project always generate the same level.
+    pub seed: u64,
+    /// In cells of the collision layer.
+    pub width: i64,
+    pub height: i64,
+    pub identifier: String,
+    /// IntGrid layer the walls are written to; auto-layers built on it render them.
+    pub collision_layer: String,
+    /// Defaults to the first value of the collision layer.
+    pub wall_value: Option<i64>,
+    pub entity_layer: String,
+    /// Entities placed by identifier, with how many of each.
+    pub placements: Vec<(String, u32)>,
+    /// Entities drawn from the definitions tagged [`ENCOUNTER_TAG`], none when there are none.
+    pub encounters: u32,
+}
+
+impl Default for GenParams {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::Rooms,
+            seed: 0,
+            width: 48,
+            height: 32,
+            identifier: "Generated".to_owned(),
+            collision_layer: "Collisions".to_owned(),
+            wall_value: None,
+            entity_layer: "Entities".to_owned(),
+            placements: vec![("Key".to_owned(), 1), ("Heal".to_owned(), 2)],
+            encounters: 6,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GenError {
+    MissingLayer(String),
+    /// The collision layer is not an IntGrid layer or has no value to draw walls with.
+    NoWallValue(String),
+    MissingEntity(String),
+    /// A field that cannot be null and has no default to place the entity with.
+    RequiredField { entity: String, field: String },
+    TooSmall { width: i64, height: i64 },
+    NoSpace { needed: usize, floor: usize },
+    ExternalLevels,
+    Rules(RuleError),
+}
+
+impl Display for GenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenError::MissingLayer(identifier) => write!(f, "project has no layer {}", identifier),
+            GenError::NoWallValue(identifier) => write!(f, "layer {} has no IntGrid value for walls", identifier),
+            GenError::MissingEntity(identifier) => write!(f, "project has no entity {}", identifier),
+            GenError::RequiredField { entity, field } =>
+                write!(f, "field {} of entity {} has no default and cannot be null", field, entity),
+            GenError::TooSmall { width, height } =>
+                write!(f, "level of {}x{} cells is too small, minimum is {}x{}", width, height, MIN_SIZE, MIN_SIZE),
+            GenError::NoSpace { needed, floor } =>
+                write!(f, "cannot place {} entities on {} floor cells", needed, floor),
+            GenError::ExternalLevels => write!(f, "generated levels cannot be added to projects with external levels"),
+            GenError::Rules(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for GenError {}
+
+impl From<RuleError> for GenError {
+    fn from(err: RuleError) -> Self {
+        GenError::Rules(err)
+    }
+}
+
+/// Generates a level for `project` from a seed. It has an instance of every layer of the
+/// project: walls in the collision layer, entities in the entity layer, auto-layer tiles
+/// from the project's rules, and every other layer empty.
+pub fn generate(project: &LdtkJson, params: &GenParams) -> Result<Level, GenError> {
+    let (width, height) = (params.width, params.height);
+    if width < MIN_SIZE || height < MIN_SIZE {
+        return Err(GenError::TooSmall { width, height });
+    }
+    let layer_def = |identifier: &str| project.defs.layers.iter()
+        .find(|def| def.identifier == identifier)
+        .ok_or_else(|| GenError::MissingLayer(identifier.to_owned()));
+    let collision_def = layer_def(&params.collision_layer)?;
+    let entity_def = layer_def(&params.entity_layer)?;
+    let wall = match params.wall_value {
+        Some(value) => collision_def.int_grid_values.iter().find(|def| def.value == value),
+        None => collision_def.int_grid_values.first(),
+    };
+    let Some(wall) = wall.map(|def| def.value).filter(|_| collision_def.layer_definition_type == "IntGrid") else {
+        return Err(GenError::NoWallValue(params.collision_layer.clone()));
+    };
+    let entities = params.placements.iter()
+        .map(|(identifier, count)| project.defs.entities.iter()
+            .find(|def| &def.identifier == identifier)
+            .map(|def| (def, *count))
+            .ok_or_else(|| GenError::MissingEntity(identifier.clone())))
+        .collect::<Result<Vec<_>, _>>()?;
+    let encounters: Vec<&EntityDefinition> = project.defs.entities.iter()
+        .filter(|def| def.tags.iter().any(|tag| tag == ENCOUNTER_TAG))
+        .collect();
+
+    let mut rng = Rng::new(params.seed);
+    let mut grid = match params.algorithm {
+        Algorithm::Rooms => rooms(&mut rng, width, height),
+        Algorithm::Caves => caves(&mut rng, width, height),
+    };
+    keep_largest_region(&mut grid);
+
+    let mut floor: Vec<(i64, i64)> = grid.cells()
+        .filter(|&(cx, cy)| !grid.is_wall(cx, cy))
+        .collect();
+    rng.shuffle(&mut floor);
+    let mut placed = Vec::new();
+    for (def, count) in entities {
+        placed.extend((0..count).map(|_| def));
+    }
+    if !encounters.is_empty() {
+        placed.extend((0..params.encounters).map(|_| encounters[rng.below(encounters.len() as u64) as usize]));
+    }
+    if placed.len() > floor.len() {
+        return Err(GenError::NoSpace { needed: placed.len(), floor: floor.len() });
+    }
+
+    let grid_size = collision_def.grid_size;
+    let (px_wid, px_hei) = (width * grid_size, height * grid_size);
+    let uid = project.next_uid;
+    let (world_x, world_y) = free_position(project);
+    let bg_color = project.default_level_bg_color.clone();
+    let level_iid = rng.uuid();
+
+    let mut layer_instances = Vec::with_capacity(project.defs.layers.len());
+    for def in &project.defs.layers {
+        let mut layer = layer_instance(project, def, &mut rng, uid, px_wid, px_hei);
+        if def.uid == collision_def.uid {
+            layer.int_grid_csv = grid.walls.iter().map(|&is_wall| if is_wall { wall } else { 0 }).collect();
+        }
+        if def.uid == entity_def.uid {
+            layer.entity_instances = placed.iter().zip(&floor)
+                .map(|(def, &cell)| entity_instance(def, &mut rng, cell, grid_size, (world_x, world_y)))
+                .collect::<Result<_, _>>()?;
+        }
+        layer_instances.push(layer);
+    }
+
+    let mut level = Level {
+        bg_color: bg_color.clone(),
+        bg_pos: None,
+        neighbours: Vec::new(),
+        smart_color: bg_color,
+        level_bg_color: None,
+        bg_pivot_x: 0.5,
+        bg_pivot_y: 0.5,
+        level_bg_pos: None,
+        bg_rel_path: None,
+        external_rel_path: None,
+        field_instances: project.defs.level_fields.iter()
+            .map(|field| field_instance(field, &params.identifier))
+            .collect::<Result<_, _>>()?,
+        identifier: params.identifier.clone(),
+        iid: level_iid,
+        layer_instances: Some(layer_instances),
+        px_hei,
+        px_wid,
+        uid,
+        use_auto_identifier: false,
+        world_depth: 0,
+        world_x,
+        world_y,
+    };
+    auto_layer::apply_rules(&project.defs, &mut level)?;
+    Ok(level)
+}
+
+/// Appends a generated level to the project, in its first world when it has several.
+pub fn add_level(project: &mut LdtkJson, level: Level) -> Result<(), GenError> {
+    if project.external_levels {
+        return Err(GenError::ExternalLevels);
+    }
+    project.next_uid = project.next_uid.max(level.uid + 1);
+    match project.worlds.first_mut() {
+        Some(world) => world.levels.push(level),
+        None => project.levels.push(level),
+    }
+    Ok(())
+}
+
+/// Right of every existing level, one world grid cell apart so it has no neighbours.
+fn free_position(project: &LdtkJson) -> (i64, i64) {
+    let levels = project.levels.iter().chain(project.worlds.iter().flat_map(|world| world.levels.iter()));
+    let (right, bottom) = levels.fold((0, 0), |(right, bottom), level| {
+        (right.max(level.world_x + level.px_wid), bottom.max(level.world_y + level.px_hei))
+    });
+    let layout = project.worlds.first().and_then(|world| world.world_layout.as_ref())
+        .or(project.world_layout.as_ref());
+    let gap = project.default_grid_size.max(1);
+    match layout {
+        Some(WorldLayout::LinearVertical) => (0, bottom + gap),
+        Some(WorldLayout::GridVania) => {
+            let step = project.world_grid_width.filter(|&step| step > 0).unwrap_or(gap);
+            ((div_ceil(right, step) + 1) * step, 0)
+        }
+        _ => (right + gap, 0),
+    }
+}
+
+fn layer_instance(project: &LdtkJson, def: &LayerDefinition, rng: &mut Rng, level_uid: i64,
+    px_wid: i64, px_hei: i64) -> LayerInstance {
+    let (c_wid, c_hei) = (div_ceil(px_wid, def.grid_size), div_ceil(px_hei, def.grid_size));
+    let tileset_rel_path = def.tileset_def_uid
+        .and_then(|uid| project.defs.tilesets.iter().find(|tileset| tileset.uid == uid))
+        .and_then(|tileset| tileset.rel_path.clone());
+    LayerInstance {
+        c_hei,
+        c_wid,
+        grid_size: def.grid_size,
+        identifier: def.identifier.clone(),
+        opacity: def.display_opacity,
+        px_total_offset_x: def.px_offset_x,
+        px_total_offset_y: def.px_offset_y,
+        tileset_def_uid: def.tileset_def_uid,
+        tileset_rel_path,
+        layer_instance_type: def.layer_definition_type.clone(),
+        auto_layer_tiles: Vec::new(),
+        entity_instances: Vec::new(),
+        grid_tiles: Vec::new(),
+        iid: rng.uuid(),
+        int_grid: None,
+        int_grid_csv: if def.layer_definition_type == "IntGrid" { vec![0; (c_wid * c_hei) as usize] } else { Vec::new() },
+        layer_def_uid: def.uid,
+        level_id: level_uid,
+        optional_rules: Vec::new(),
+        override_tileset_uid: None,
+        px_offset_x: 0,
+        px_offset_y: 0,
+        seed: rng.below(10_000_000) as i64,
+        visible: true,
+    }
+}
+
+/// Placed with its bounds on the top-left corner of the cell.
+fn entity_instance(def: &EntityDefinition, rng: &mut Rng, (cx, cy): (i64, i64), grid_size: i64,
+    (world_x, world_y): (i64, i64)) -> Result<EntityInstance, GenError> {
+    let px = [
+        cx * grid_size + (def.pivot_x * def.width as f64).round() as i64,
+        cy * grid_size + (def.pivot_y * def.height as f64).round() as i64,
+    ];
+    Ok(EntityInstance {
+        grid: vec![px[0] / grid_size, px[1] / grid_size],
+        identifier: def.identifier.clone(),
+        pivot: vec![def.pivot_x, def.pivot_y],
+        smart_color: def.color.clone(),
+        tags: def.tags.clone(),
+        tile: def.tile_rect.clone(),
+        world_x: Some(world_x + px[0]),
+        world_y: Some(world_y + px[1]),
+        def_uid: def.uid,
+        field_instances: def.field_defs.iter()
+            .map(|field| field_instance(field, &def.identifier))
+            .collect::<Result<_, _>>()?,
+        height: def.height,
+        iid: rng.uuid(),
+        px: px.to_vec(),
+        width: def.width,
+    })
+}
+
+/// A field at its default value: the default set in LDtk, else null, else the zero value
+/// of its type.
+fn field_instance(def: &FieldDefinition, owner: &str) -> Result<FieldInstance, GenError> {
+    let kind = def.field_definition_type.as_str();
+    let default = def.default_override.as_ref()
+        .and_then(|default| default.get("params")?.get(0).cloned());
+    let value = if def.is_array {
+        Value::Array(Vec::new())
+    } else if let Some(default) = default {
+        match default.as_i64() {
+            Some(color) if kind == "Color" => Value::String(format!("#{:06X}", color)),
+            _ => default,
+        }
+    } else if def.can_be_null {
+        Value::Null
+    } else {
+        match kind {
+            "Int" | "Float" => Value::from(0),
+            "Bool" => Value::Bool(false),
+            "String" | "Multilines" => Value::String(String::new()),
+            "Color" => Value::String("#000000".to_owned()),
+            _ => return Err(GenError::RequiredField { entity: owner.to_owned(), field: def.identifier.clone() }),
+        }
+    };
+    Ok(FieldInstance {
+        identifier: def.identifier.clone(),
+        tile: None,
+        field_instance_type: def.field_definition_type.clone(),
+        value: Some(value),
+        def_uid: def.uid,
+        real_editor_values: Vec::new(),
+    })
+}
+
+fn div_ceil(value: i64, divisor: i64) -> i64 {
+    (value + divisor - 1) / divisor.max(1)
+}
+
+/// Walls of a generated level, row by row. The border is always wall.
+struct Grid {
+    width: i64,
+    height: i64,
+    walls: Vec<bool>,
+}
+
+impl Grid {
+
+    fn filled(width: i64, height: i64) -> Self {
+        Self {
+            width,
+            height,
+            walls: vec![true; (width * height) as usize],
+        }
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (i64, i64)> {
+        let width = self.width;
+        (0..self.height).flat_map(move |cy| (0..width).map(move |cx| (cx, cy)))
+    }
+
+    fn is_inner(&self, cx: i64, cy: i64) -> bool {
+        cx > 0 && cy > 0 && cx < self.width - 1 && cy < self.height - 1
+    }
+
+    /// Out of bounds is wall.
+    fn is_wall(&self, cx: i64, cy: i64) -> bool {
+        !(0..self.width).contains(&cx) || !(0..self.height).contains(&cy)
+            || self.walls[(cy * self.width + cx) as usize]
+    }
+
+    fn set(&mut self, cx: i64, cy: i64, wall: bool) {
+        if self.is_inner(cx, cy) {
+            self.walls[(cy * self.width + cx) as usize] = wall;
+        }
+    }
+
+    fn wall_neighbours(&self, cx: i64, cy: i64) -> usize {
+        (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| (dx, dy) != (0, 0) && self.is_wall(cx + dx, cy + dy))
+            .count()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Room {
+    x: i64,
+    y: i64,
+    w: i64,
+    h: i64,
+}
+
+impl Room {
+    fn center(&self) -> (i64, i64) {
+        (self.x + self.w / 2, self.y + self.h / 2)
+    }
+
+    /// Rooms keep at least one wall between them.
+    fn touches(&self, other: &Room) -> bool {
+        self.x <= other.x + other.w && other.x <= self.x + self.w
+            && self.y <= other.y + other.h && other.y <= self.y + self.h
+    }
+}
+
+fn rooms(rng: &mut Rng, width: i64, height: i64) -> Grid {
+    let mut grid = Grid::filled(width, height);
+    let max_rooms = (width * height / AREA_PER_ROOM).max(2);
+    let max_w = MAX_ROOM.min(width - 2);
+    let max_h = MAX_ROOM.min(height - 2);
+    let mut rooms: Vec<Room> = Vec::new();
+    for _ in 0..max_rooms * 10 {
+        if rooms.len() as i64 >= max_rooms {
+            break;
+        }
+        let w = rng.range(MIN_ROOM.min(max_w), max_w + 1);
+        let h = rng.range(MIN_ROOM.min(max_h), max_h + 1);
+        let room = Room {
+            x: rng.range(1, width - w),
+            y: rng.range(1, height - h),
+            w,
+            h,
+        };
+        if rooms.iter().any(|other| room.touches(other)) {
+            continue;
+        }
+        for cy in room.y..room.y + room.h {
+            for cx in room.x..room.x + room.w {
+                grid.set(cx, cy, false);
+            }
+        }
+        if let Some(previous) = rooms.last() {
+            let ((x1, y1), (x2, y2)) = (previous.center(), room.center());
+            if rng.chance(0.5) {
+                corridor(&mut grid, (x1, y1), (x2, y1));
+                corridor(&mut grid, (x2, y1), (x2, y2));
+            } else {
+                corridor(&mut grid, (x1, y1), (x1, y2));
+                corridor(&mut grid, (x1, y2), (x2, y2));
+            }
+        }
+        rooms.push(room);
+    }
+    grid
+}
+
+/// Carves a straight line, horizontal or vertical.
+fn corridor(grid: &mut Grid, (x1, y1): (i64, i64), (x2, y2): (i64, i64)) {
+    for cy in y1.min(y2)..=y1.max(y2) {
+        for cx in x1.min(x2)..=x1.max(x2) {
+            grid.set(cx, cy, false);
+        }
+    }
+}
+
+/// Random fill smoothed by the 4-5 rule: a cell becomes wall with five or more wall
+/// neighbours, floor with three or less, and keeps its state otherwise.
+fn caves(rng: &mut Rng, width: i64, height: i64) -> Grid {
+    let mut grid = Grid::filled(width, height);
+    for (cx, cy) in grid.cells() {
+        let wall = rng.chance(CAVE_FILL);
+        grid.set(cx, cy, wall);
+    }
+    for _ in 0..CAVE_STEPS {
+        let mut next = Grid { walls: grid.walls.clone(), ..grid };
+        for (cx, cy) in grid.cells() {
+            match grid.wall_neighbours(cx, cy) {
+                0..=3 => next.set(cx, cy, false),
+                5.. => next.set(cx, cy, true),
+                _ => {}
+            }
+        }
+        grid = next;
+    }
+    grid
+}
+
+/// Walls in every floor region but the largest, so all floor cells are reachable.
+fn keep_largest_region(grid: &mut Grid) {
+    let mut region = vec![usize::MAX; grid.walls.len()];
+    let mut sizes = Vec::new();
+    for (cx, cy) in grid.cells() {
+        let index = (cy * grid.width + cx) as usize;
+        if grid.walls[index] || region[index] != usize::MAX {
+            continue;
+        }
+        let id = sizes.len();
+        let mut size = 0;
+        let mut queue = VecDeque::from([(cx, cy)]);
+        region[index] = id;
+        while let Some((x, y)) = queue.pop_front() {
+            size += 1;
+            for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                if grid.is_wall(nx, ny) {
+                    continue;
+                }
+                let next = (ny * grid.width + nx) as usize;
+                if region[next] == usize::MAX {
+                    region[next] = id;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        sizes.push(size);
+    }
+    let largest = (0..sizes.len()).max_by_key(|&id| (sizes[id], std::cmp::Reverse(id)));
+    for (index, wall) in grid.walls.iter_mut().enumerate() {
+        if !*wall && Some(region[index]) != largest {
+            *wall = true;
+        }
+    }
+}
+
+/// SplitMix64. Implemented here rather than taken from a crate so a seed generates the
+/// same level across dependency updates.
+struct Rng(u64);
+
+impl Rng {
+
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// In `0..bound`.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound.max(1)
+    }
+
+    /// In `low..high`, or `low` when the range is empty.
+    fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + self.below((high - low).max(1) as u64) as i64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for index in (1..items.len()).rev() {
+            items.swap(index, self.below(index as u64 + 1) as usize);
+        }
+    }
+
+    fn uuid(&mut self) -> String {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.next_u64().to_le_bytes());
+        bytes[8..].copy_from_slice(&self.next_u64().to_le_bytes());
+        uuid::Builder::from_random_bytes(bytes).into_uuid().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{test_util, Project};
+
+    fn sample_project() -> LdtkJson {
+        Project::load(&test_util::sample_project()).unwrap().into_json()
+    }
+
+    /// Identifier, iid and cell of a placed entity.
+    type Placement = (String, String, Vec<i64>);
+
+    /// What a seed decides: the level iid, the walls, and where each entity goes under which iid.
+    fn outcome(level: &Level, params: &GenParams) -> (String, Vec<i64>, Vec<Placement>) {
+        let layers = level.layer_instances.as_deref().unwrap();
+        let layer = |identifier: &str| layers.iter().find(|layer| layer.identifier == identifier).unwrap();
+        let entities = layer(&params.entity_layer).entity_instances.iter()
+            .map(|entity| (entity.identifier.clone(), entity.iid.clone(), entity.grid.clone()))
+            .collect();
+        (level.iid.clone(), layer(&params.collision_layer).int_grid_csv.clone(), entities)
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_level() {
+        let project = sample_project();
+        for algorithm in [Algorithm::Rooms, Algorithm::Caves] {
+            let params = GenParams { algorithm, seed: 42, ..GenParams::default() };
+            let first = outcome(&generate(&project, &params).unwrap(), &params);
+            let second = outcome(&generate(&project, &params).unwrap(), &params);
+            assert_eq!(first, second, "{:?}", algorithm);
+            assert!(first.1.contains(&0) && first.1.iter().any(|&value| value != 0), "{:?}", algorithm);
+            assert_eq!(first.2.len(), 3, "{:?}", algorithm);
+
+            let params = GenParams { seed: 43, ..params };
+            let other = outcome(&generate(&project, &params).unwrap(), &params);
+            assert_ne!(first.0, other.0, "{:?}", algorithm);
+            assert_ne!(first.1, other.1, "{:?}", algorithm);
+            assert_ne!(first.2, other.2, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn encounters_are_drawn_from_tagged_entities() {
+        let mut project = sample_project();
+        for def in &mut project.defs.entities {
+            def.tags.push(ENCOUNTER_TAG.to_owned());
+        }
+        let tagged: Vec<String> = project.defs.entities.iter().map(|def| def.identifier.clone()).collect();
+        assert!(tagged.len() > 1, "the draw has a choice to make");
+
+        let params = GenParams { seed: 7, encounters: 5, ..GenParams::default() };
+        let placed: usize = params.placements.iter().map(|(_, count)| *count as usize).sum();
+        let (_, _, entities) = outcome(&generate(&project, &params).unwrap(), &params);
+        assert_eq!(entities.len(), placed + 5);
+        // Encounters are placed after the fixed placements.
+        let encounters = &entities[placed..];
+        assert!(encounters.iter().all(|(identifier, _, _)| tagged.contains(identifier)));
+        assert!(encounters.iter().any(|(identifier, _, _)| *identifier != encounters[0].0), "{:?}", encounters);
+        let (_, _, again) = outcome(&generate(&project, &params).unwrap(), &params);
+        assert_eq!(again, entities);
+
+        let none = GenParams { encounters: 0, ..params };
+        assert_eq!(outcome(&generate(&project, &none).unwrap(), &none).2.len(), placed);
+    }
+}
@@ -0,0 +1,148 @@
+use std::{collections::HashMap, fmt::Display, sync::Arc};
+
+use super::{
+    field::{EntityRef, FieldValue},
+    model::{Entity, Level, World},
+};
+
+#[derive(Debug, Clone, Copy)]
+struct EntityPos {
+    world: usize,
+    level: usize,
+}
+
+/// An entity together with the level and world it belongs to.
+#[derive(Debug, Clone, Copy)]
+pub struct EntityHandle<'a> {
+    pub world: &'a World,
+    pub level: &'a Level,
+    pub entity: &'a Entity,
+}
+
+/// Index of entity iids over a set of worlds, used to resolve `EntityRef` fields.
+pub struct IidIndex {
+    worlds: Vec<Arc<World>>,
+    entities: HashMap<String, EntityPos>,
+}
+
+impl IidIndex {
+
+    pub fn new(worlds: Vec<World>) -> Self {
+        Self::shared(worlds.into_iter().map(Arc::new).collect())
+    }
+
+    /// Indexes worlds that are also held elsewhere, like the world a game session runs.
+    pub fn shared(worlds: Vec<Arc<World>>) -> Self {
+        let mut entities = HashMap::new();
+        for (world_index, world) in worlds.iter().enumerate() {
+            for (level_index, level) in world.levels.iter().enumerate() {
+                for entity in level.entities() {
+                    entities.insert(entity.iid.clone(), EntityPos { world: world_index, level: level_index });
+                }
+            }
+        }
+        Self { worlds, entities }
+    }
+
+    pub fn worlds(&self) -> &[Arc<World>] {
+        &self.worlds
+    }
+
+    /// Clones the worlds that are still shared elsewhere.
+    pub fn into_worlds(self) -> Vec<World> {
+        self.worlds.into_iter().map(Arc::unwrap_or_clone).collect()
+    }
+
+    pub fn entity(&self, iid: &str) -> Option<EntityHandle<'_>> {
+        let pos = self.entities.get(iid)?;
+        let world = &self.worlds[pos.world];
+        let level = &world.levels[pos.level];
+        Some(EntityHandle {
+            world,
+            level,
+            entity: level.entity(iid)?,
+        })
+    }
+
+    /// Resolves a reference; the target must still live in the level and world it names.
+    pub fn resolve(&self, reference: &EntityRef) -> Result<EntityHandle<'_>, RefError> {
+        let handle = self.entity(&reference.entity_iid).ok_or(RefError::NotFound)?;
+        if handle.level.iid != reference.level_iid || handle.world.iid != reference.world_iid {
+            return Err(RefError::Moved {
+                level_iid: handle.level.iid.clone(),
+                world_iid: handle.world.iid.clone(),
+            });
+        }
+        Ok(handle)
+    }
+
+    /// Every `EntityRef` field value, including array items, that does not resolve.
+    pub fn dangling_refs(&self) -> Vec<DanglingRef> {
+        let mut dangling = Vec::new();
+        for world in &self.worlds {
+            for level in &world.levels {
+                for entity in level.entities() {
+                    for field in &entity.fields {
+                        for reference in entity_refs(&field.value) {
+                            if let Err(error) = self.resolve(reference) {
+                                dangling.push(DanglingRef {
+                                    level_iid: level.iid.clone(),
+                                    entity_iid: entity.iid.clone(),
+                                    field: field.identifier.clone(),
+                                    reference: reference.clone(),
+                                    error,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        dangling
+    }
+}
+
+fn entity_refs(value: &FieldValue) -> Vec<&EntityRef> {
+    match value {
+        FieldValue::EntityRef(reference) => vec![reference],
+        FieldValue::Array(values) => values.iter().flat_map(entity_refs).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefError {
+    NotFound,
+    /// The entity exists, but not in the level or world named by the reference.
+    Moved {
+        level_iid: String,
+        world_iid: String,
+    },
+}
+
+impl Display for RefError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RefError::NotFound => write!(f, "entity not found"),
+            RefError::Moved { level_iid, world_iid } =>
+                write!(f, "entity is in level {} of world {}", level_iid, world_iid),
+        }
+    }
+}
+
+impl std::error::Error for RefError {}
+
+#[derive(Debug, Clone)]
+pub struct DanglingRef {
+    pub level_iid: String,
+    pub entity_iid: String,
+    pub field: String,
+    pub reference: EntityRef,
+    pub error: RefError,
+}
+
+impl Display for DanglingRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: field {} references {}: {}", self.entity_iid, self.field, self.reference.entity_iid, self.error)
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
+
+use super::geom::{Aabb, Vec2};
+
+/// Uniform grid over a level, in level-local pixels. Each item is listed in every
+/// cell its box overlaps, so queries only look at the cells around the query area.
+#[derive(Debug, Clone)]
+pub struct SpatialIndex<K> {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<K>>,
+    items: HashMap<K, Aabb>,
+    /// Cells ever occupied; queries never look outside them, whatever their area.
+    extent: Option<CellRange>,
+}
+
+/// Inclusive range of grid cells covered by a box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CellRange {
+    min: (i32, i32),
+    max: (i32, i32),
+}
+
+impl CellRange {
+    fn union(self, other: CellRange) -> CellRange {
+        CellRange {
+            min: (self.min.0.min(other.min.0), self.min.1.min(other.min.1)),
+            max: (self.max.0.max(other.max.0), self.max.1.max(other.max.1)),
+        }
+    }
+
+    fn intersection(self, other: CellRange) -> Option<CellRange> {
+        let range = CellRange {
+            min: (self.min.0.max(other.min.0), self.min.1.max(other.min.1)),
+            max: (self.max.0.min(other.max.0), self.max.1.min(other.max.1)),
+        };
+        (range.min.0 <= range.max.0 && range.min.1 <= range.max.1).then_some(range)
+    }
+
+    fn cells(self) -> impl Iterator<Item = (i32, i32)> {
+        (self.min.1..=self.max.1).flat_map(move |cy| (self.min.0..=self.max.0).map(move |cx| (cx, cy)))
+    }
+}
+
+impl<K: Clone + Eq + Hash> SpatialIndex<K> {
+
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            items: HashMap::new(),
+            extent: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn get(&self, key: &K) -> Option<Aabb> {
+        self.items.get(key).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &Aabb)> {
+        self.items.iter()
+    }
+
+    /// Adds an item, or moves it when the key is already indexed.
+    pub fn insert(&mut self, key: K, bounds: Aabb) {
+        if self.items.contains_key(&key) {
+            self.update(&key, bounds);
+            return;
+        }
+        self.link(&key, self.range(bounds));
+        self.items.insert(key, bounds);
+    }
+
+    /// Moves an indexed item. Returns false when the key is unknown.
+    pub fn update(&mut self, key: &K, bounds: Aabb) -> bool {
+        let Some(old) = self.items.get(key).copied() else {
+            return false;
+        };
+        let (old_range, new_range) = (self.range(old), self.range(bounds));
+        if old_range != new_range {
+            self.unlink(key, old_range);
+            self.link(key, new_range);
+        }
+        self.items.insert(key.clone(), bounds);
+        true
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<Aabb> {
+        let bounds = self.items.remove(key)?;
+        self.unlink(key, self.range(bounds));
+        Some(bounds)
+    }
+
+    /// Items whose box overlaps `area`.
+    pub fn query_rect(&self, area: Aabb) -> Vec<K> {
+        self.collect(self.range(area), |bounds| bounds.intersects(&area))
+    }
+
+    /// Items whose box comes within `radius` of `center`.
+    pub fn query_radius(&self, center: Vec2, radius: f32) -> Vec<K> {
+        let range = self.span(
+            Vec2::new(center.x - radius, center.y - radius),
+            Vec2::new(center.x + radius, center.y + radius));
+        self.collect(range, |bounds| bounds.distance_to(center) <= radius)
+    }
+
+    fn collect(&self, range: CellRange, keep: impl Fn(&Aabb) -> bool) -> Vec<K> {
+        let mut seen = HashSet::new();
+        let mut found = Vec::new();
+        let Some(range) = self.extent.and_then(|extent| extent.intersection(range)) else {
+            return found;
+        };
+        for cell in range.cells() {
+            for key in self.cells.get(&cell).into_iter().flatten() {
+                if seen.insert(key) && keep(&self.items[key]) {
+                    found.push(key.clone());
+                }
+            }
+        }
+        found
+    }
+
+    fn link(&mut self, key: &K, range: CellRange) {
+        for cell in range.cells() {
+            self.cells.entry(cell).or_default().push(key.clone());
+        }
+        self.extent = Some(self.extent.map_or(range, |extent| extent.union(range)));
+    }
+
+    fn unlink(&mut self, key: &K, range: CellRange) {
+        for cell in range.cells() {
+            if let Some(keys) = self.cells.get_mut(&cell) {
+                keys.retain(|other| other != key);
+                if keys.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+
+    fn cell_of(&self, point: Vec2) -> (i32, i32) {
+        ((point.x / self.cell_size).floor() as i32, (point.y / self.cell_size).floor() as i32)
+    }
+
+    /// Cells touched by the closed box, so items exactly on a border are found from both sides.
+    fn range(&self, bounds: Aabb) -> CellRange {
+        self.span(bounds.pos(), Vec2::new(bounds.x + bounds.w, bounds.y + bounds.h))
+    }
+
+    fn span(&self, min: Vec2, max: Vec2) -> CellRange {
+        CellRange {
+            min: self.cell_of(min),
+            max: self.cell_of(max),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut keys: Vec<&'static str>) -> Vec<&'static str> {
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn insert_move_and_remove() {
+        let mut index = SpatialIndex::new(16.0);
+        index.insert("a", Aabb::new(2.0, 2.0, 4.0, 4.0));
+        index.insert("b", Aabb::new(40.0, 2.0, 4.0, 4.0));
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.query_rect(Aabb::new(0.0, 0.0, 8.0, 8.0)), ["a"]);
+
+        index.insert("a", Aabb::new(100.0, 100.0, 4.0, 4.0));
+        assert_eq!(index.len(), 2);
+        assert!(index.query_rect(Aabb::new(0.0, 0.0, 8.0, 8.0)).is_empty());
+        assert_eq!(index.query_rect(Aabb::new(96.0, 96.0, 16.0, 16.0)), ["a"]);
+        assert!(index.update(&"b", Aabb::new(98.0, 98.0, 4.0, 4.0)));
+        assert!(!index.update(&"c", Aabb::new(0.0, 0.0, 4.0, 4.0)));
+        assert_eq!(sorted(index.query_rect(Aabb::new(96.0, 96.0, 16.0, 16.0))), ["a", "b"]);
+
+        assert_eq!(index.remove(&"a"), Some(Aabb::new(100.0, 100.0, 4.0, 4.0)));
+        assert_eq!(index.remove(&"a"), None);
+        assert_eq!(index.query_rect(Aabb::new(96.0, 96.0, 16.0, 16.0)), ["b"]);
+        index.remove(&"b");
+        assert!(index.is_empty());
+        assert!(index.cells.is_empty());
+    }
+
+    #[test]
+    fn items_on_a_cell_border_are_found_from_both_sides() {
+        let mut index = SpatialIndex::new(16.0);
+        // Right edge exactly on the border between cells 0 and 1.
+        index.insert("a", Aabb::new(8.0, 0.0, 8.0, 8.0));
+        assert_eq!(index.query_radius(Vec2::new(20.0, 4.0), 4.0), ["a"]);
+        assert_eq!(index.query_radius(Vec2::new(4.0, 4.0), 4.0), ["a"]);
+        assert!(index.query_radius(Vec2::new(21.0, 4.0), 4.0).is_empty());
+        assert_eq!(index.query_rect(Aabb::new(16.0, 0.0, 4.0, 4.0)), Vec::<&str>::new(),
+            "boxes touching at an edge do not overlap");
+    }
+
+    #[test]
+    fn radius_checks_the_distance_to_the_box() {
+        let mut index = SpatialIndex::new(16.0);
+        index.insert("a", Aabb::new(0.0, 0.0, 10.0, 10.0));
+        // The corner (10, 10) is 5 away from (13, 14).
+        assert_eq!(index.query_radius(Vec2::new(13.0, 14.0), 5.0), ["a"]);
+        assert!(index.query_radius(Vec2::new(13.0, 14.0), 4.9).is_empty());
+    }
+
+    #[test]
+    fn huge_queries_only_visit_occupied_cells() {
+        let mut index = SpatialIndex::new(16.0);
+        assert!(index.query_radius(Vec2::new(0.0, 0.0), f32::MAX).is_empty());
+        index.insert("a", Aabb::new(-40.0, 30.0, 4.0, 4.0));
+        index.insert("b", Aabb::new(500.0, -300.0, 4.0, 4.0));
+        assert_eq!(sorted(index.query_radius(Vec2::new(0.0, 0.0), f32::INFINITY)), ["a", "b"]);
+        assert_eq!(sorted(index.query_rect(Aabb::new(-1e30, -1e30, 2e30, 2e30))), ["a", "b"]);
+    }
+}
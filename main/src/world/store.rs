@@ -0,0 +1,465 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, Pool, Postgres};
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::config::{GameConfig, MapStoreBackend};
+
+/// Where the filesystem backend keeps previous versions, inside each project directory.
+pub const VERSIONS_DIR: &str = ".versions";
+
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct FileVersion {
+    /// Starts at 1 and grows by one with each stored change.
+    pub version: i64,
+    /// Hex SHA-256 of the contents.
+    pub hash: String,
+    pub size: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct StoredFile {
+    /// Path inside the project, `/` separated, like `map1.ldtk` or `map1/Level_0.ldtkl`.
+    pub path: String,
+    #[sqlx(flatten)]
+    pub version: FileVersion,
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Io(PathBuf, io::Error),
+    Db(sqlx::Error),
+    InvalidPath(String),
+    NotFound {
+        project: String,
+        path: String,
+        version: Option<i64>,
+    },
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            StoreError::Db(err) => write!(f, "database error: {}", err),
+            StoreError::InvalidPath(path) => write!(f, "invalid map store path {}", path),
+            StoreError::NotFound { project, path, version: Some(version) } =>
+                write!(f, "{}/{} has no version {}", project, path, version),
+            StoreError::NotFound { project, path, version: None } => write!(f, "{}/{} does not exist", project, path),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<sqlx::Error> for StoreError {
+    fn from(err: sqlx::Error) -> Self {
+        StoreError::Db(err)
+    }
+}
+
+/// Versioned storage of LDtk projects and their assets. A project is a named set
+/// of files: the `.ldtk` file, external `.ldtkl` levels and tileset images.
+#[async_trait]
+pub trait MapStore: Send + Sync {
+    async fn projects(&self) -> Result<Vec<String>, StoreError>;
+
+    /// Latest version of every file of a project.
+    async fn files(&self, project: &str) -> Result<Vec<StoredFile>, StoreError>;
+
+    /// Contents of a file, the latest version unless `version` is given.
+    async fn read(&self, project: &str, path: &str, version: Option<i64>) -> Result<Vec<u8>, StoreError>;
+
+    /// Stores contents as the next version of a file. Unchanged contents keep the current version.
+    async fn write(&self, project: &str, path: &str, data: &[u8]) -> Result<FileVersion, StoreError>;
+
+    /// Every version of a file, oldest first.
+    async fn versions(&self, project: &str, path: &str) -> Result<Vec<FileVersion>, StoreError>;
+}
+
+/// Builds the backend selected by `game.store.backend`.
+pub fn open(config: &GameConfig, pool: Pool<Postgres>) -> Arc<dyn MapStore> {
+    match config.store.backend {
+        MapStoreBackend::Filesystem => Arc::new(FsStore::new(config.maps_dir.clone())),
+        MapStoreBackend::Postgres => Arc::new(PgStore::new(pool)),
+    }
+}
+
+/// Writes the latest version of every stored project under `dir`, one directory
+/// per project, skipping files already up to date. Returns how many files were written.
+pub async fn checkout(store: &dyn MapStore, dir: &Path) -> Result<usize, StoreError> {
+    let mut written = 0;
+    for project in store.projects().await? {
+        for file in store.files(&project).await? {
+            let target = dir.join(&project).join(&file.path);
+            if let Ok(local) = tokio::fs::read(&target).await {
+                if content_hash(&local) == file.version.hash {
+                    continue;
+                }
+            }
+            let data = store.read(&project, &file.path, Some(file.version.version)).await?;
+            if let Some(parent) = target.parent() {
+                tokio::fs::create_dir_all(parent).await.map_err(|err| StoreError::Io(parent.to_owned(), err))?;
+            }
+            tokio::fs::write(&target, data).await.map_err(|err| StoreError::Io(target.clone(), err))?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+/// Stores every file of a local project directory under `project`.
+/// Returns each path with its version after the push.
+pub async fn push_dir(store: &dyn MapStore, project: &str, dir: &Path) -> Result<Vec<(String, FileVersion)>, StoreError> {
+    let dir_owned = dir.to_owned();
+    let paths = tokio::task::spawn_blocking(move || local_files(&dir_owned))
+        .await
+        .expect("listing files does not panic")
+        .map_err(|err| StoreError::Io(dir.to_owned(), err))?;
+    let mut pushed = Vec::new();
+    for path in paths {
+        let local = dir.join(&path);
+        let data = tokio::fs::read(&local).await.map_err(|err| StoreError::Io(local, err))?;
+        let version = store.write(project, &path, &data).await?;
+        pushed.push((path, version));
+    }
+    Ok(pushed)
+}
+
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Rejects absolute paths, `..` and hidden components, so a stored path never
+/// escapes its project or reaches the version history.
+fn check_path(path: &str) -> Result<(), StoreError> {
+    let valid = !path.is_empty() && Path::new(path).components().all(|component| match component {
+        Component::Normal(name) => !name.to_string_lossy().starts_with('.'),
+        _ => false,
+    });
+    if valid { Ok(()) } else { Err(StoreError::InvalidPath(path.to_owned())) }
+}
+
+fn check_project(project: &str) -> Result<(), StoreError> {
+    check_path(project)?;
+    if project.contains('/') {
+        return Err(StoreError::InvalidPath(project.to_owned()));
+    }
+    Ok(())
+}
+
+/// Relative `/` separated paths of the files below `dir`, skipping hidden entries.
+fn local_files(dir: &Path) -> io::Result<Vec<String>> {
+    fn walk(dir: &Path, prefix: &str, files: &mut Vec<String>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+            let path = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+            if entry.file_type()?.is_dir() {
+                walk(&entry.path(), &path, files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+    let mut files = Vec::new();
+    walk(dir, "", &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+/// Projects are the subdirectories of `root`. The live file is always the latest
+/// version; older ones are kept as `<project>/.versions/<path>/<version>`.
+/// Files edited in place, by LDtk for instance, change without a new version.
+#[derive(Debug, Clone)]
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    async fn blocking<T, F>(&self, f: F) -> Result<T, StoreError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&FsStore) -> Result<T, StoreError> + Send + 'static,
+    {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || f(&store)).await.expect("map store tasks do not panic")
+    }
+
+    fn live_path(&self, project: &str, path: &str) -> PathBuf {
+        self.root.join(project).join(path)
+    }
+
+    fn history_dir(&self, project: &str, path: &str) -> PathBuf {
+        self.root.join(project).join(VERSIONS_DIR).join(path)
+    }
+
+    /// Version numbers kept in the history of a file, ascending.
+    fn history(&self, project: &str, path: &str) -> Result<Vec<i64>, StoreError> {
+        let dir = self.history_dir(project, path);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(StoreError::Io(dir, err)),
+        };
+        let mut versions: Vec<i64> = entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_string_lossy().parse().ok())
+            .collect();
+        versions.sort_unstable();
+        Ok(versions)
+    }
+
+    fn live_version(&self, project: &str, path: &str) -> Result<i64, StoreError> {
+        Ok(self.history(project, path)?.last().copied().unwrap_or(0) + 1)
+    }
+
+    fn describe(version: i64, file: &Path) -> Result<FileVersion, StoreError> {
+        let data = fs::read(file).map_err(|err| StoreError::Io(file.to_owned(), err))?;
+        let modified = fs::metadata(file)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|err| StoreError::Io(file.to_owned(), err))?;
+        Ok(FileVersion {
+            version,
+            hash: content_hash(&data),
+            size: data.len() as i64,
+            created_at: modified.into(),
+        })
+    }
+
+    fn projects_sync(&self) -> Result<Vec<String>, StoreError> {
+        let entries = fs::read_dir(&self.root).map_err(|err| StoreError::Io(self.root.clone(), err))?;
+        let mut projects: Vec<String> = entries
+            .flatten()
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| !name.starts_with('.'))
+            .collect();
+        projects.sort();
+        Ok(projects)
+    }
+
+    fn files_sync(&self, project: &str) -> Result<Vec<StoredFile>, StoreError> {
+        check_project(project)?;
+        let dir = self.root.join(project);
+        let paths = local_files(&dir).map_err(|err| StoreError::Io(dir, err))?;
+        paths.into_iter()
+            .map(|path| {
+                let version = Self::describe(self.live_version(project, &path)?, &self.live_path(project, &path))?;
+                Ok(StoredFile { path, version })
+            })
+            .collect()
+    }
+
+    fn read_sync(&self, project: &str, path: &str, version: Option<i64>) -> Result<Vec<u8>, StoreError> {
+        check_project(project)?;
+        check_path(path)?;
+        let live = self.live_version(project, path)?;
+        let file = match version {
+            None => self.live_path(project, path),
+            Some(version) if version == live => self.live_path(project, path),
+            Some(version) => self.history_dir(project, path).join(version.to_string()),
+        };
+        fs::read(&file).map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => StoreError::NotFound {
+                project: project.to_owned(),
+                path: path.to_owned(),
+                version,
+            },
+            _ => StoreError::Io(file, err),
+        })
+    }
+
+    fn write_sync(&self, project: &str, path: &str, data: &[u8]) -> Result<FileVersion, StoreError> {
+        check_project(project)?;
+        check_path(path)?;
+        let live_path = self.live_path(project, path);
+        let mut version = self.live_version(project, path)?;
+        if let Ok(current) = fs::read(&live_path) {
+            if content_hash(&current) == content_hash(data) {
+                return Self::describe(version, &live_path);
+            }
+            let history = self.history_dir(project, path);
+            fs::create_dir_all(&history).map_err(|err| StoreError::Io(history.clone(), err))?;
+            let kept = history.join(version.to_string());
+            fs::rename(&live_path, &kept).map_err(|err| StoreError::Io(kept, err))?;
+            version += 1;
+        }
+
+        if let Some(parent) = live_path.parent() {
+            fs::create_dir_all(parent).map_err(|err| StoreError::Io(parent.to_owned(), err))?;
+        }
+        fs::write(&live_path, data).map_err(|err| StoreError::Io(live_path.clone(), err))?;
+        Self::describe(version, &live_path)
+    }
+
+    fn versions_sync(&self, project: &str, path: &str) -> Result<Vec<FileVersion>, StoreError> {
+        check_project(project)?;
+        check_path(path)?;
+        let history = self.history_dir(project, path);
+        let mut versions = self.history(project, path)?
+            .into_iter()
+            .map(|version| Self::describe(version, &history.join(version.to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
+        let live_path = self.live_path(project, path);
+        if live_path.is_file() {
+            versions.push(Self::describe(self.live_version(project, path)?, &live_path)?);
+        }
+        Ok(versions)
+    }
+}
+
+#[async_trait]
+impl MapStore for FsStore {
+
+    async fn projects(&self) -> Result<Vec<String>, StoreError> {
+        self.blocking(|store| store.projects_sync()).await
+    }
+
+    async fn files(&self, project: &str) -> Result<Vec<StoredFile>, StoreError> {
+        let project = project.to_owned();
+        self.blocking(move |store| store.files_sync(&project)).await
+    }
+
+    async fn read(&self, project: &str, path: &str, version: Option<i64>) -> Result<Vec<u8>, StoreError> {
+        let (project, path) = (project.to_owned(), path.to_owned());
+        self.blocking(move |store| store.read_sync(&project, &path, version)).await
+    }
+
+    async fn write(&self, project: &str, path: &str, data: &[u8]) -> Result<FileVersion, StoreError> {
+        let (project, path, data) = (project.to_owned(), path.to_owned(), data.to_vec());
+        self.blocking(move |store| store.write_sync(&project, &path, &data)).await
+    }
+
+    async fn versions(&self, project: &str, path: &str) -> Result<Vec<FileVersion>, StoreError> {
+        let (project, path) = (project.to_owned(), path.to_owned());
+        self.blocking(move |store| store.versions_sync(&project, &path)).await
+    }
+}
+
+/// Contents live in `map_blob` keyed by their hash, so identical files and
+/// unchanged assets across versions are stored once. `map_file` lists versions.
+#[derive(Debug, Clone)]
+pub struct PgStore {
+    pool: Pool<Postgres>,
+}
+
+impl PgStore {
+
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl MapStore for PgStore {
+
+    async fn projects(&self) -> Result<Vec<String>, StoreError> {
+        Ok(sqlx::query_scalar("SELECT DISTINCT project FROM map_file ORDER BY project")
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn files(&self, project: &str) -> Result<Vec<StoredFile>, StoreError> {
+        check_project(project)?;
+        Ok(sqlx::query_as::<_, StoredFile>(
+            "SELECT DISTINCT ON (path) path, version, hash, size, created_at \
+             FROM map_file JOIN map_blob USING (hash) \
+             WHERE project = $1 ORDER BY path, version DESC")
+            .bind(project)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn read(&self, project: &str, path: &str, version: Option<i64>) -> Result<Vec<u8>, StoreError> {
+        check_project(project)?;
+        check_path(path)?;
+        sqlx::query_scalar(
+            "SELECT data FROM map_file JOIN map_blob USING (hash) \
+             WHERE project = $1 AND path = $2 AND ($3::bigint IS NULL OR version = $3) \
+             ORDER BY version DESC LIMIT 1")
+            .bind(project)
+            .bind(path)
+            .bind(version)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| StoreError::NotFound {
+                project: project.to_owned(),
+                path: path.to_owned(),
+                version,
+            })
+    }
+
+    async fn write(&self, project: &str, path: &str, data: &[u8]) -> Result<FileVersion, StoreError> {
+        check_project(project)?;
+        check_path(path)?;
+        let hash = content_hash(data);
+        let mut tx = self.pool.begin().await?;
+        let current = sqlx::query_as::<_, FileVersion>(
+            "SELECT version, hash, size, created_at FROM map_file JOIN map_blob USING (hash) \
+             WHERE project = $1 AND path = $2 ORDER BY version DESC LIMIT 1")
+            .bind(project)
+            .bind(path)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if let Some(current) = &current {
+            if current.hash == hash {
+                return Ok(current.clone());
+            }
+        }
+
+        sqlx::query("INSERT INTO map_blob(hash, data, size) VALUES ($1, $2, $3) ON CONFLICT (hash) DO NOTHING")
+            .bind(&hash)
+            .bind(data)
+            .bind(data.len() as i64)
+            .execute(&mut *tx)
+            .await?;
+        let version = current.map_or(1, |current| current.version + 1);
+        let created_at: DateTime<Utc> = sqlx::query_scalar(
+            "INSERT INTO map_file(project, path, version, hash) VALUES ($1, $2, $3, $4) RETURNING created_at")
+            .bind(project)
+            .bind(path)
+            .bind(version)
+            .bind(&hash)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(FileVersion {
+            version,
+            hash,
+            size: data.len() as i64,
+            created_at,
+        })
+    }
+
+    async fn versions(&self, project: &str, path: &str) -> Result<Vec<FileVersion>, StoreError> {
+        check_project(project)?;
+        check_path(path)?;
+        Ok(sqlx::query_as::<_, FileVersion>(
+            "SELECT version, hash, size, created_at FROM map_file JOIN map_blob USING (hash) \
+             WHERE project = $1 AND path = $2 ORDER BY version")
+            .bind(project)
+            .bind(path)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+}
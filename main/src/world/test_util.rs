@@ -0,0 +1,56 @@
+//! Fixtures shared by the unit tests of the world modules.
+
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+/// A fresh directory in the system temp dir, removed when dropped, so also when the test fails.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+
+    /// `name` must be unique among the tests, which run in parallel.
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("langrpg-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The sample LDtk project, `map1.ldtk`.
+pub fn sample_project() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../test_storange/maps/map1/map1.ldtk")
+}
+
+/// Copies the sample project and its tileset image into `dir`, so its files can be edited.
+pub fn copy_sample_project(dir: &Path) -> PathBuf {
+    fs::create_dir_all(dir).unwrap();
+    let source = sample_project();
+    for file in ["map1.ldtk", "TopDown_by_deepnight.png"] {
+        fs::copy(source.with_file_name(file), dir.join(file)).unwrap();
+    }
+    dir.join("map1.ldtk")
+}
@@ -0,0 +1,115 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::ldtk_json::TilesetDefinition;
+
+/// Enum tags and custom data of the tiles of one tileset, by tile id, so
+/// gameplay can ask what a tile is instead of hard-coding tile ids.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TileMeta {
+    tags: HashMap<i32, HashSet<String>>,
+    data: HashMap<i32, TileData>,
+}
+
+/// Custom data of a tile as typed in LDtk, and the key/values read from it.
+/// Serializes as the raw text only.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct TileData {
+    pub raw: String,
+    pub values: Map<String, Value>,
+}
+
+impl TileData {
+
+    /// Reads a JSON object, or else one `key = value` or `key: value` per line.
+    /// Line values are JSON when they parse as such and strings otherwise; a line
+    /// without a separator is a flag set to `true`.
+    pub fn parse(raw: &str) -> Self {
+        let values = match serde_json::from_str(raw.trim()) {
+            Ok(Value::Object(values)) => values,
+            _ => raw.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(|line| match line.find(['=', ':']) {
+                    Some(index) => {
+                        let value = line[index + 1..].trim();
+                        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_owned()));
+                        (line[..index].trim().to_owned(), value)
+                    }
+                    None => (line.to_owned(), Value::Bool(true)),
+                })
+                .collect(),
+        };
+        Self {
+            raw: raw.to_owned(),
+            values,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.values.get(key).and_then(Value::as_str)
+    }
+}
+
+impl From<String> for TileData {
+    fn from(raw: String) -> Self {
+        Self::parse(&raw)
+    }
+}
+
+impl From<TileData> for String {
+    fn from(data: TileData) -> Self {
+        data.raw
+    }
+}
+
+impl TileMeta {
+
+    pub fn new(tags: HashMap<i32, HashSet<String>>, data: HashMap<i32, TileData>) -> Self {
+        Self { tags, data }
+    }
+
+    pub fn from_ldtk(tileset: &TilesetDefinition) -> Self {
+        let mut tags: HashMap<i32, HashSet<String>> = HashMap::new();
+        for tag in &tileset.enum_tags {
+            for &tile_id in &tag.tile_ids {
+                tags.entry(tile_id as i32).or_default().insert(tag.enum_value_id.clone());
+            }
+        }
+        let data = tileset.custom_data.iter()
+            .map(|custom| (custom.tile_id as i32, TileData::parse(&custom.data)))
+            .collect();
+        Self { tags, data }
+    }
+
+    pub fn has_tag(&self, tile_id: i32, tag: &str) -> bool {
+        self.tags.get(&tile_id).is_some_and(|tags| tags.contains(tag))
+    }
+
+    pub fn tags(&self, tile_id: i32) -> impl Iterator<Item = &str> {
+        self.tags.get(&tile_id).into_iter().flatten().map(String::as_str)
+    }
+
+    /// Ids of the tiles tagged with an enum value, in no particular order.
+    pub fn tiles_tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = i32> + 'a {
+        self.tags.iter()
+            .filter(move |(_, tags)| tags.contains(tag))
+            .map(|(&tile_id, _)| tile_id)
+    }
+
+    pub fn data(&self, tile_id: i32) -> Option<&TileData> {
+        self.data.get(&tile_id)
+    }
+
+    /// A custom data value of a tile, e.g. `value(42, "footstep")`.
+    pub fn value(&self, tile_id: i32, key: &str) -> Option<&Value> {
+        self.data.get(&tile_id)?.get(key)
+    }
+}
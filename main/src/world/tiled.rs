@@ -0,0 +1,1108 @@
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{Map as JsonMap, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    fs,
+    io::Read,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use super::{
+    field::{Color, EntityRef, FieldValue},
+    geom::{Cell, Point, Size},
+    model::{
+        Definitions, Entity, EntityDef, Field, FieldDef, IntGridValueDef, Layer, LayerDef, LayerKind, LayerType,
+        Level, Tile, TilesetDef, World, WorldLayout,
+    },
+    tile_meta::{TileData, TileMeta},
+};
+
+pub const TMX_EXTENSION: &str = "tmx";
+pub const TMJ_EXTENSION: &str = "tmj";
+pub const TSX_EXTENSION: &str = "tsx";
+pub const TSJ_EXTENSION: &str = "tsj";
+
+/// Bool layer property turning a tile layer into an IntGrid layer, so it can be a
+/// collision layer like in LDtk.
+pub const INTGRID_PROPERTY: &str = "intgrid";
+/// Int tile property giving the IntGrid value of a tile; tiles without it count as 1.
+pub const VALUE_PROPERTY: &str = "value";
+
+const DEFAULT_BG_COLOR: &str = "#40465B";
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+const GID_MASK: u32 = 0x0fff_ffff;
+
+#[derive(Debug)]
+pub enum TiledErrorKind {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Xml(roxmltree::Error),
+    Invalid(String),
+}
+
+#[derive(Debug)]
+pub struct TiledError {
+    pub file: PathBuf,
+    pub kind: TiledErrorKind,
+}
+
+impl TiledError {
+    fn invalid(file: &Path, message: impl Into<String>) -> Self {
+        Self {
+            file: file.to_owned(),
+            kind: TiledErrorKind::Invalid(message.into()),
+        }
+    }
+}
+
+impl Display for TiledError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            TiledErrorKind::Io(err) => write!(f, "cannot read {}: {}", self.file.display(), err),
+            TiledErrorKind::Json(err) => write!(f, "cannot parse {}: {}", self.file.display(), err),
+            TiledErrorKind::Xml(err) => write!(f, "cannot parse {}: {}", self.file.display(), err),
+            TiledErrorKind::Invalid(message) => write!(f, "{}: {}", self.file.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for TiledError {}
+
+/// Whether a file is a Tiled map, `.tmx` or `.tmj`.
+pub fn is_tiled_map(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == TMX_EXTENSION || ext == TMJ_EXTENSION)
+}
+
+/// Builds the runtime model of a Tiled map: one world holding one level.
+///
+/// Tile layers become `Tiles` layers, or `IntGrid` layers with the [`INTGRID_PROPERTY`],
+/// object layers become `Entities` layers and group layers are flattened. Objects are
+/// entities named after their class, or their name when they have none, and their
+/// custom properties become fields. Tile classes and properties become the tile tags
+/// and custom data of the tileset. Iids are derived from the file name and Tiled ids.
+pub fn load(path: &Path) -> Result<World, TiledError> {
+    let map = read_map(path)?;
+    Converter::new(path, &map)?.world()
+}
+
+/// External tileset files a map uses.
+pub fn dependencies(path: &Path) -> Result<Vec<PathBuf>, TiledError> {
+    let map = read_map(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    Ok(map.tilesets.iter()
+        .filter_map(|tileset| tileset.source.as_deref())
+        .map(|source| dir.join(source))
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct Map {
+    orientation: String,
+    width: i32,
+    height: i32,
+    tilewidth: i32,
+    tileheight: i32,
+    infinite: bool,
+    backgroundcolor: Option<String>,
+    tilesets: Vec<TilesetEntry>,
+    layers: Vec<MapLayer>,
+}
+
+impl Default for Map {
+    fn default() -> Self {
+        Self {
+            orientation: "orthogonal".to_owned(),
+            width: 0,
+            height: 0,
+            tilewidth: 0,
+            tileheight: 0,
+            infinite: false,
+            backgroundcolor: None,
+            tilesets: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct TilesetEntry {
+    firstgid: u32,
+    /// External `.tsx` or `.tsj` file, relative to the map.
+    source: Option<String>,
+    #[serde(flatten)]
+    inline: Tileset,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct Tileset {
+    name: String,
+    tilewidth: i32,
+    tileheight: i32,
+    spacing: i32,
+    margin: i32,
+    tilecount: i32,
+    columns: i32,
+    image: Option<String>,
+    imagewidth: i32,
+    imageheight: i32,
+    tiles: Vec<TileInfo>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct TileInfo {
+    id: i32,
+    #[serde(rename = "type", alias = "class")]
+    class: String,
+    properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Property {
+    name: String,
+    #[serde(rename = "type", default = "string_type")]
+    kind: String,
+    #[serde(default)]
+    value: Value,
+}
+
+fn string_type() -> String {
+    "string".to_owned()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct MapLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    id: i64,
+    name: String,
+    offsetx: f64,
+    offsety: f64,
+    opacity: f64,
+    visible: bool,
+    properties: Vec<Property>,
+    data: Option<LayerData>,
+    encoding: Option<String>,
+    compression: Option<String>,
+    objects: Vec<Object>,
+    layers: Vec<MapLayer>,
+}
+
+impl Default for MapLayer {
+    fn default() -> Self {
+        Self {
+            kind: String::new(),
+            id: 0,
+            name: String::new(),
+            offsetx: 0.0,
+            offsety: 0.0,
+            opacity: 1.0,
+            visible: true,
+            properties: Vec::new(),
+            data: None,
+            encoding: None,
+            compression: None,
+            objects: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum LayerData {
+    Gids(Vec<u32>),
+    /// Base64 in `.tmj`; CSV or base64 in `.tmx`.
+    Encoded(String),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Object {
+    id: i64,
+    name: String,
+    #[serde(rename = "type", alias = "class")]
+    class: String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    gid: Option<u32>,
+    properties: Vec<Property>,
+}
+
+fn read_map(path: &Path) -> Result<Map, TiledError> {
+    let text = fs::read_to_string(path).map_err(|err| TiledError { file: path.to_owned(), kind: TiledErrorKind::Io(err) })?;
+    if path.extension().is_some_and(|ext| ext == TMJ_EXTENSION) {
+        serde_json::from_str(&text).map_err(|err| TiledError { file: path.to_owned(), kind: TiledErrorKind::Json(err) })
+    } else {
+        let document = roxmltree::Document::parse(&text)
+            .map_err(|err| TiledError { file: path.to_owned(), kind: TiledErrorKind::Xml(err) })?;
+        xml::map(document.root_element()).map_err(|message| TiledError::invalid(path, message))
+    }
+}
+
+fn read_tileset(path: &Path) -> Result<Tileset, TiledError> {
+    let text = fs::read_to_string(path).map_err(|err| TiledError { file: path.to_owned(), kind: TiledErrorKind::Io(err) })?;
+    if path.extension().is_some_and(|ext| ext == TSJ_EXTENSION) {
+        serde_json::from_str(&text).map_err(|err| TiledError { file: path.to_owned(), kind: TiledErrorKind::Json(err) })
+    } else {
+        let document = roxmltree::Document::parse(&text)
+            .map_err(|err| TiledError { file: path.to_owned(), kind: TiledErrorKind::Xml(err) })?;
+        Ok(xml::tileset(document.root_element()))
+    }
+}
+
+/// Reads `.tmx` and `.tsx` files into the structures `.tmj` and `.tsj` deserialize to.
+mod xml {
+    use roxmltree::Node;
+    use serde_json::Value;
+    use std::str::FromStr;
+
+    use super::{LayerData, Map, MapLayer, Object, Property, TileInfo, Tileset, TilesetEntry};
+
+    fn attr<T: FromStr>(node: Node, name: &str) -> Option<T> {
+        node.attribute(name).and_then(|value| value.parse().ok())
+    }
+
+    fn children<'a, 'input>(node: Node<'a, 'input>, tag: &'static str) -> impl Iterator<Item = Node<'a, 'input>> {
+        node.children().filter(move |child| child.has_tag_name(tag))
+    }
+
+    pub(super) fn map(node: Node) -> Result<Map, String> {
+        if !node.has_tag_name("map") {
+            return Err(format!("root element is {}, expected map", node.tag_name().name()));
+        }
+        Ok(Map {
+            orientation: node.attribute("orientation").unwrap_or("orthogonal").to_owned(),
+            width: attr(node, "width").unwrap_or_default(),
+            height: attr(node, "height").unwrap_or_default(),
+            tilewidth: attr(node, "tilewidth").unwrap_or_default(),
+            tileheight: attr(node, "tileheight").unwrap_or_default(),
+            infinite: attr::<i32>(node, "infinite").unwrap_or_default() != 0,
+            backgroundcolor: node.attribute("backgroundcolor").map(str::to_owned),
+            tilesets: children(node, "tileset")
+                .map(|tileset| TilesetEntry {
+                    firstgid: attr(tileset, "firstgid").unwrap_or(1),
+                    source: tileset.attribute("source").map(str::to_owned),
+                    inline: self::tileset(tileset),
+                })
+                .collect(),
+            layers: layers(node)?,
+        })
+    }
+
+    pub(super) fn tileset(node: Node) -> Tileset {
+        let image = children(node, "image").next();
+        Tileset {
+            name: node.attribute("name").unwrap_or_default().to_owned(),
+            tilewidth: attr(node, "tilewidth").unwrap_or_default(),
+            tileheight: attr(node, "tileheight").unwrap_or_default(),
+            spacing: attr(node, "spacing").unwrap_or_default(),
+            margin: attr(node, "margin").unwrap_or_default(),
+            tilecount: attr(node, "tilecount").unwrap_or_default(),
+            columns: attr(node, "columns").unwrap_or_default(),
+            image: image.and_then(|image| image.attribute("source")).map(str::to_owned),
+            imagewidth: image.and_then(|image| attr(image, "width")).unwrap_or_default(),
+            imageheight: image.and_then(|image| attr(image, "height")).unwrap_or_default(),
+            tiles: children(node, "tile")
+                .map(|tile| TileInfo {
+                    id: attr(tile, "id").unwrap_or_default(),
+                    class: tile.attribute("class").or(tile.attribute("type")).unwrap_or_default().to_owned(),
+                    properties: properties(tile),
+                })
+                .collect(),
+        }
+    }
+
+    fn layers(node: Node) -> Result<Vec<MapLayer>, String> {
+        node.children()
+            .filter(|child| child.is_element())
+            .filter_map(|child| {
+                let kind = match child.tag_name().name() {
+                    "layer" => "tilelayer",
+                    "objectgroup" => "objectgroup",
+                    "group" => "group",
+                    "imagelayer" => "imagelayer",
+                    _ => return None,
+                };
+                Some(layer(child, kind))
+            })
+            .collect()
+    }
+
+    fn layer(node: Node, kind: &str) -> Result<MapLayer, String> {
+        let data = children(node, "data").next();
+        let encoding = data.and_then(|data| data.attribute("encoding"));
+        let gids = match (data, encoding) {
+            (None, _) => None,
+            (Some(data), Some("csv")) => Some(LayerData::Gids(data.text().unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|gid| !gid.is_empty())
+                .map(|gid| gid.parse().map_err(|_| format!("invalid tile gid {} in layer", gid)))
+                .collect::<Result<_, _>>()?)),
+            (Some(data), Some(_)) => Some(LayerData::Encoded(data.text().unwrap_or_default().trim().to_owned())),
+            (Some(data), None) => Some(LayerData::Gids(children(data, "tile")
+                .map(|tile| attr(tile, "gid").unwrap_or_default())
+                .collect())),
+        };
+        Ok(MapLayer {
+            kind: kind.to_owned(),
+            id: attr(node, "id").unwrap_or_default(),
+            name: node.attribute("name").unwrap_or_default().to_owned(),
+            offsetx: attr(node, "offsetx").unwrap_or_default(),
+            offsety: attr(node, "offsety").unwrap_or_default(),
+            opacity: attr(node, "opacity").unwrap_or(1.0),
+            visible: attr::<i32>(node, "visible").unwrap_or(1) != 0,
+            properties: properties(node),
+            data: gids,
+            // CSV is already decoded above.
+            encoding: encoding.filter(|encoding| *encoding != "csv").map(str::to_owned),
+            compression: data.and_then(|data| data.attribute("compression")).map(str::to_owned),
+            objects: children(node, "object")
+                .map(|object| Object {
+                    id: attr(object, "id").unwrap_or_default(),
+                    name: object.attribute("name").unwrap_or_default().to_owned(),
+                    class: object.attribute("class").or(object.attribute("type")).unwrap_or_default().to_owned(),
+                    x: attr(object, "x").unwrap_or_default(),
+                    y: attr(object, "y").unwrap_or_default(),
+                    width: attr(object, "width").unwrap_or_default(),
+                    height: attr(object, "height").unwrap_or_default(),
+                    gid: attr(object, "gid"),
+                    properties: properties(object),
+                })
+                .collect(),
+            layers: if kind == "group" { layers(node)? } else { Vec::new() },
+        })
+    }
+
+    fn properties(node: Node) -> Vec<Property> {
+        children(node, "properties")
+            .flat_map(|properties| children(properties, "property"))
+            .map(|property| {
+                let kind = property.attribute("type").unwrap_or("string").to_owned();
+                // Multiline strings are stored as the element text.
+                let text = property.attribute("value").or(property.text()).unwrap_or_default();
+                let value = match kind.as_str() {
+                    "int" | "object" => text.parse::<i64>().map(Value::from).unwrap_or(Value::Null),
+                    "float" => text.parse::<f64>().map(Value::from).unwrap_or(Value::Null),
+                    "bool" => Value::Bool(text == "true"),
+                    _ => Value::String(text.to_owned()),
+                };
+                Property {
+                    name: property.attribute("name").unwrap_or_default().to_owned(),
+                    kind,
+                    value,
+                }
+            })
+            .collect()
+    }
+}
+
+/// A tileset of the map with the range of gids it covers.
+struct MapTileset {
+    first_gid: u32,
+    uid: i64,
+    tileset: Tileset,
+}
+
+struct Converter<'a> {
+    path: &'a Path,
+    map: &'a Map,
+    /// By first gid, ascending.
+    tilesets: Vec<MapTileset>,
+    stem: String,
+    /// Layer iid of every object, by object id, to resolve object properties.
+    object_layers: HashMap<i64, String>,
+    layer_defs: HashMap<i64, LayerDef>,
+    entity_defs: HashMap<String, EntityDef>,
+}
+
+impl<'a> Converter<'a> {
+
+    fn new(path: &'a Path, map: &'a Map) -> Result<Self, TiledError> {
+        if map.orientation != "orthogonal" {
+            return Err(TiledError::invalid(path, format!("{} maps are not supported, only orthogonal", map.orientation)));
+        }
+        if map.infinite {
+            return Err(TiledError::invalid(path, "infinite maps are not supported"));
+        }
+        if map.tilewidth <= 0 || map.tilewidth != map.tileheight {
+            return Err(TiledError::invalid(path, format!(
+                "tiles must be square, found {}x{}", map.tilewidth, map.tileheight)));
+        }
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut tilesets = Vec::new();
+        for entry in &map.tilesets {
+            let (mut tileset, tileset_dir) = match &entry.source {
+                Some(source) => {
+                    let file = dir.join(source);
+                    let tileset = read_tileset(&file)?;
+                    (tileset, file.parent().map(Path::to_owned).unwrap_or_default())
+                }
+                None => (entry.inline.clone(), dir.to_owned()),
+            };
+            if tileset.columns <= 0 || tileset.image.is_none() {
+                return Err(TiledError::invalid(path, format!(
+                    "tileset {} has no single image, image collections are not supported", tileset.name)));
+            }
+            // Like LDtk, image paths are kept relative to the map.
+            tileset.image = tileset.image.map(|image| relative_path(&tileset_dir.join(image), dir));
+            tilesets.push(MapTileset {
+                first_gid: entry.firstgid,
+                uid: entry.firstgid as i64,
+                tileset,
+            });
+        }
+        tilesets.sort_by_key(|tileset| tileset.first_gid);
+
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut object_layers = HashMap::new();
+        collect_objects(&map.layers, &stem, &mut object_layers);
+        Ok(Self {
+            path,
+            map,
+            tilesets,
+            stem,
+            object_layers,
+            layer_defs: HashMap::new(),
+            entity_defs: HashMap::new(),
+        })
+    }
+
+    fn world(mut self) -> Result<World, TiledError> {
+        let map = self.map;
+        let mut layers = Vec::new();
+        self.layers(&map.layers, Point::default(), 1.0, true, &mut layers)?;
+        // Tiled lists layers bottom first, LDtk top first.
+        layers.reverse();
+
+        let size = Size::new(map.width * map.tilewidth, map.height * map.tileheight);
+        let level = Level {
+            uid: 0,
+            iid: self.level_iid(),
+            identifier: self.stem.clone(),
+            world_pos: Point::default(),
+            world_depth: 0,
+            size,
+            bg_color: map.backgroundcolor.as_deref().map(rgb_hex).unwrap_or(DEFAULT_BG_COLOR.to_owned()),
+            neighbours: Vec::new(),
+            layers,
+        };
+        let world_iid = self.world_iid();
+        let tilesets = self.tilesets.iter()
+            .map(|tileset| (tileset.uid, tileset_def(tileset)))
+            .collect();
+        let defs = Definitions {
+            layers: self.layer_defs,
+            entities: self.entity_defs.into_values().map(|def| (def.uid, def)).collect(),
+            tilesets,
+            enums: HashMap::new(),
+        };
+        Ok(World::new(world_iid, self.stem, WorldLayout::Free, size, vec![level], Arc::new(defs)))
+    }
+
+    fn world_iid(&self) -> String {
+        format!("{}-world", self.stem)
+    }
+
+    fn level_iid(&self) -> String {
+        self.stem.clone()
+    }
+
+    /// Converts layers bottom first, group layers passing their offset, opacity and visibility down.
+    fn layers(&mut self, layers: &[MapLayer], offset: Point, opacity: f32, visible: bool, out: &mut Vec<Layer>)
+        -> Result<(), TiledError> {
+        for layer in layers {
+            let offset = offset.offset(layer.offsetx.round() as i32, layer.offsety.round() as i32);
+            let opacity = opacity * layer.opacity as f32;
+            let visible = visible && layer.visible;
+            let (kind, tileset_uid, int_grid_values) = match layer.kind.as_str() {
+                "group" => {
+                    self.layers(&layer.layers, offset, opacity, visible, out)?;
+                    continue;
+                }
+                "tilelayer" => self.tile_layer(layer)?,
+                "objectgroup" => (LayerKind::Entities { entities: self.objects(layer)? }, None, Vec::new()),
+                _ => continue,
+            };
+            let grid_size = self.map.tilewidth;
+            let layer_type = match kind {
+                LayerKind::IntGrid { .. } => LayerType::IntGrid,
+                LayerKind::Tiles { .. } => LayerType::Tiles,
+                LayerKind::AutoLayer { .. } => LayerType::AutoLayer,
+                LayerKind::Entities { .. } => LayerType::Entities,
+            };
+            self.layer_defs.insert(layer.id, LayerDef {
+                uid: layer.id,
+                identifier: layer.name.clone(),
+                layer_type,
+                grid_size,
+                tileset_uid,
+                int_grid_values,
+            });
+            out.push(Layer {
+                iid: layer_iid(&self.stem, layer.id),
+                identifier: layer.name.clone(),
+                def_uid: layer.id,
+                grid_size,
+                grid: Size::new(self.map.width, self.map.height),
+                px_offset: offset,
+                opacity,
+                visible,
+                tileset_uid,
+                kind,
+            });
+        }
+        Ok(())
+    }
+
+    /// The layer, its tileset and, for IntGrid layers, the values it uses named after
+    /// the class of the first tile with each value.
+    fn tile_layer(&self, layer: &MapLayer) -> Result<(LayerKind, Option<i64>, Vec<IntGridValueDef>), TiledError> {
+        let gids = self.gids(layer)?;
+        let expected = (self.map.width * self.map.height) as usize;
+        if gids.len() != expected {
+            return Err(TiledError::invalid(self.path, format!(
+                "layer {} has {} tiles, expected {}", layer.name, gids.len(), expected)));
+        }
+
+        let mut tileset_uid = None;
+        let mut tiles = Vec::new();
+        let mut cells = Vec::with_capacity(gids.len());
+        for (index, &gid) in gids.iter().enumerate() {
+            if gid & GID_MASK == 0 {
+                cells.push(None);
+                continue;
+            }
+            if gid & FLIPPED_DIAGONALLY != 0 {
+                return Err(TiledError::invalid(self.path, format!(
+                    "layer {} has rotated tiles, only flips are supported", layer.name)));
+            }
+            let map_tileset = self.tileset_of(gid & GID_MASK)
+                .ok_or_else(|| TiledError::invalid(self.path, format!("layer {} uses unknown tile gid {}", layer.name, gid & GID_MASK)))?;
+            if *tileset_uid.get_or_insert(map_tileset.uid) != map_tileset.uid {
+                return Err(TiledError::invalid(self.path, format!(
+                    "layer {} mixes tilesets, use one tileset per layer", layer.name)));
+            }
+            let tileset = &map_tileset.tileset;
+            let id = ((gid & GID_MASK) - map_tileset.first_gid) as i32;
+            let (cx, cy) = (index as i32 % self.map.width, index as i32 / self.map.width);
+            tiles.push(Tile {
+                id,
+                // Tiles taller than the grid stick out upwards, like Tiled draws them.
+                px: Point::new(cx * self.map.tilewidth, cy * self.map.tileheight + self.map.tileheight - tileset.tileheight),
+                src: Point::new(
+                    tileset.margin + (id % tileset.columns) * (tileset.tilewidth + tileset.spacing),
+                    tileset.margin + (id / tileset.columns) * (tileset.tileheight + tileset.spacing)),
+                flip_x: gid & FLIPPED_HORIZONTALLY != 0,
+                flip_y: gid & FLIPPED_VERTICALLY != 0,
+                alpha: 1.0,
+            });
+            cells.push(Some((map_tileset, id)));
+        }
+
+        let int_grid = property(&layer.properties, INTGRID_PROPERTY).and_then(Value::as_bool).unwrap_or(false);
+        if !int_grid {
+            return Ok((LayerKind::Tiles { tiles }, tileset_uid, Vec::new()));
+        }
+        let mut values = Vec::with_capacity(cells.len());
+        let mut value_defs: Vec<IntGridValueDef> = Vec::new();
+        for cell in cells {
+            let Some((tileset, id)) = cell else {
+                values.push(0);
+                continue;
+            };
+            let tile = tile_info(&tileset.tileset, id);
+            let value = tile
+                .and_then(|tile| property(&tile.properties, VALUE_PROPERTY))
+                .and_then(Value::as_i64)
+                .map(|value| value as i32)
+                .unwrap_or(1);
+            if !value_defs.iter().any(|def| def.value == value) {
+                value_defs.push(IntGridValueDef {
+                    value,
+                    identifier: tile.map(|tile| tile.class.clone()).filter(|class| !class.is_empty()),
+                });
+            }
+            values.push(value);
+        }
+        value_defs.sort_by_key(|def| def.value);
+        Ok((LayerKind::IntGrid { values, auto_tiles: tiles }, tileset_uid, value_defs))
+    }
+
+    fn gids(&self, layer: &MapLayer) -> Result<Vec<u32>, TiledError> {
+        let encoded = match &layer.data {
+            None => return Ok(Vec::new()),
+            Some(LayerData::Gids(gids)) => return Ok(gids.clone()),
+            Some(LayerData::Encoded(encoded)) => encoded,
+        };
+        if layer.encoding.as_deref() != Some("base64") {
+            return Err(TiledError::invalid(self.path, format!(
+                "layer {} has unsupported encoding {}", layer.name, layer.encoding.as_deref().unwrap_or("none"))));
+        }
+        let bytes = base64::engine::general_purpose::STANDARD.decode(encoded.trim())
+            .map_err(|err| TiledError::invalid(self.path, format!("layer {}: {}", layer.name, err)))?;
+        let mut data = Vec::new();
+        let read = match layer.compression.as_deref().filter(|compression| !compression.is_empty()) {
+            None => {
+                data = bytes;
+                Ok(0)
+            }
+            Some("zlib") => flate2::read::ZlibDecoder::new(bytes.as_slice()).read_to_end(&mut data),
+            Some("gzip") => flate2::read::GzDecoder::new(bytes.as_slice()).read_to_end(&mut data),
+            Some(other) => return Err(TiledError::invalid(self.path, format!(
+                "layer {} has unsupported compression {}", layer.name, other))),
+        };
+        read.map_err(|err| TiledError::invalid(self.path, format!("layer {}: {}", layer.name, err)))?;
+        Ok(data.chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect())
+    }
+
+    fn objects(&mut self, layer: &MapLayer) -> Result<Vec<Entity>, TiledError> {
+        let grid_size = self.map.tilewidth;
+        let mut entities = Vec::new();
+        for object in &layer.objects {
+            let identifier = [&object.class, &object.name].into_iter()
+                .find(|name| !name.is_empty())
+                .cloned()
+                .unwrap_or_else(|| "Object".to_owned());
+            let size = Size::new(object.width.round() as i32, object.height.round() as i32);
+            let px = Point::new(object.x.round() as i32, object.y.round() as i32);
+            // Tile objects are placed by their bottom-left corner.
+            let pivot = if object.gid.is_some() { (0.0, 1.0) } else { (0.0, 0.0) };
+
+            let mut fields = Vec::new();
+            for property in &object.properties {
+                let Some((type_name, value)) = self.field_value(property) else {
+                    tracing::warn!("{}: skipping property {} of object {}, type {} is not supported",
+                        self.path.display(), property.name, object.id, property.kind);
+                    continue;
+                };
+                fields.push((property.name.clone(), type_name, value));
+            }
+
+            let next_uid = self.entity_defs.len() as i64 + 1;
+            let def = self.entity_defs.entry(identifier.clone()).or_insert_with(|| EntityDef {
+                uid: next_uid,
+                identifier: identifier.clone(),
+                size,
+                tags: Vec::new(),
+                fields: Vec::new(),
+            });
+            let fields = fields.into_iter()
+                .map(|(identifier, type_name, value)| {
+                    let uid = match def.fields.iter().find(|field| field.identifier == identifier) {
+                        Some(field) => field.uid,
+                        None => {
+                            let uid = def.uid * 1000 + def.fields.len() as i64 + 1;
+                            def.fields.push(FieldDef {
+                                uid,
+                                identifier: identifier.clone(),
+                                type_name: type_name.to_owned(),
+                                is_array: false,
+                                // Tiled objects of a class do not have to set every property.
+                                can_be_null: true,
+                            });
+                            uid
+                        }
+                    };
+                    Field {
+                        identifier,
+                        def_uid: uid,
+                        type_name: type_name.to_owned(),
+                        value,
+                    }
+                })
+                .collect();
+
+            entities.push(Entity {
+                iid: object_iid(&self.stem, object.id),
+                identifier,
+                def_uid: def.uid,
+                cell: Cell::new(px.x.div_euclid(grid_size), px.y.div_euclid(grid_size)),
+                px,
+                size,
+                pivot,
+                tags: Vec::new(),
+                fields,
+            });
+        }
+        Ok(entities)
+    }
+
+    /// LDtk type name and value of a custom property, `None` for unsupported types like classes.
+    fn field_value(&self, property: &Property) -> Option<(&'static str, FieldValue)> {
+        let value = &property.value;
+        Some(match property.kind.as_str() {
+            "string" => ("String", value.as_str().map(|value| FieldValue::String(value.to_owned())).unwrap_or(FieldValue::Null)),
+            "int" => ("Int", value.as_i64().map(FieldValue::Int).unwrap_or(FieldValue::Null)),
+            "float" => ("Float", value.as_f64().map(FieldValue::Float).unwrap_or(FieldValue::Null)),
+            "bool" => ("Bool", value.as_bool().map(FieldValue::Bool).unwrap_or(FieldValue::Null)),
+            "color" => ("Color", value.as_str()
+                .and_then(|color| Color::from_hex(&rgb_hex(color)))
+                .map(FieldValue::Color)
+                .unwrap_or(FieldValue::Null)),
+            "file" => ("FilePath", value.as_str()
+                .filter(|path| !path.is_empty())
+                .map(|path| FieldValue::FilePath(path.to_owned()))
+                .unwrap_or(FieldValue::Null)),
+            "object" => ("EntityRef", value.as_i64()
+                .and_then(|id| Some(FieldValue::EntityRef(EntityRef {
+                    entity_iid: object_iid(&self.stem, id),
+                    layer_iid: self.object_layers.get(&id)?.clone(),
+                    level_iid: self.level_iid(),
+                    world_iid: self.world_iid(),
+                })))
+                .unwrap_or(FieldValue::Null)),
+            _ => return None,
+        })
+    }
+
+    fn tileset_of(&self, gid: u32) -> Option<&MapTileset> {
+        self.tilesets.iter()
+            .rev()
+            .find(|tileset| tileset.first_gid <= gid)
+            .filter(|tileset| gid - tileset.first_gid < tileset.tileset.tilecount.max(0) as u32)
+    }
+}
+
+fn collect_objects(layers: &[MapLayer], stem: &str, out: &mut HashMap<i64, String>) {
+    for layer in layers {
+        collect_objects(&layer.layers, stem, out);
+        for object in &layer.objects {
+            out.insert(object.id, layer_iid(stem, layer.id));
+        }
+    }
+}
+
+fn layer_iid(stem: &str, id: i64) -> String {
+    format!("{}-layer-{}", stem, id)
+}
+
+fn object_iid(stem: &str, id: i64) -> String {
+    format!("{}-object-{}", stem, id)
+}
+
+fn property<'a>(properties: &'a [Property], name: &str) -> Option<&'a Value> {
+    properties.iter().find(|property| property.name == name).map(|property| &property.value)
+}
+
+fn tile_info(tileset: &Tileset, id: i32) -> Option<&TileInfo> {
+    tileset.tiles.iter().find(|tile| tile.id == id)
+}
+
+fn tileset_def(map_tileset: &MapTileset) -> TilesetDef {
+    let tileset = &map_tileset.tileset;
+    let mut tags: HashMap<i32, HashSet<String>> = HashMap::new();
+    let mut data = HashMap::new();
+    for tile in &tileset.tiles {
+        if !tile.class.is_empty() {
+            tags.entry(tile.id).or_default().insert(tile.class.clone());
+        }
+        if !tile.properties.is_empty() {
+            let values: JsonMap<String, Value> = tile.properties.iter()
+                .map(|property| (property.name.clone(), property.value.clone()))
+                .collect();
+            data.insert(tile.id, TileData {
+                raw: Value::Object(values.clone()).to_string(),
+                values,
+            });
+        }
+    }
+    TilesetDef {
+        uid: map_tileset.uid,
+        identifier: tileset.name.clone(),
+        rel_path: tileset.image.clone(),
+        size: Size::new(tileset.imagewidth, tileset.imageheight),
+        tile_grid_size: tileset.tilewidth,
+        spacing: tileset.spacing,
+        padding: tileset.margin,
+        grid: Size::new(tileset.columns, (tileset.tilecount + tileset.columns - 1) / tileset.columns),
+        meta: TileMeta::new(tags, data),
+    }
+}
+
+/// Tiled writes colors as `#aarrggbb` or `#rrggbb`; LDtk uses `#rrggbb`.
+fn rgb_hex(color: &str) -> String {
+    let digits = color.trim_start_matches('#');
+    format!("#{}", if digits.len() == 8 { &digits[2..] } else { digits })
+}
+
+/// `path` relative to `base`, both taken as written, with `/` separators.
+fn relative_path(path: &Path, base: &Path) -> String {
+    let normalize = |path: &Path| {
+        let mut parts: Vec<String> = Vec::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir if parts.last().is_some_and(|part| part != "..") => {
+                    parts.pop();
+                }
+                other => parts.push(other.as_os_str().to_string_lossy().into_owned()),
+            }
+        }
+        parts
+    };
+    let (path, base) = (normalize(path), normalize(base));
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut parts = vec![".."; base.len() - common];
+    parts.extend(path[common..].iter().map(String::as_str));
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::{write::{GzEncoder, ZlibEncoder}, Compression};
+    use std::io::Write;
+
+    use super::*;
+    use crate::world::test_util::TempDir;
+
+    const TILESET: &str = r#"<tileset firstgid="1" name="terrain" tilewidth="16" tileheight="16" tilecount="8" columns="4">
+  <image source="terrain.png" width="64" height="32"/>
+ </tileset>"#;
+
+    /// Writes a 3x2 `.tmx` map with square 16px tiles around `body`.
+    fn write_tmx(dir: &Path, name: &str, attributes: &str, body: &str) -> PathBuf {
+        let path = dir.join(format!("{}.tmx", name));
+        fs::write(&path, format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16" {}>
+ {}
+</map>"#, attributes, body)).unwrap();
+        path
+    }
+
+    fn layer<'a>(level: &'a Level, identifier: &str) -> &'a Layer {
+        level.layers.iter().find(|layer| layer.identifier == identifier).unwrap()
+    }
+
+    fn tiles(layer: &Layer) -> &[Tile] {
+        match &layer.kind {
+            LayerKind::Tiles { tiles } => tiles,
+            other => panic!("expected a tiles layer, found {:?}", other),
+        }
+    }
+
+    fn load_error(path: &Path) -> String {
+        load(path).map(|_| ()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn loads_tmx_maps() {
+        let dir = TempDir::new("tiled-tmx");
+        fs::create_dir_all(dir.join("tilesets")).unwrap();
+        fs::write(dir.join("tilesets/terrain.tsx"), r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset name="terrain" tilewidth="16" tileheight="16" tilecount="8" columns="4">
+ <image source="../images/terrain.png" width="64" height="32"/>
+ <tile id="1" class="Wall">
+  <properties><property name="value" type="int" value="2"/></properties>
+ </tile>
+</tileset>"#).unwrap();
+        let path = write_tmx(&dir, "sample", r##"backgroundcolor="#ff102030""##, r#"<tileset firstgid="1" source="tilesets/terrain.tsx"/>
+ <layer id="1" name="Ground" width="3" height="2">
+  <data encoding="csv">
+1,2,0,
+2147483649,1073741826,0
+</data>
+ </layer>
+ <group id="4" name="Walls" offsetx="8" offsety="4">
+  <layer id="2" name="Collisions" width="3" height="2" opacity="0.5">
+   <properties><property name="intgrid" type="bool" value="true"/></properties>
+   <data encoding="csv">0,2,2,1,0,0</data>
+  </layer>
+ </group>
+ <objectgroup id="3" name="Entities">
+  <object id="1" name="Door" class="Door" x="16" y="0" width="16" height="16">
+   <properties>
+    <property name="locked" type="bool" value="true"/>
+    <property name="target" type="object" value="2"/>
+    <property name="missing" type="object" value="99"/>
+   </properties>
+  </object>
+  <object id="2" type="Key" gid="3" x="32" y="32" width="16" height="16">
+   <properties><property name="name" value="gold"/></properties>
+  </object>
+ </objectgroup>"#);
+
+        let world = load(&path).unwrap();
+        assert_eq!(world.iid, "sample-world");
+        assert_eq!(world.identifier, "sample");
+        let level = &world.levels[0];
+        assert_eq!((level.iid.as_str(), level.size), ("sample", Size::new(48, 32)));
+        assert_eq!(level.bg_color, "#102030");
+        let identifiers: Vec<_> = level.layers.iter().map(|layer| layer.identifier.as_str()).collect();
+        assert_eq!(identifiers, ["Entities", "Collisions", "Ground"]);
+
+        let ground = tiles(layer(level, "Ground"));
+        let summary: Vec<_> = ground.iter().map(|tile| (tile.id, tile.px, tile.src, tile.flip_x, tile.flip_y)).collect();
+        assert_eq!(summary, [
+            (0, Point::new(0, 0), Point::new(0, 0), false, false),
+            (1, Point::new(16, 0), Point::new(16, 0), false, false),
+            (0, Point::new(0, 16), Point::new(0, 0), true, false),
+            (1, Point::new(16, 16), Point::new(16, 0), false, true),
+        ]);
+
+        let collisions = layer(level, "Collisions");
+        assert_eq!(collisions.iid, "sample-layer-2");
+        assert_eq!(collisions.px_offset, Point::new(8, 4));
+        assert_eq!(collisions.opacity, 0.5);
+        let LayerKind::IntGrid { values, auto_tiles } = &collisions.kind else {
+            panic!("expected an IntGrid layer");
+        };
+        assert_eq!(values, &[0, 2, 2, 1, 0, 0]);
+        assert_eq!(auto_tiles.len(), 3);
+        let value_defs: Vec<_> = world.defs.layers[&2].int_grid_values.iter()
+            .map(|def| (def.value, def.identifier.as_deref()))
+            .collect();
+        assert_eq!(value_defs, [(1, None), (2, Some("Wall"))]);
+
+        let tileset = &world.defs.tilesets[&1];
+        assert_eq!(tileset.rel_path.as_deref(), Some("images/terrain.png"));
+        assert!(tileset.meta.has_tag(1, "Wall"));
+        assert_eq!(tileset.meta.value(1, VALUE_PROPERTY), Some(&Value::from(2)));
+
+        let LayerKind::Entities { entities } = &layer(level, "Entities").kind else {
+            panic!("expected an Entities layer");
+        };
+        let door = &entities[0];
+        assert_eq!((door.iid.as_str(), door.identifier.as_str()), ("sample-object-1", "Door"));
+        assert_eq!((door.px, door.cell, door.pivot), (Point::new(16, 0), Cell::new(1, 0), (0.0, 0.0)));
+        let fields: Vec<_> = door.fields.iter().map(|field| (field.identifier.as_str(), &field.value)).collect();
+        assert_eq!(fields, [
+            ("locked", &FieldValue::Bool(true)),
+            ("target", &FieldValue::EntityRef(EntityRef {
+                entity_iid: "sample-object-2".to_owned(),
+                layer_iid: "sample-layer-3".to_owned(),
+                level_iid: "sample".to_owned(),
+                world_iid: "sample-world".to_owned(),
+            })),
+            ("missing", &FieldValue::Null),
+        ]);
+        let key = &entities[1];
+        assert_eq!((key.identifier.as_str(), key.pivot, key.cell), ("Key", (0.0, 1.0), Cell::new(2, 2)));
+        assert_eq!(key.fields[0].value, FieldValue::String("gold".to_owned()));
+        assert_eq!(world.defs.entities.len(), 2);
+    }
+
+    #[test]
+    fn decodes_base64_tmj_layers() {
+        let dir = TempDir::new("tiled-tmj");
+        let gids: Vec<u8> = [1u32, 0, 3, FLIPPED_HORIZONTALLY | 2, 0, 8].iter()
+            .flat_map(|gid| gid.to_le_bytes())
+            .collect();
+        let zlib = {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&gids).unwrap();
+            encoder.finish().unwrap()
+        };
+        let gzip = {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&gids).unwrap();
+            encoder.finish().unwrap()
+        };
+
+        for (compression, bytes) in [("", gids.clone()), ("zlib", zlib), ("gzip", gzip)] {
+            let path = dir.join(format!("map-{}.tmj", compression));
+            let map = serde_json::json!({
+                "orientation": "orthogonal", "width": 3, "height": 2, "tilewidth": 16, "tileheight": 16,
+                "tilesets": [{
+                    "firstgid": 1, "name": "terrain", "tilewidth": 16, "tileheight": 16, "tilecount": 8,
+                    "columns": 4, "image": "terrain.png", "imagewidth": 64, "imageheight": 32,
+                }],
+                "layers": [{
+                    "type": "tilelayer", "id": 1, "name": "Ground", "encoding": "base64", "compression": compression,
+                    "data": base64::engine::general_purpose::STANDARD.encode(&bytes),
+                }],
+            });
+            fs::write(&path, map.to_string()).unwrap();
+
+            let world = load(&path).unwrap();
+            let ground = tiles(&world.levels[0].layers[0]);
+            let summary: Vec<_> = ground.iter().map(|tile| (tile.id, tile.px, tile.flip_x)).collect();
+            assert_eq!(summary, [
+                (0, Point::new(0, 0), false),
+                (2, Point::new(32, 0), false),
+                (1, Point::new(0, 16), true),
+                (7, Point::new(32, 16), false),
+            ], "compression {:?}", compression);
+        }
+    }
+
+    #[test]
+    fn reads_gids_from_csv_and_plain_tmx_data() {
+        let dir = TempDir::new("tiled-plain");
+        let csv = write_tmx(&dir, "csv", "", &format!(r#"{}
+ <layer id="1" name="Ground"><data encoding="csv">1,0,2,0,0,3</data></layer>"#, TILESET));
+        let plain = write_tmx(&dir, "plain", "", &format!(r#"{}
+ <layer id="1" name="Ground"><data>
+  <tile gid="1"/><tile/><tile gid="2"/><tile/><tile/><tile gid="3"/>
+ </data></layer>"#, TILESET));
+
+        for path in [csv, plain] {
+            let world = load(&path).unwrap();
+            let ids: Vec<_> = tiles(&world.levels[0].layers[0]).iter().map(|tile| tile.id).collect();
+            assert_eq!(ids, [0, 1, 2], "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_maps() {
+        let dir = TempDir::new("tiled-errors");
+        let ground = |data: &str| format!(r#"{}
+ <layer id="1" name="Ground"><data encoding="csv">{}</data></layer>"#, TILESET, data);
+
+        let infinite = write_tmx(&dir, "infinite", r#"infinite="1""#, TILESET);
+        assert!(load_error(&infinite).contains("infinite maps are not supported"));
+
+        let path = dir.join("rectangular.tmx");
+        fs::write(&path, r#"<map orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="8"/>"#).unwrap();
+        assert!(load_error(&path).contains("tiles must be square, found 16x8"));
+
+        let isometric = dir.join("isometric.tmx");
+        fs::write(&isometric, r#"<map orientation="isometric" width="3" height="2" tilewidth="16" tileheight="16"/>"#).unwrap();
+        assert!(load_error(&isometric).contains("isometric maps are not supported"));
+
+        let rotated = write_tmx(&dir, "rotated", "", &ground(&format!("{},0,0,0,0,0", FLIPPED_DIAGONALLY | 1)));
+        assert!(load_error(&rotated).contains("layer Ground has rotated tiles"));
+
+        let mixed = write_tmx(&dir, "mixed", "", &format!(r#"{}
+ <tileset firstgid="9" name="items" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="items.png" width="32" height="32"/>
+ </tileset>
+ <layer id="1" name="Ground"><data encoding="csv">1,9,0,0,0,0</data></layer>"#, TILESET));
+        assert!(load_error(&mixed).contains("layer Ground mixes tilesets"));
+
+        let short = write_tmx(&dir, "short", "", &ground("1,0,0"));
+        assert!(load_error(&short).contains("layer Ground has 3 tiles, expected 6"));
+
+        let unknown = write_tmx(&dir, "unknown", "", &ground("1,0,0,0,0,42"));
+        assert!(load_error(&unknown).contains("layer Ground uses unknown tile gid 42"));
+
+        let collection = write_tmx(&dir, "collection", "",
+            r#"<tileset firstgid="1" name="props" tilewidth="16" tileheight="16" tilecount="2" columns="0"/>"#);
+        assert!(load_error(&collection).contains("tileset props has no single image"));
+    }
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(relative_path(Path::new("maps/tilesets/../images/a.png"), Path::new("maps")), "images/a.png");
+        assert_eq!(relative_path(Path::new("images/a.png"), Path::new("maps/town")), "../../images/a.png");
+        assert_eq!(relative_path(Path::new("./maps/./a.png"), Path::new("maps")), "a.png");
+        assert_eq!(relative_path(Path::new("a.png"), Path::new("")), "a.png");
+        assert_eq!(relative_path(Path::new("../shared/a.png"), Path::new("maps")), "../../shared/a.png");
+    }
+}
@@ -0,0 +1,258 @@
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    field::FieldType,
+    geom::Rect,
+    ldtk_json::{EntityInstance, Level},
+    loader::Project,
+    model::World,
+};
+
+/// Oldest `jsonVersion` the runtime model is known to read.
+pub const MIN_JSON_VERSION: (u32, u32) = (1, 3);
+/// Newest major `jsonVersion` the runtime model is known to read.
+pub const MAX_JSON_MAJOR: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Project or external level file the problem was found in.
+    pub file: PathBuf,
+    /// Iid of the level, layer or entity, when the problem is not project wide.
+    pub iid: Option<String>,
+    pub message: String,
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.file.display())?;
+        if let Some(iid) = &self.iid {
+            write!(f, "{}: ", iid)?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// Checks a loaded project for content problems the runtime model does not catch,
+/// or catches one at a time. `required_layers` must exist in every level.
+pub fn validate(project: &Project, required_layers: &[String]) -> Vec<Issue> {
+    let mut validator = Validator {
+        project,
+        issues: Vec::new(),
+        iids: HashMap::new(),
+    };
+    validator.json_version();
+    validator.tilesets();
+    for world in &project.json().worlds {
+        validator.iid(&world.iid, project.path(), "world");
+    }
+    for level in project.levels() {
+        validator.level(level, required_layers);
+    }
+    validator.issues
+}
+
+/// The checks of [`validate`] that apply to worlds built from other editors than LDtk,
+/// run on the runtime model of the map at `path`.
+pub fn validate_worlds(path: &Path, worlds: &[World], required_layers: &[String]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut report = |severity, iid: Option<&str>, message| issues.push(Issue {
+        severity,
+        file: path.to_owned(),
+        iid: iid.map(str::to_owned),
+        message,
+    });
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for world in worlds {
+        for tileset in world.defs.tilesets.values() {
+            if let Some(image) = tileset.rel_path.as_deref().map(|rel_path| dir.join(rel_path)) {
+                if !image.is_file() {
+                    report(Severity::Error, None, format!(
+                        "tileset {} image {} does not exist", tileset.identifier, image.display()));
+                }
+            }
+        }
+        for level in &world.levels {
+            for required in required_layers {
+                if level.layer(required).is_none() {
+                    report(Severity::Error, Some(&level.iid), format!(
+                        "level {} has no {} layer", level.identifier, required));
+                }
+            }
+            for entity in level.entities() {
+                let bounds = entity.bounds();
+                if !level.bounds().contains_rect(&bounds) {
+                    report(Severity::Warning, Some(&entity.iid), format!(
+                        "entity {} at {},{} lies outside the level", entity.identifier, bounds.x, bounds.y));
+                }
+            }
+        }
+    }
+    issues
+}
+
+pub fn has_errors(issues: &[Issue]) -> bool {
+    issues.iter().any(|issue| issue.severity == Severity::Error)
+}
+
+struct Validator<'a> {
+    project: &'a Project,
+    issues: Vec<Issue>,
+    /// What each iid was first seen on, and in which file.
+    iids: HashMap<String, (&'static str, PathBuf)>,
+}
+
+impl Validator<'_> {
+
+    fn report(&mut self, severity: Severity, file: &Path, iid: Option<&str>, message: String) {
+        self.issues.push(Issue {
+            severity,
+            file: file.to_owned(),
+            iid: iid.map(str::to_owned),
+            message,
+        });
+    }
+
+    fn json_version(&mut self) {
+        let version = &self.project.json().json_version;
+        let parsed: Vec<u32> = version.split('.').map_while(|part| part.parse().ok()).collect();
+        let message = match parsed.as_slice() {
+            [major, minor, ..] if (*major, *minor) < MIN_JSON_VERSION => format!(
+                "jsonVersion {} is older than the supported {}.{}", version, MIN_JSON_VERSION.0, MIN_JSON_VERSION.1),
+            [major, ..] if *major > MAX_JSON_MAJOR => format!(
+                "jsonVersion {} is newer than the supported {}.x", version, MAX_JSON_MAJOR),
+            [_, _, ..] => return,
+            _ => format!("jsonVersion {} is not a valid version", version),
+        };
+        let path = self.project.path().to_owned();
+        self.report(Severity::Error, &path, None, message);
+    }
+
+    fn tilesets(&mut self) {
+        let project = self.project;
+        for tileset in &project.json().defs.tilesets {
+            if let Some(path) = project.tileset_path(tileset) {
+                if !path.is_file() {
+                    self.report(Severity::Error, project.path(), None, format!(
+                        "tileset {} image {} does not exist", tileset.identifier, path.display()));
+                }
+            }
+        }
+    }
+
+    fn iid(&mut self, iid: &str, file: &Path, what: &'static str) {
+        if let Some((first_what, first_file)) = self.iids.get(iid) {
+            let message = format!("duplicate {} iid, also used by {} in {}",
+                what, first_what, first_file.display());
+            self.report(Severity::Error, file, Some(iid), message);
+        } else {
+            self.iids.insert(iid.to_owned(), (what, file.to_owned()));
+        }
+    }
+
+    fn level(&mut self, level: &Level, required_layers: &[String]) {
+        let project = self.project;
+        let file = level.external_rel_path.as_deref()
+            .map(|rel_path| project.resolve(rel_path))
+            .unwrap_or_else(|| project.path().to_owned());
+        self.iid(&level.iid, &file, "level");
+
+        let Some(layers) = &level.layer_instances else {
+            self.report(Severity::Error, &file, Some(&level.iid), "level has no layer instances".to_owned());
+            return;
+        };
+        for required in required_layers {
+            if !layers.iter().any(|layer| &layer.identifier == required) {
+                self.report(Severity::Error, &file, Some(&level.iid), format!(
+                    "level {} has no {} layer", level.identifier, required));
+            }
+        }
+
+        let bounds = Rect::new(0, 0, level.px_wid as i32, level.px_hei as i32);
+        for layer in layers {
+            self.iid(&layer.iid, &file, "layer");
+            for entity in &layer.entity_instances {
+                self.iid(&entity.iid, &file, "entity");
+                self.entity(entity, &file, bounds);
+            }
+        }
+    }
+
+    fn entity(&mut self, entity: &EntityInstance, file: &Path, level_bounds: Rect) {
+        if let ([x, y], [pivot_x, pivot_y]) = (entity.px.as_slice(), entity.pivot.as_slice()) {
+            let bounds = Rect::new(
+                *x as i32 - (pivot_x * entity.width as f64) as i32,
+                *y as i32 - (pivot_y * entity.height as f64) as i32,
+                entity.width as i32,
+                entity.height as i32);
+            if !level_bounds.contains_rect(&bounds) {
+                self.report(Severity::Warning, file, Some(&entity.iid), format!(
+                    "entity {} at {},{} lies outside the level", entity.identifier, bounds.x, bounds.y));
+            }
+        }
+
+        let defs = &self.project.json().defs;
+        let Some(entity_def) = defs.entities.iter().find(|def| def.uid == entity.def_uid) else {
+            self.report(Severity::Error, file, Some(&entity.iid), format!(
+                "entity {} has unknown definition {}", entity.identifier, entity.def_uid));
+            return;
+        };
+        for field_def in &entity_def.field_defs {
+            let value = entity.field_instances.iter()
+                .find(|field| field.def_uid == field_def.uid)
+                .and_then(|field| field.value.as_ref())
+                .filter(|value| !value.is_null());
+            if value.is_none() && !field_def.can_be_null {
+                self.report(Severity::Error, file, Some(&entity.iid), format!(
+                    "entity {} is missing required field {}", entity.identifier, field_def.identifier));
+            }
+        }
+        for field in &entity.field_instances {
+            let enum_name = match FieldType::parse(&field.field_instance_type) {
+                Some(FieldType::Enum(name)) => name,
+                Some(FieldType::Array(inner)) => match *inner {
+                    FieldType::Enum(name) => name,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            let Some(enum_def) = defs.enums.iter().chain(defs.external_enums.iter())
+                .find(|def| def.identifier == enum_name) else {
+                self.report(Severity::Error, file, Some(&entity.iid), format!(
+                    "field {} uses unknown enum {}", field.identifier, enum_name));
+                continue;
+            };
+            let values: Vec<&str> = match &field.value {
+                Some(Value::String(value)) => vec![value.as_str()],
+                Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            for value in values {
+                if !enum_def.values.iter().any(|known| known.id == value) {
+                    self.report(Severity::Error, file, Some(&entity.iid), format!(
+                        "field {} has unknown {} value {}", field.identifier, enum_name, value));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::HashSet;
+
+use crate::config::{CollisionConfig, VisibilityConfig};
+
+use super::{
+    collision::CollisionMap,
+    geom::{Cell, Point, Vec2},
+    model::{Definitions, Level},
+};
+
+/// Which cells of a level's collision grid block sight. Cells outside the grid
+/// are opaque, like they are solid for movement.
+#[derive(Debug, Clone)]
+pub struct Visibility {
+    map: CollisionMap,
+    opaque: Vec<bool>,
+}
+
+/// Cells seen from a point, see [`Visibility::field_of_view`].
+#[derive(Debug, Clone, Default)]
+pub struct FieldOfView {
+    cells: HashSet<Cell>,
+}
+
+impl FieldOfView {
+    pub fn contains(&self, cell: Cell) -> bool {
+        self.cells.contains(&cell)
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = &Cell> {
+        self.cells.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+}
+
+/// Maps octant-local coordinates to grid offsets, one per octant around the origin.
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+impl Visibility {
+
+    pub fn from_level(level: &Level, defs: &Definitions, map: &CollisionMap,
+        collision: &CollisionConfig, config: &VisibilityConfig) -> Self {
+        let size = map.size();
+        let cells = (0..size.h).flat_map(|cy| (0..size.w).map(move |cx| Cell::new(cx, cy)));
+        let mut opaque: Vec<bool> = cells.clone()
+            .map(|cell| config.opaque_terrain.contains(&map.terrain(cell)))
+            .collect();
+
+        if !config.opaque_values.is_empty() {
+            for layer in collision.layers.iter().filter_map(|identifier| level.layer(identifier)) {
+                let Some(layer_def) = defs.layers.get(&layer.def_uid) else {
+                    continue;
+                };
+                let values: HashSet<i32> = layer_def.int_grid_values.iter()
+                    .filter(|value| config.opaque_values.contains(&value.value.to_string())
+                        || value.identifier.as_ref().is_some_and(|identifier| config.opaque_values.contains(identifier)))
+                    .map(|value| value.value)
+                    .collect();
+                for (index, cell) in cells.clone().enumerate() {
+                    let center = map.cell_center(cell);
+                    let point = Point::new(center.x.floor() as i32, center.y.floor() as i32);
+                    if layer.int_value(layer.cell_at(point)).is_some_and(|value| values.contains(&value)) {
+                        opaque[index] = true;
+                    }
+                }
+            }
+        }
+
+        if !config.opaque_tags.is_empty() {
+            for layer in &level.layers {
+                let Some(tileset) = layer.tileset_uid.and_then(|uid| defs.tilesets.get(&uid)) else {
+                    continue;
+                };
+                let tile_ids: HashSet<i32> = config.opaque_tags.iter()
+                    .flat_map(|tag| tileset.meta.tiles_tagged(tag))
+                    .collect();
+                if tile_ids.is_empty() {
+                    continue;
+                }
+                for tile in layer.tiles().iter().filter(|tile| tile_ids.contains(&tile.id)) {
+                    // Every collision cell whose center lies on the tile.
+                    let (x, y) = (tile.px.x + layer.px_offset.x, tile.px.y + layer.px_offset.y);
+                    let first = map.cell_at(Vec2::new(x as f32, y as f32));
+                    let last = map.cell_at(Vec2::new((x + layer.grid_size) as f32 - 1.0, (y + layer.grid_size) as f32 - 1.0));
+                    for cy in first.cy..=last.cy {
+                        for cx in first.cx..=last.cx {
+                            let cell = Cell::new(cx, cy);
+                            let center = map.cell_center(cell);
+                            let on_tile = center.x >= x as f32 && center.x < (x + layer.grid_size) as f32
+                                && center.y >= y as f32 && center.y < (y + layer.grid_size) as f32;
+                            if on_tile && map.contains(cell) {
+                                opaque[(cy * size.w + cx) as usize] = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Self {
+            map: map.clone(),
+            opaque,
+        }
+    }
+
+    pub fn is_opaque(&self, cell: Cell) -> bool {
+        !self.map.contains(cell) || self.opaque[(cell.cy * self.map.size().w + cell.cx) as usize]
+    }
+
+    pub fn cell_at(&self, point: Vec2) -> Cell {
+        self.map.cell_at(point)
+    }
+
+    /// True when no opaque cell lies between two level-local points. The cells of
+    /// the points themselves do not count, so a wall can be seen.
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        let (first, last) = (self.map.cell_at(from), self.map.cell_at(to));
+        self.map.ray_cast_cells(from, to, |cell| cell != first && cell != last && self.is_opaque(cell))
+            .is_none()
+    }
+
+    /// Recursive shadow casting from `origin` out to `radius` pixels. Opaque cells
+    /// bordering the visible area are included.
+    pub fn field_of_view(&self, origin: Vec2, radius: f32) -> FieldOfView {
+        let origin = self.map.cell_at(origin);
+        let mut caster = ShadowCaster {
+            visibility: self,
+            origin,
+            radius: radius / self.map.grid_size() as f32,
+            fov: FieldOfView::default(),
+        };
+        caster.fov.cells.insert(origin);
+        for octant in OCTANTS {
+            caster.cast(1, 1.0, 0.0, octant);
+        }
+        caster.fov
+    }
+
+    /// Whether a level-local point lies in a cell of `fov`.
+    pub fn sees(&self, fov: &FieldOfView, point: Vec2) -> bool {
+        fov.contains(self.map.cell_at(point))
+    }
+}
+
+struct ShadowCaster<'a> {
+    visibility: &'a Visibility,
+    origin: Cell,
+    /// In cells.
+    radius: f32,
+    fov: FieldOfView,
+}
+
+impl ShadowCaster<'_> {
+
+    /// Scans one octant row by row, between the `start` and `end` slopes. Opaque
+    /// cells narrow the lit wedge and start a recursive scan past them.
+    fn cast(&mut self, row: i32, mut start: f32, end: f32, (xx, xy, yx, yy): (i32, i32, i32, i32)) {
+        if start < end {
+            return;
+        }
+        let max_row = self.radius.ceil() as i32;
+        let mut next_start = start;
+        for distance in row..=max_row {
+            let dy = -distance;
+            let mut blocked = false;
+            for dx in -distance..=0 {
+                let left_slope = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+                let right_slope = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+                if start < right_slope {
+                    continue;
+                }
+                if end > left_slope {
+                    break;
+                }
+
+                let cell = Cell::new(self.origin.cx + dx * xx + dy * xy, self.origin.cy + dx * yx + dy * yy);
+                if ((dx * dx + dy * dy) as f32) <= self.radius * self.radius {
+                    self.fov.cells.insert(cell);
+                }
+                let opaque = self.visibility.is_opaque(cell);
+                if blocked {
+                    if opaque {
+                        next_start = right_slope;
+                    } else {
+                        blocked = false;
+                        start = next_start;
+                    }
+                } else if opaque && distance < max_row {
+                    blocked = true;
+                    self.cast(distance + 1, start, left_slope, (xx, xy, yx, yy));
+                    next_start = right_slope;
+                }
+            }
+            if blocked {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{collision::Terrain, geom::Size};
+
+    /// Visibility of a level without layers, over a 16px grid from rows of `.`, `~` (water) and `#`.
+    fn visibility(rows: &[&str], config: &VisibilityConfig) -> Visibility {
+        let size = Size::new(rows[0].len() as i32, rows.len() as i32);
+        let cells = rows.iter()
+            .flat_map(|row| row.chars())
+            .map(|c| match c {
+                '#' => Terrain::Solid,
+                '~' => Terrain::Water,
+                _ => Terrain::Floor,
+            })
+            .collect();
+        let map = CollisionMap::new(16, Point::default(), size, cells, 0.5);
+        let level = Level {
+            uid: 0,
+            iid: "level".to_owned(),
+            identifier: "Level".to_owned(),
+            world_pos: Point::default(),
+            world_depth: 0,
+            size: Size::new(size.w * 16, size.h * 16),
+            bg_color: String::new(),
+            neighbours: Vec::new(),
+            layers: Vec::new(),
+        };
+        Visibility::from_level(&level, &Definitions::default(), &map, &CollisionConfig::default(), config)
+    }
+
+    fn center(cx: i32, cy: i32) -> Vec2 {
+        Vec2::new(cx as f32 * 16.0 + 8.0, cy as f32 * 16.0 + 8.0)
+    }
+
+    const OPEN: [&str; 7] = [
+        ".......",
+        ".......",
+        ".......",
+        ".......",
+        ".......",
+        ".......",
+        ".......",
+    ];
+
+    #[test]
+    fn open_area_is_seen_out_to_the_radius() {
+        let visibility = visibility(&OPEN, &VisibilityConfig::default());
+        let fov = visibility.field_of_view(center(3, 3), 48.0);
+        let expected: HashSet<Cell> = (0..7)
+            .flat_map(|cy| (0..7).map(move |cx| Cell::new(cx, cy)))
+            .filter(|cell| (cell.cx - 3).pow(2) + (cell.cy - 3).pow(2) <= 9)
+            .collect();
+        assert_eq!(fov.cells().copied().collect::<HashSet<_>>(), expected);
+        assert!(visibility.sees(&fov, center(3, 0)));
+        assert!(!visibility.sees(&fov, center(0, 0)));
+    }
+
+    #[test]
+    fn walls_are_seen_but_hide_what_is_behind() {
+        let visibility = visibility(&[
+            ".......",
+            ".......",
+            "...#...",
+            ".......",
+            ".......",
+        ], &VisibilityConfig::default());
+        let fov = visibility.field_of_view(center(3, 4), 80.0);
+        assert!(fov.contains(Cell::new(3, 2)));
+        assert!(!fov.contains(Cell::new(3, 1)));
+        assert!(!fov.contains(Cell::new(3, 0)));
+        assert!(fov.contains(Cell::new(0, 1)));
+        assert!(fov.contains(Cell::new(6, 1)));
+    }
+
+    #[test]
+    fn field_of_view_is_symmetric_around_a_pillar() {
+        let visibility = visibility(&[
+            ".......",
+            ".......",
+            ".......",
+            "...#...",
+            ".......",
+            ".......",
+            ".......",
+        ], &VisibilityConfig::default());
+        let fov = visibility.field_of_view(center(3, 5), 80.0);
+        for cell in fov.cells().filter(|cell| visibility.map.contains(**cell)) {
+            assert!(fov.contains(Cell::new(6 - cell.cx, cell.cy)), "{:?} seen but not its mirror", cell);
+        }
+    }
+
+    #[test]
+    fn outside_the_grid_is_opaque() {
+        let visibility = visibility(&OPEN, &VisibilityConfig::default());
+        assert!(visibility.is_opaque(Cell::new(-1, 3)));
+        assert!(visibility.is_opaque(Cell::new(3, 7)));
+        let fov = visibility.field_of_view(center(0, 3), 48.0);
+        assert!(fov.contains(Cell::new(-1, 3)), "bordering opaque cells are included");
+        assert!(!fov.contains(Cell::new(-2, 3)));
+    }
+
+    #[test]
+    fn line_of_sight_stops_at_walls_between_the_points() {
+        let visibility = visibility(&[
+            ".....",
+            "..#..",
+            ".....",
+        ], &VisibilityConfig::default());
+        assert!(!visibility.line_of_sight(center(0, 1), center(4, 1)));
+        assert!(visibility.line_of_sight(center(0, 0), center(4, 0)));
+        assert!(visibility.line_of_sight(center(0, 1), center(2, 1)), "the wall itself can be seen");
+    }
+
+    #[test]
+    fn opaque_terrain_is_configurable() {
+        let rows = [
+            ".....",
+            "..~..",
+            ".....",
+        ];
+        let clear = visibility(&rows, &VisibilityConfig::default());
+        assert!(clear.line_of_sight(center(0, 1), center(4, 1)));
+        let config = VisibilityConfig { opaque_terrain: vec![Terrain::Solid, Terrain::Water], ..Default::default() };
+        let murky = visibility(&rows, &config);
+        assert!(murky.is_opaque(Cell::new(2, 1)));
+        assert!(!murky.line_of_sight(center(0, 1), center(4, 1)));
+    }
+}
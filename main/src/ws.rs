@@ -1,6 +1,5 @@
 use axum::{
     Error,
-    Extension,
     extract::{
         ConnectInfo, WebSocketUpgrade,
         ws::{Message, WebSocket},
@@ -11,13 +10,21 @@ use axum::{
 use axum_extra::TypedHeader;
 use futures::stream::StreamExt;
 use futures_util::SinkExt;
-use futures_util::stream::SplitStream;
-use tokio::{sync::Mutex, time::timeout};
+use futures_util::stream::{SplitSink, SplitStream};
+use tokio::{sync::{mpsc, Mutex}, time::timeout};
 use std::{net::SocketAddr, ops::ControlFlow, sync::Arc};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use bytes::Bytes;
 
-use crate::{app_state::{AppState, ClientHandle, ClientsState}, auth::Claims, client::WsClient};
+use uuid::Uuid;
+
+use crate::{
+    app_state::{AppState, ClientHandle, ClientsState},
+    client::WsClient,
+    game::{protocol::{ClientMessage, ServerMessage}, GameSession},
+    metrics::Metrics,
+    world::geom::Vec2,
+};
 
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
@@ -26,49 +33,103 @@ pub async fn ws_handler(
     State(state): State<AppState>,
 ) -> impl IntoResponse {
     let clients = state.clients();
+    let idle_timeout = state.config().game.ws_idle_timeout();
+    let metrics = state.metrics();
+    let game = state.game();
     ws.on_upgrade(move |socket| {
-        handle_socket(socket, addr, clients)
+        handle_socket(socket, addr, clients, idle_timeout, metrics, game)
     })
 }
 
-async fn handle_socket(socket: WebSocket, who: SocketAddr, clients: Arc<ClientsState>) {
+async fn handle_socket(socket: WebSocket, who: SocketAddr, clients: Arc<ClientsState>, idle_timeout: Duration,
+    metrics: Arc<Metrics>, game: Arc<Mutex<GameSession>>) {
     let (mut sender, mut receiver) = socket.split();
 
     tokio::spawn(async move {
-        let client = clients.insert_client(WsClient::new()).await;
-        tracing::info!("New client {}", client.lock().await.id());
+        let (outbox, mut inbox) = mpsc::unbounded_channel();
+        let client = clients.insert_client(WsClient::with_outbox(outbox)).await;
+        let id = client.lock().await.id();
+        tracing::info!("New client {}", id);
+        let joined = game.lock().await.join(id);
+        if let Some(message) = joined {
+            if send_message(&mut sender, &message).await.is_err() {
+                stop_processing(clients, client, &game).await;
+                return;
+            }
+        }
+        let mut ping_sent: Option<Instant> = None;
         loop {
-            match get_message(&mut receiver).await {
+            let received = tokio::select! {
+                Some(message) = inbox.recv() => {
+                    let sent = send_message(&mut sender, &message).await.is_ok();
+                    let kicked = matches!(message, ServerMessage::Kicked);
+                    if kicked {
+                        let _ = sender.send(Message::Close(None)).await;
+                    }
+                    if !sent || kicked {
+                        stop_processing(clients, client, &game).await;
+                        break;
+                    }
+                    continue;
+                }
+                received = get_message(&mut receiver, idle_timeout) => received,
+            };
+            match received {
                 Err(_) => match sender.send(Message::Ping(Bytes::new())).await {
-                    Ok(_) => {}
+                    Ok(_) => ping_sent = Some(Instant::now()),
                     Err(_) => {
-                        stop_processing(clients, client).await;
+                        stop_processing(clients, client, &game).await;
                         break;
                     }
                 }
                 Ok(msg) => match msg {
                     None => {
-                        stop_processing(clients, client).await;
+                        stop_processing(clients, client, &game).await;
                         break;
                     }
-                    Some(m) => if process_message(m, who).is_break() {
-                        stop_processing(clients, client).await;
-                        break;
+                    Some(m) => {
+                        if let (Message::Pong(_), Some(sent)) = (&m, ping_sent.take()) {
+                            metrics.ws_ping_rtt.observe(sent.elapsed().as_secs_f64());
+                        }
+                        let kind = message_type(&m);
+                        let started = Instant::now();
+                        let flow = process_message(m, who, id, &game).await;
+                        metrics.ws_messages.with_label_values(&[kind]).inc();
+                        metrics.ws_message_duration.with_label_values(&[kind])
+                            .observe(started.elapsed().as_secs_f64());
+                        let replies = match flow {
+                            ControlFlow::Continue(replies) => replies,
+                            ControlFlow::Break(()) => {
+                                stop_processing(clients, client, &game).await;
+                                break;
+                            }
+                        };
+                        let mut sent = true;
+                        for reply in &replies {
+                            sent = sent && send_message(&mut sender, reply).await.is_ok();
+                        }
+                        if !sent {
+                            stop_processing(clients, client, &game).await;
+                            break;
+                        }
                     }
                 }
             }
+            if client.lock().await.is_kicked() {
+                let _ = sender.send(Message::Close(None)).await;
+                stop_processing(clients, client, &game).await;
+                break;
+            }
         }
     });
 }
 
-async fn get_message(receiver: &mut SplitStream<WebSocket>) -> Result<Option<Message>, Error> {
-    match timeout(Duration::from_millis(5000), receiver.next()).await {
+async fn get_message(receiver: &mut SplitStream<WebSocket>, idle_timeout: Duration) -> Result<Option<Message>, Error> {
+    match timeout(idle_timeout, receiver.next()).await {
         Ok(t) => match t {
-            Some(m) => {
-                return match m {
-                    Ok(msg) => Ok(Some(msg)),
-                    Err(e) => Err(e)
-                };
+            Some(m) => match m {
+                Ok(msg) => Ok(Some(msg)),
+                Err(e) => Err(e)
             }
             None => Ok(None)
         }
@@ -76,10 +137,32 @@ async fn get_message(receiver: &mut SplitStream<WebSocket>) -> Result<Option<Mes
     }
 }
 
-fn process_message(msg: Message, who: SocketAddr) -> ControlFlow<(), ()> {
+fn message_type(msg: &Message) -> &'static str {
+    match msg {
+        Message::Text(_) => "text",
+        Message::Binary(_) => "binary",
+        Message::Close(_) => "close",
+        Message::Pong(_) => "pong",
+        Message::Ping(_) => "ping",
+    }
+}
+
+async fn send_message(sender: &mut SplitSink<WebSocket, Message>, message: &ServerMessage) -> Result<(), Error> {
+    let text = serde_json::to_string(message).expect("server messages serialize");
+    sender.send(Message::Text(text.into())).await
+}
+
+async fn process_message(msg: Message, who: SocketAddr, id: Uuid, game: &Mutex<GameSession>)
+    -> ControlFlow<(), Vec<ServerMessage>> {
     match msg {
         Message::Text(t) => {
-            println!(">>> {} sent str: {:?}", who, t);
+            return ControlFlow::Continue(match serde_json::from_str::<ClientMessage>(t.as_str()) {
+                Ok(ClientMessage::Move { dx, dy }) => game.lock().await.move_player(id, Vec2::new(dx, dy)),
+                Err(err) => {
+                    tracing::debug!("{} sent an invalid message: {}", who, err);
+                    vec![ServerMessage::Error { message: err.to_string() }]
+                }
+            });
         }
         Message::Binary(d) => {
             println!(">>> {} sent {} bytes: {:?}", who, d.len(), d);
@@ -106,10 +189,12 @@ fn process_message(msg: Message, who: SocketAddr) -> ControlFlow<(), ()> {
             println!(">>> {} sent ping with {:?}", who, v);
         }
     }
-    ControlFlow::Continue(())
+    ControlFlow::Continue(Vec::new())
 }
 
-async fn stop_processing(clients: Arc<ClientsState>, client: ClientHandle) {
-    tracing::info!("Close client with id {}", client.lock().await.id());
-    clients.del_client(client.lock().await.id().to_string().as_str()).await;
+async fn stop_processing(clients: Arc<ClientsState>, client: ClientHandle, game: &Mutex<GameSession>) {
+    let id = client.lock().await.id();
+    tracing::info!("Close client with id {}", id);
+    game.lock().await.leave(id);
+    clients.del_client(id.to_string().as_str()).await;
 }